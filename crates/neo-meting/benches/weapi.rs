@@ -0,0 +1,99 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use neo_meting::netease::{Netease, WeapiEncoder};
+use serde_json::Value;
+use tokio::{runtime::Runtime, sync::Semaphore};
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+/// 统计分配次数和字节数，跑 bench 前先打印一次单次调用的分配情况
+struct CountingAlloc;
+
+static ALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+static ALLOC_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn report_alloc<O>(name: &str, f: impl FnOnce() -> O) -> O {
+    let count = ALLOC_COUNT.load(Ordering::Relaxed);
+    let bytes = ALLOC_BYTES.load(Ordering::Relaxed);
+    let output = f();
+    eprintln!(
+        "{name}: {} allocs, {} bytes",
+        ALLOC_COUNT.load(Ordering::Relaxed) - count,
+        ALLOC_BYTES.load(Ordering::Relaxed) - bytes
+    );
+    output
+}
+
+const SONG_REQ: &str = r#"{"c":"[{\"id\":1901371647,\"v\":0}]"}"#;
+
+fn encode(c: &mut Criterion) {
+    report_alloc("weapi encode", || {
+        WeapiEncoder::try_from_str(SONG_REQ).unwrap()
+    });
+
+    let mut group = c.benchmark_group("weapi");
+    group.throughput(Throughput::Bytes(SONG_REQ.len() as u64));
+    group.bench_function("try_from_str", |b| {
+        b.iter(|| WeapiEncoder::try_from_str(black_box(SONG_REQ)).unwrap())
+    });
+    group.finish();
+}
+
+fn exec(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let server = rt.block_on(async {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"code":200}"#))
+            .mount(&server)
+            .await;
+        server
+    });
+    let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+
+    report_alloc("exec", || {
+        rt.block_on(async {
+            let data = WeapiEncoder::try_from_str(SONG_REQ).unwrap();
+            netease
+                .exec::<HashMap<String, Value>>("/weapi/bench", data)
+                .await
+                .unwrap()
+        })
+    });
+
+    c.bench_function("exec", |b| {
+        b.to_async(&rt).iter(|| async {
+            let data = WeapiEncoder::try_from_str(SONG_REQ).unwrap();
+            netease
+                .exec::<HashMap<String, Value>>("/weapi/bench", data)
+                .await
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, encode, exec);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! 直接把 `Netease` 当库用：`cargo run --example fetch_song -- <song_id>`
+
+use std::sync::Arc;
+
+use neo_meting::{netease::Netease, MetingApi};
+use tokio::sync::Semaphore;
+
+#[tokio::main]
+async fn main() {
+    let id = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "1901371647".to_string());
+    // 同时最多 4 个上游请求
+    let netease = Netease::new(Arc::new(Semaphore::new(4)));
+    // pic / lrc / url 三个闭包把 id 变成子链接，这里直接返回 id
+    let song = netease
+        .song(
+            &id,
+            |pid| pid.to_string(),
+            |lid| lid.to_string(),
+            |uid| uid.to_string(),
+        )
+        .await;
+    match song {
+        Ok(song) => println!("{}", serde_json::to_string_pretty(&song).unwrap()),
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
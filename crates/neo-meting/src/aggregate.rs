@@ -0,0 +1,536 @@
+//! 同时请求所有 provider 的汇总接口
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use neo_meting::{
+    current_region, normalize_keyword, Error, MetingSearchOptions, MetingSong, MetingStats, REGION,
+};
+use salvo::{async_trait, http::StatusError, Depot, FlowCtrl, Handler, Request, Response};
+use tokio::{sync::Semaphore, task::JoinSet, time::error::Elapsed};
+use tracing::warn;
+
+use crate::{
+    base_url,
+    render::{render_grouped_songs, Json},
+    Provider,
+};
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStatus {
+    Ok,
+    Timeout,
+    Error,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SearchBody {
+    /// 每个成功的 provider 的结果
+    pub results: BTreeMap<&'static str, Vec<MetingSong>>,
+    /// 每个 provider 的结果状态，超时或出错的不会出现在 `results` 里
+    pub sources: BTreeMap<&'static str, SourceStatus>,
+}
+
+/// 某个路由前缀下汇总接口可以用的 provider，见 `--provider-sets`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProviderFilter {
+    /// 只用这些
+    Allow(Vec<String>),
+    /// 除了这些都用
+    Deny(Vec<String>),
+}
+
+impl ProviderFilter {
+    pub fn names(&self) -> &[String] {
+        match self {
+            ProviderFilter::Allow(names) | ProviderFilter::Deny(names) => names,
+        }
+    }
+
+    pub fn select(&self, providers: &[Arc<dyn Provider>]) -> Vec<Arc<dyn Provider>> {
+        let allow = matches!(self, ProviderFilter::Allow(_));
+        providers
+            .iter()
+            .filter(|provider| {
+                let listed = self.names().iter().any(|name| name == provider.name());
+                listed == allow
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// `{ "<prefix>": { "allow": [provider] } | { "deny": [provider] } }`，前缀只能是一段路径
+pub fn parse_provider_sets(json: &str) -> Result<BTreeMap<String, ProviderFilter>, String> {
+    let sets = serde_json::from_str::<BTreeMap<String, ProviderFilter>>(json)
+        .map_err(|e| e.to_string())?;
+    sets.into_iter()
+        .map(|(prefix, filter)| {
+            let prefix = prefix.trim_matches('/');
+            if prefix.is_empty() || prefix.contains('/') {
+                return Err(format!("{prefix:?}: prefix is a single path segment"));
+            }
+            Ok((prefix.to_string(), filter))
+        })
+        .collect()
+}
+
+/// 所有汇总接口共用的上游并发额度，和各 provider 自己的额度分开计算
+#[derive(Debug)]
+pub struct FanOutLimit {
+    permits: Semaphore,
+    waiting: AtomicUsize,
+}
+
+/// 离开时把排队数减回去，排队中途超时被取消也一样
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl FanOutLimit {
+    /// `permits` 为 0 时不限
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Semaphore::new(match permits {
+                0 => Semaphore::MAX_PERMITS,
+                permits => permits,
+            }),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> MetingStats {
+        MetingStats {
+            available_permits: self.permits.available_permits(),
+            queue_depth: self.waiting.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+
+    /// 拿到额度后执行 `task`
+    async fn run<T>(&self, task: impl Future<Output = T>) -> T {
+        let permit = {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            let _waiting = Waiting(&self.waiting);
+            self.permits.acquire().await
+        };
+        let output = task.await;
+        drop(permit);
+        output
+    }
+}
+
+/// 每个 provider 一个任务执行 `task`，各自计时（包括排队等 `limit` 的时间），结果按完成顺序返回
+async fn fan_out<T, Task, Fut>(
+    providers: &[Arc<dyn Provider>],
+    limit: &Arc<FanOutLimit>,
+    timeout: Duration,
+    task: Task,
+) -> Vec<(&'static str, Result<Result<T, Error>, Elapsed>)>
+where
+    T: Send + 'static,
+    Task: Fn(Arc<dyn Provider>) -> Fut,
+    Fut: Future<Output = Result<T, Error>> + Send + 'static,
+{
+    let mut tasks = JoinSet::new();
+    // task local 不会跟着 spawn 走，需要在新任务里重新进入
+    let region = current_region();
+    for provider in providers.iter().cloned() {
+        let (name, region, limit) = (provider.name(), region.clone(), limit.clone());
+        let task = task(provider);
+        tasks.spawn(async move {
+            let run = tokio::time::timeout(timeout, limit.run(task));
+            let result = match region {
+                Some(region) => REGION.scope(region, run).await,
+                None => run.await,
+            };
+            (name, result)
+        });
+    }
+    let mut results = Vec::new();
+    while let Some(task) = tasks.join_next().await {
+        if let Ok(result) = task {
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// `GET /search/{keyword}`，每个 provider 单独计时，慢的不拖累快的
+pub struct Search {
+    providers: Vec<Arc<dyn Provider>>,
+    limit: Arc<FanOutLimit>,
+    timeout: Duration,
+}
+
+impl Search {
+    pub fn new(
+        providers: Vec<Arc<dyn Provider>>,
+        limit: Arc<FanOutLimit>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            providers,
+            limit,
+            timeout,
+        }
+    }
+
+    pub async fn search(
+        &self,
+        keyword: &str,
+        options: MetingSearchOptions,
+        base: &str,
+    ) -> SearchBody {
+        let results = fan_out(&self.providers, &self.limit, self.timeout, |provider| {
+            let (keyword, base) = (keyword.to_string(), base.to_string());
+            async move {
+                let songs = provider.search(&keyword, options, &base).await?;
+                let source = provider.source();
+                Ok(songs
+                    .into_iter()
+                    .map(|song| song.with_source(source))
+                    .collect())
+            }
+        })
+        .await;
+        let mut body = SearchBody {
+            results: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        };
+        for (name, result) in results {
+            let status = match result {
+                Ok(Ok(songs)) => {
+                    body.results.insert(name, songs);
+                    SourceStatus::Ok
+                }
+                Ok(Err(e)) => {
+                    warn!("aggregate search {name}: {e:?}");
+                    SourceStatus::Error
+                }
+                Err(_) => SourceStatus::Timeout,
+            };
+            body.sources.insert(name, status);
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl Handler for Search {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let Some(keyword) = req
+            .param::<&str>("keyword")
+            .map(normalize_keyword)
+            .filter(|keyword| !keyword.is_empty())
+        else {
+            res.render(StatusError::bad_request());
+            return;
+        };
+        let Some(base) = base_url(req) else {
+            res.render(StatusError::bad_request());
+            return;
+        };
+        let options = MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let body = self.search(&keyword, options, &base).await;
+        render_grouped_songs(req, res, body, "results");
+    }
+}
+
+/// `GET /url/multi?name=&artist=`，每个 provider 搜索同一首歌并解析播放地址
+///
+/// 返回 `{ provider: url }`，超时、出错或没搜到的 provider 不出现
+pub struct MultiUrl {
+    providers: Vec<Arc<dyn Provider>>,
+    limit: Arc<FanOutLimit>,
+    timeout: Duration,
+}
+
+impl MultiUrl {
+    pub fn new(
+        providers: Vec<Arc<dyn Provider>>,
+        limit: Arc<FanOutLimit>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            providers,
+            limit,
+            timeout,
+        }
+    }
+
+    pub async fn resolve(&self, name: &str, artist: &str) -> BTreeMap<&'static str, String> {
+        fan_out(&self.providers, &self.limit, self.timeout, |provider| {
+            let (name, artist) = (name.to_string(), artist.to_string());
+            async move { provider.find_url(&name, &artist).await }
+        })
+        .await
+        .into_iter()
+        .filter_map(|(name, result)| match result {
+            Ok(Ok(url)) => Some((name, url)),
+            Ok(Err(e)) => {
+                warn!("multi url {name}: {e:?}");
+                None
+            }
+            Err(_) => None,
+        })
+        .collect()
+    }
+}
+
+#[async_trait]
+impl Handler for MultiUrl {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let Some(name) = req
+            .query::<String>("name")
+            .filter(|name| !name.trim().is_empty())
+        else {
+            res.render(StatusError::bad_request().brief("missing ?name="));
+            return;
+        };
+        let artist = req.query::<String>("artist").unwrap_or_default();
+        res.render(Json(self.resolve(&name, &artist).await));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use neo_meting::{Error, MetingApi, MetingSearchOptions, MetingSong};
+
+    use super::{parse_provider_sets, FanOutLimit, MultiUrl, ProviderFilter, Search, SourceStatus};
+
+    #[derive(Clone)]
+    struct Fast;
+    impl MetingApi for Fast {
+        fn name() -> &'static str {
+            "fast"
+        }
+        async fn search(
+            &self,
+            _keyword: &str,
+            _option: MetingSearchOptions,
+            _pic: impl Fn(&str) -> String + Send,
+            _lrc: impl Fn(&str) -> String + Send,
+            _url: impl Fn(&str) -> String + Send,
+        ) -> Result<Vec<MetingSong>, Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[derive(Clone)]
+    struct Slow;
+    impl MetingApi for Slow {
+        fn name() -> &'static str {
+            "slow"
+        }
+        async fn search(
+            &self,
+            _keyword: &str,
+            _option: MetingSearchOptions,
+            _pic: impl Fn(&str) -> String + Send,
+            _lrc: impl Fn(&str) -> String + Send,
+            _url: impl Fn(&str) -> String + Send,
+        ) -> Result<Vec<MetingSong>, Error> {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(Vec::new())
+        }
+    }
+
+    /// 搜索结果里有同名不同艺术家的歌，只有 id 2 能匹配上
+    #[derive(Clone)]
+    struct Found;
+    impl MetingApi for Found {
+        fn name() -> &'static str {
+            "found"
+        }
+        async fn search(
+            &self,
+            _keyword: &str,
+            _option: MetingSearchOptions,
+            pic: impl Fn(&str) -> String + Send,
+            lrc: impl Fn(&str) -> String + Send,
+            url: impl Fn(&str) -> String + Send,
+        ) -> Result<Vec<MetingSong>, Error> {
+            [("1", "other"), ("2", "x")]
+                .into_iter()
+                .map(|(id, artist)| {
+                    serde_json::from_value(serde_json::json!({
+                        "name": "A", "artist": artist, "url": url(id), "pic": pic(id), "lrc": lrc(id)
+                    }))
+                    .map_err(|e| Error::Remote(e.to_string()))
+                })
+                .collect()
+        }
+        async fn url(&self, id: &str) -> Result<String, Error> {
+            Ok(format!("https://found.example/{id}.mp3"))
+        }
+    }
+
+    /// 同一个 provider 的另一个实例，换了来源标签
+    #[derive(Clone)]
+    struct Mirror;
+    impl MetingApi for Mirror {
+        fn name() -> &'static str {
+            "mirror"
+        }
+        fn source(&self) -> &str {
+            "mirror1:found"
+        }
+        async fn search(
+            &self,
+            keyword: &str,
+            option: MetingSearchOptions,
+            pic: impl Fn(&str) -> String + Send,
+            lrc: impl Fn(&str) -> String + Send,
+            url: impl Fn(&str) -> String + Send,
+        ) -> Result<Vec<MetingSong>, Error> {
+            Found.search(keyword, option, pic, lrc, url).await
+        }
+    }
+
+    #[derive(Clone)]
+    struct Broken;
+    impl MetingApi for Broken {
+        fn name() -> &'static str {
+            "broken"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_results() {
+        let search = Search::new(
+            vec![Arc::new(Fast), Arc::new(Slow), Arc::new(Broken)],
+            Arc::new(FanOutLimit::new(0)),
+            Duration::from_millis(50),
+        );
+        let options = MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let body = search.search("a", options, "http://localhost").await;
+        assert!(matches!(body.sources["fast"], SourceStatus::Ok));
+        assert!(matches!(body.sources["slow"], SourceStatus::Timeout));
+        assert!(matches!(body.sources["broken"], SourceStatus::Error));
+        assert_eq!(body.results.keys().copied().collect::<Vec<_>>(), ["fast"]);
+    }
+
+    #[tokio::test]
+    async fn test_source_label() {
+        let search = Search::new(
+            vec![Arc::new(Found), Arc::new(Mirror)],
+            Arc::new(FanOutLimit::new(0)),
+            Duration::from_secs(1),
+        );
+        let options = MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let body = search.search("a", options, "http://localhost").await;
+        assert_eq!(body.results["found"][0].source(), Some("found"));
+        assert_eq!(body.results["mirror"][0].source(), Some("mirror1:found"));
+    }
+
+    #[tokio::test]
+    async fn test_multi_url() {
+        let multi = MultiUrl::new(
+            vec![Arc::new(Found), Arc::new(Fast), Arc::new(Slow)],
+            Arc::new(FanOutLimit::new(0)),
+            Duration::from_millis(50),
+        );
+        let urls = multi.resolve("a", "X").await;
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls["found"], "https://found.example/2.mp3");
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_limit() {
+        let limit = Arc::new(FanOutLimit::new(1));
+        let search = Search::new(
+            vec![Arc::new(Slow), Arc::new(Slow)],
+            limit.clone(),
+            Duration::from_millis(50),
+        );
+        let options = MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let searching = tokio::spawn(async move { search.search("a", options, "").await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // 只有一个额度，一个在跑，另一个在排队
+        let stats = limit.stats();
+        assert_eq!((stats.available_permits, stats.queue_depth), (0, 1));
+        let body = searching.await.unwrap();
+        assert!(matches!(body.sources["slow"], SourceStatus::Timeout));
+        // 排队中超时的也不再计入
+        let stats = limit.stats();
+        assert_eq!((stats.available_permits, stats.queue_depth), (1, 0));
+    }
+
+    #[test]
+    fn test_provider_sets() {
+        let sets = parse_provider_sets(
+            r#"{ "/public/": { "allow": ["fast"] }, "admin": { "deny": [] } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sets["public"],
+            ProviderFilter::Allow(vec!["fast".to_string()])
+        );
+        let providers: Vec<Arc<dyn crate::Provider>> = vec![Arc::new(Fast), Arc::new(Found)];
+        let names = |filter: &ProviderFilter| {
+            filter
+                .select(&providers)
+                .iter()
+                .map(|provider| provider.name())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&sets["public"]), ["fast"]);
+        assert_eq!(names(&sets["admin"]), ["fast", "found"]);
+        assert_eq!(
+            names(&ProviderFilter::Deny(vec!["fast".to_string()])),
+            ["found"]
+        );
+        for json in [
+            r#"{ "a/b": { "allow": [] } }"#,
+            r#"{ "": { "allow": [] } }"#,
+            r#"{ "a": { "only": [] } }"#,
+            r#"{ "a": { "allow": [], "deny": [] } }"#,
+        ] {
+            assert!(parse_provider_sets(json).is_err(), "{json}");
+        }
+    }
+}
@@ -0,0 +1,605 @@
+//! 启动参数，命令行优先，其次是同名的 `NEO_METING_*` 环境变量
+
+use std::{
+    collections::BTreeMap, fmt::Display, net::SocketAddr, num::NonZeroUsize, path::PathBuf,
+    sync::OnceLock, time::Duration,
+};
+
+use clap::{builder::BoolishValueParser, error::ErrorKind, CommandFactory, Parser};
+use neo_meting::netease::{
+    ClientOptions, DEFAULT_CONNECT_TIMEOUT, DEFAULT_PLAYLIST_TASKS, DEFAULT_QUEUE_TIMEOUT,
+};
+use reqwest::{header::HeaderValue, tls, Certificate};
+use salvo::http::StatusCode;
+
+use crate::{
+    aggregate::{parse_provider_sets, ProviderFilter},
+    render::Compat,
+};
+
+/// 时长参数统一按秒解析，0 表示关闭 / 不限
+fn secs(secs: u64) -> Option<Duration> {
+    (secs != 0).then(|| Duration::from_secs(secs))
+}
+
+/// `1.2` / `1.3`
+fn tls_version(version: &str) -> Result<tls::Version, String> {
+    match version {
+        "1.2" => Ok(tls::Version::TLS_1_2),
+        "1.3" => Ok(tls::Version::TLS_1_3),
+        _ => Err("expected 1.2 or 1.3".to_string()),
+    }
+}
+
+/// 重定向状态码：301 / 302 / 303 / 307 / 308
+fn redirect_status(status: &str) -> Result<StatusCode, String> {
+    match status.parse::<StatusCode>() {
+        Ok(status) if status.is_redirection() && !matches!(status.as_u16(), 300 | 304..=306) => {
+            Ok(status)
+        }
+        _ => Err("expected 301, 302, 303, 307 or 308".to_string()),
+    }
+}
+
+/// `<alias>=<provider>`
+fn alias(value: &str) -> Result<(String, String), String> {
+    let (alias, provider) = value.split_once('=').ok_or("expected <alias>=<provider>")?;
+    let alias = alias.trim_matches('/');
+    if alias.is_empty() || alias.contains('/') || provider.is_empty() {
+        return Err("expected <alias>=<provider>, alias is a single path segment".to_string());
+    }
+    Ok((alias.to_string(), provider.to_string()))
+}
+
+/// lrc 响应的 `Content-Type`
+fn lrc_content_type(value: &str) -> Result<String, String> {
+    match value {
+        "text/plain; charset=utf-8" | "application/octet-stream" => Ok(value.to_string()),
+        _ => Err("expected `text/plain; charset=utf-8` or `application/octet-stream`".to_string()),
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "meting api server")]
+pub struct Args {
+    /// 不启动服务，对每个 provider 请求一首公开歌曲的 song / pic / lrc / search，有失败时以 1 退出
+    #[arg(long)]
+    pub selftest: bool,
+
+    /// 监听地址，逗号分隔可以同时监听多个，如 `127.0.0.1:5811,[::1]:5811`
+    ///
+    /// Linux 下 `[::]` 默认同时接受 IPv4（v4-mapped），单独写它就是双栈
+    #[arg(
+        long,
+        env = "NEO_METING_BIND",
+        value_delimiter = ',',
+        default_value = "127.0.0.1:5811"
+    )]
+    pub bind: Vec<SocketAddr>,
+
+    /// tokio 工作线程数，默认为 CPU 核数
+    #[arg(long, env = "NEO_METING_WORKERS")]
+    pub workers: Option<NonZeroUsize>,
+
+    /// 挂在反向代理子路径下时的路径前缀，如 `/music`
+    #[arg(long, env = "NEO_METING_BASE_PATH", default_value = "")]
+    pub base_path: String,
+
+    /// provider 的别名前缀，逗号分隔，如 `default=netease` 让 `/default/...` 等同于 `/netease/...`
+    #[arg(long, env = "NEO_METING_ALIAS", value_delimiter = ',', value_parser = alias)]
+    pub alias: Vec<(String, String)>,
+
+    /// JSON 文件，给汇总接口（/search、/url/multi、/providers）再挂几个只用部分 provider 的前缀，
+    /// 如 `{ "public": { "allow": ["netease"] }, "admin": { "deny": [] } }`
+    #[arg(long, env = "NEO_METING_PROVIDER_SETS")]
+    pub provider_sets: Option<PathBuf>,
+
+    /// 单个上游请求失败后的重试次数
+    #[arg(long, env = "NEO_METING_RETRY", default_value_t = 0)]
+    pub retry: u8,
+
+    /// 一次客户端请求里所有上游请求加起来的重试次数上限
+    #[arg(long, env = "NEO_METING_RETRY_BUDGET", default_value_t = 16)]
+    pub retry_budget: usize,
+
+    /// 歌单分批请求重试时的并发许可：release 每次尝试单独获取、退避期间归还，
+    /// hold 一直占到成功或放弃，重试不用重新排队，但上游出问题时会挤占其他请求
+    #[arg(long, env = "NEO_METING_RETRY_PERMIT", default_value = "release")]
+    pub retry_permit: neo_meting::netease::RetryPermit,
+
+    /// netease 的上游并发数
+    #[arg(long, env = "NEO_METING_NETEASE_CONCURRENCY", default_value_t = 8)]
+    pub netease_concurrency: usize,
+
+    /// 一个歌单同时 spawn 的分批请求任务数（每批最多 512 首），0 为不限
+    #[arg(long, env = "NEO_METING_PLAYLIST_TASKS", default_value_t = DEFAULT_PLAYLIST_TASKS)]
+    pub playlist_tasks: usize,
+
+    /// 排队等待上游并发额度的超时（秒），0 为一直排队
+    #[arg(long, env = "NEO_METING_QUEUE_TIMEOUT", default_value_t = DEFAULT_QUEUE_TIMEOUT.as_secs())]
+    pub queue_timeout: u64,
+
+    /// 没有 `?region=` 时的默认地区，如 cn / hk / jp，不设置时不指定
+    #[arg(long, env = "NEO_METING_REGION")]
+    pub region: Option<String>,
+
+    /// 没有地区时的 X-Real-IP：random 每次随机国内 ip，off 不带，或者一个固定 ip
+    #[cfg(feature = "random-ip")]
+    #[arg(long, env = "NEO_METING_RANDOM_IP", default_value = "random")]
+    pub random_ip: neo_meting::netease::RandomIp,
+
+    /// 整个客户端请求的处理时限（秒），超过时返回 504 并取消还没完成的上游请求，0 为不限
+    ///
+    /// `--request-timeout` 只限制单个上游请求，一个接口可能先后或同时发出多个，还要加上排队和重试
+    #[arg(long, env = "NEO_METING_HANDLER_TIMEOUT", default_value_t = 0)]
+    pub handler_timeout: u64,
+
+    /// 处理时间超过该值（毫秒）的请求打 warn 日志，0 为关闭
+    #[arg(long, env = "NEO_METING_SLOW_MS", default_value_t = 5000)]
+    pub slow_ms: u64,
+
+    /// url / pic / lrc 的缓存时间（秒），0 为关闭
+    #[arg(long, env = "NEO_METING_CACHE_TTL", default_value_t = 300)]
+    pub cache_ttl: u64,
+
+    /// 缓存的最大条目数，超出后淘汰最久没用过的，0 为关闭
+    #[arg(long, env = "NEO_METING_CACHE_SIZE", default_value_t = 10000)]
+    pub cache_size: usize,
+
+    /// 缓存里较长的值（歌词、json）gzip 后存，省内存但读写多花 CPU
+    #[arg(long, env = "NEO_METING_CACHE_GZIP", value_parser = BoolishValueParser::new())]
+    pub cache_gzip: bool,
+
+    /// `/cache/warm/pics` 同时解析的封面数
+    #[arg(long, env = "NEO_METING_WARM_PIC_TASKS", default_value_t = 8)]
+    pub warm_pic_tasks: usize,
+
+    /// netease 登录后的 Cookie（如 `MUSIC_U=...`），每日推荐等接口需要
+    #[arg(long, env = "NEO_METING_COOKIE", hide_env_values = true)]
+    pub cookie: Option<String>,
+
+    /// 每天用 `--cookie` 的账号在 netease 签到一次，默认关闭
+    #[arg(long, env = "NEO_METING_DAILY_SIGNIN", value_parser = BoolishValueParser::new())]
+    pub daily_signin: bool,
+
+    /// 调试 / 管理接口的 Bearer token，不设置时这些接口一律拒绝
+    #[arg(long, env = "NEO_METING_AUTH", hide_env_values = true)]
+    pub auth: Option<String>,
+
+    /// lrc 响应的 Content-Type，部分客户端需要 `application/octet-stream`
+    #[arg(
+        long,
+        env = "NEO_METING_LRC_CONTENT_TYPE",
+        default_value = "text/plain; charset=utf-8",
+        value_parser = lrc_content_type
+    )]
+    pub lrc_content_type: String,
+
+    /// json 响应缩进输出，方便在浏览器里看；`?pretty=` 可覆盖
+    #[arg(long, env = "NEO_METING_PRETTY", value_parser = BoolishValueParser::new())]
+    pub pretty: bool,
+
+    /// 歌曲对象的字段名：meting 原样，aplayer 为老版 APlayer 的 title / author；`?compat=` 可覆盖
+    #[arg(long, env = "NEO_METING_COMPAT", value_enum, default_value_t = Compat::Meting)]
+    pub compat: Compat,
+
+    /// 歌单有批次在重试后仍然失败时整个请求报错，而不是跳过缺失的曲目；`?strict=` 可覆盖
+    #[arg(long, env = "NEO_METING_STRICT_PLAYLIST", value_parser = BoolishValueParser::new())]
+    pub strict_playlist: bool,
+
+    /// url 由服务端转发（支持 Range），不再重定向
+    #[arg(long, env = "NEO_METING_PROXY_AUDIO", value_parser = BoolishValueParser::new())]
+    pub proxy_audio: bool,
+
+    /// 转发时额外允许的上游 host，逗号分隔，子域名也算；provider 自己的 CDN 不用写
+    #[arg(long, env = "NEO_METING_PROXY_HOSTS", value_delimiter = ',')]
+    pub proxy_hosts: Vec<String>,
+
+    /// url 重定向的状态码，播放地址会过期，默认 302
+    #[arg(long, env = "NEO_METING_URL_REDIRECT", default_value = "302", value_parser = redirect_status)]
+    pub url_redirect: StatusCode,
+
+    /// pic 重定向的状态码，图片链接稳定时可以用 301 让客户端 / CDN 长期缓存
+    #[arg(long, env = "NEO_METING_PIC_REDIRECT", default_value = "302", value_parser = redirect_status)]
+    pub pic_redirect: StatusCode,
+
+    /// 封面不存在时 pic 接口 302 到这张图，而不是 404，不设置时仍然 404
+    #[arg(long, env = "NEO_METING_PIC_PLACEHOLDER")]
+    pub pic_placeholder: Option<String>,
+
+    /// 汇总接口（/search、/url/multi）里每个 provider 的超时（秒）
+    #[arg(long, env = "NEO_METING_AGGREGATE_TIMEOUT", default_value_t = 5)]
+    pub aggregate_timeout: u64,
+
+    /// 所有汇总接口加起来同时发出的上游请求数（不含各 provider 自己的并发限制），0 为不限
+    #[arg(long, env = "NEO_METING_AGGREGATE_CONCURRENCY", default_value_t = 16)]
+    pub aggregate_concurrency: usize,
+
+    /// 定期注册 netease 匿名 token 的间隔（秒），0 为关闭
+    #[arg(long, env = "NEO_METING_TOKEN_REFRESH", default_value_t = 24 * 60 * 60)]
+    pub token_refresh: u64,
+
+    /// 汇总接口（/search）里 netease 歌曲的 source 标签，如 `mirror1:netease`，默认为 netease
+    #[arg(long, env = "NEO_METING_NETEASE_SOURCE")]
+    pub netease_source: Option<String>,
+
+    /// 把 pic 的 p*.music.126.net 换成镜像 host，路径不变
+    #[arg(long, env = "NEO_METING_PIC_CDN")]
+    pub pic_cdn: Option<String>,
+
+    /// 每个上游 host 保留的空闲连接数，默认不限
+    #[arg(long, env = "NEO_METING_POOL_MAX_IDLE")]
+    pub pool_max_idle: Option<usize>,
+
+    /// 空闲连接回收时间（秒），0 为不回收
+    #[arg(long, env = "NEO_METING_POOL_IDLE_TIMEOUT", default_value_t = 90)]
+    pub pool_idle_timeout: u64,
+
+    /// 连接上游（DNS + TCP + TLS）的超时（秒），0 为不限
+    #[arg(long, env = "NEO_METING_CONNECT_TIMEOUT", default_value_t = DEFAULT_CONNECT_TIMEOUT.as_secs())]
+    pub connect_timeout: u64,
+
+    /// 单个上游请求的总超时（秒），0 为不限；整个客户端请求的时限见 `--handler-timeout`
+    #[arg(long, env = "NEO_METING_REQUEST_TIMEOUT", default_value_t = 0)]
+    pub request_timeout: u64,
+
+    /// 上游连接允许的最低 TLS 版本：1.2 / 1.3，默认由 TLS 后端决定
+    #[arg(long, env = "NEO_METING_MIN_TLS_VERSION", value_parser = tls_version)]
+    pub min_tls_version: Option<tls::Version>,
+
+    /// PEM 格式的 CA 证书文件，设置后上游只信任其中的证书，不再使用系统根证书
+    #[arg(long, env = "NEO_METING_CA_FILE")]
+    pub ca_file: Option<PathBuf>,
+
+    /// 跳过 ALPN 直接用 http2
+    #[arg(long, env = "NEO_METING_HTTP2_PRIOR_KNOWLEDGE", value_parser = BoolishValueParser::new())]
+    pub http2_prior_knowledge: bool,
+}
+
+impl Args {
+    /// 统一成 `/a/b` 的形式，空字符串表示挂在根路径
+    pub fn base_path(&self) -> String {
+        self.base_path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| format!("/{segment}"))
+            .collect()
+    }
+
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl)
+    }
+
+    pub fn queue_timeout(&self) -> Option<Duration> {
+        secs(self.queue_timeout)
+    }
+
+    pub fn handler_timeout(&self) -> Option<Duration> {
+        secs(self.handler_timeout)
+    }
+
+    pub fn aggregate_timeout(&self) -> Duration {
+        Duration::from_secs(self.aggregate_timeout)
+    }
+
+    pub fn token_refresh(&self) -> Option<Duration> {
+        secs(self.token_refresh)
+    }
+
+    /// `--provider-sets` 的内容，没设置时为空；读不到或格式不对时返回错误
+    pub fn provider_sets(&self) -> Result<BTreeMap<String, ProviderFilter>, String> {
+        let Some(path) = &self.provider_sets else {
+            return Ok(BTreeMap::new());
+        };
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| parse_provider_sets(&json))
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// 启动前检查各参数本身和参数之间的组合，返回所有问题而不是只报第一个
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+        check(
+            self.netease_concurrency > 0,
+            "--netease-concurrency: must be at least 1, otherwise every request waits forever",
+        );
+        check(
+            self.warm_pic_tasks > 0,
+            "--warm-pic-tasks: must be at least 1",
+        );
+        check(
+            self.aggregate_timeout > 0,
+            "--aggregate-timeout: must be at least 1, otherwise every provider times out",
+        );
+        check(
+            self.auth
+                .as_deref()
+                .is_none_or(|auth| !auth.trim().is_empty()),
+            "--auth: empty token, unset it to disable the protected endpoints",
+        );
+        check(
+            self.cookie.as_deref().is_none_or(|cookie| {
+                !cookie.trim().is_empty() && HeaderValue::from_str(cookie).is_ok()
+            }),
+            "--cookie: empty or not a valid header value",
+        );
+        check(
+            !self.daily_signin || self.cookie.is_some(),
+            "--daily-signin: needs a login --cookie",
+        );
+        check(
+            self.retry == 0 || self.retry_budget > 0,
+            "--retry: has no effect with --retry-budget 0",
+        );
+        check(
+            self.cache_ttl > 0 && self.cache_size > 0 || !self.cache_gzip,
+            "--cache-gzip: has no effect while the cache is disabled (--cache-ttl 0 or --cache-size 0)",
+        );
+        check(
+            self.proxy_hosts.is_empty() || self.proxy_audio,
+            "--proxy-hosts: has no effect without --proxy-audio",
+        );
+        check(
+            self.proxy_hosts
+                .iter()
+                .all(|host| !host.is_empty() && !host.contains(['/', ':', '*'])),
+            "--proxy-hosts: expected bare host names such as cdn.example.com",
+        );
+        check(
+            self.pic_cdn.as_deref().is_none_or(|cdn| {
+                let cdn = match cdn.contains("://") {
+                    true => cdn.to_string(),
+                    false => format!("https://{cdn}"),
+                };
+                reqwest::Url::parse(&cdn).is_ok_and(|url| url.has_host())
+            }),
+            "--pic-cdn: expected a host or an http(s) url",
+        );
+        check(
+            self.pic_placeholder.as_deref().is_none_or(|url| {
+                url.is_empty()
+                    || url.starts_with('/')
+                    || reqwest::Url::parse(url)
+                        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+            }),
+            "--pic-placeholder: expected an http(s) url or an absolute path",
+        );
+        check(
+            self.handler_timeout == 0
+                || self.request_timeout == 0
+                || self.handler_timeout >= self.request_timeout,
+            "--handler-timeout: shorter than --request-timeout, upstream requests are cut off before their own timeout",
+        );
+        let mut binds = self.bind.clone();
+        binds.sort();
+        check(
+            binds.windows(2).all(|pair| pair[0] != pair[1]),
+            "--bind: the same address is given twice",
+        );
+        // Linux 下 `[::]` 也接受 IPv4，同端口再监听 IPv4 地址会 EADDRINUSE
+        check(
+            !self.bind.iter().any(|v6| {
+                v6.is_ipv6()
+                    && v6.ip().is_unspecified()
+                    && self
+                        .bind
+                        .iter()
+                        .any(|v4| v4.is_ipv4() && v4.port() == v6.port())
+            }),
+            "--bind: `[::]` also accepts IPv4 on Linux, drop the IPv4 address on the same port",
+        );
+        let mut aliases = self
+            .alias
+            .iter()
+            .map(|(alias, _)| alias)
+            .collect::<Vec<_>>();
+        aliases.sort();
+        check(
+            aliases.windows(2).all(|pair| pair[0] != pair[1]),
+            "--alias: the same alias is given twice",
+        );
+        for result in [
+            self.client_options().map(drop),
+            self.provider_sets().map(drop),
+        ] {
+            if let Err(e) = result {
+                problems.push(e);
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems),
+        }
+    }
+
+    /// `--ca-file` 读不到或没有证书时返回错误
+    pub fn client_options(&self) -> Result<ClientOptions, String> {
+        let mut options = ClientOptions {
+            pool_idle_timeout: secs(self.pool_idle_timeout),
+            http2_prior_knowledge: self.http2_prior_knowledge,
+            connect_timeout: secs(self.connect_timeout),
+            request_timeout: secs(self.request_timeout),
+            min_tls_version: self.min_tls_version,
+            ..ClientOptions::default()
+        };
+        if let Some(max_idle) = self.pool_max_idle {
+            options.pool_max_idle_per_host = max_idle;
+        }
+        if let Some(path) = &self.ca_file {
+            let pem = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+            options.root_certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            if options.root_certs.is_empty() {
+                return Err(format!("{}: no certificate found", path.display()));
+            }
+        }
+        Ok(options)
+    }
+}
+
+static ARGS: OnceLock<Args> = OnceLock::new();
+
+/// `main` 里先 [`init`]；测试里没有命令行，只读环境变量和默认值
+pub fn args() -> &'static Args {
+    ARGS.get_or_init(|| Args::parse_from([env!("CARGO_PKG_NAME")]))
+}
+
+pub fn init() -> &'static Args {
+    ARGS.get_or_init(Args::parse)
+}
+
+/// 参数校验不过时按 clap 的格式报错退出
+pub fn exit(msg: impl Display) -> ! {
+    Args::command()
+        .error(ErrorKind::ValueValidation, msg)
+        .exit()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::Args;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_base_path() {
+        for (input, expected) in [("", ""), ("/", ""), ("music", "/music"), ("/a//b/", "/a/b")] {
+            let args = Args::parse_from(["neo-meting", "--base-path", input]);
+            assert_eq!(args.base_path(), expected);
+        }
+    }
+
+    #[test]
+    fn test_tls_options() {
+        let args = Args::parse_from(["neo-meting", "--min-tls-version", "1.3"]);
+        let options = args.client_options().unwrap();
+        assert_eq!(
+            options.min_tls_version,
+            Some(reqwest::tls::Version::TLS_1_3)
+        );
+        assert!(options.root_certs.is_empty());
+        assert!(Args::try_parse_from(["neo-meting", "--min-tls-version", "1.1"]).is_err());
+
+        let args = Args::parse_from(["neo-meting", "--ca-file", "/nonexistent/ca.pem"]);
+        assert!(args.client_options().is_err());
+        let args = Args::parse_from(["neo-meting", "--ca-file", file!()]);
+        assert!(args.client_options().is_err());
+    }
+
+    #[test]
+    fn test_redirect_status() {
+        let args = Args::parse_from(["neo-meting", "--pic-redirect", "301"]);
+        assert_eq!(args.url_redirect.as_u16(), 302);
+        assert_eq!(args.pic_redirect.as_u16(), 301);
+        for status in ["200", "300", "304", "abc"] {
+            assert!(Args::try_parse_from(["neo-meting", "--url-redirect", status]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_alias() {
+        let args = Args::parse_from(["neo-meting", "--alias", "default=netease,/163/=netease"]);
+        assert_eq!(
+            args.alias,
+            [
+                ("default".to_string(), "netease".to_string()),
+                ("163".to_string(), "netease".to_string()),
+            ]
+        );
+        for alias in ["netease", "=netease", "a/b=netease", "a="] {
+            assert!(Args::try_parse_from(["neo-meting", "--alias", alias]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Args::parse_from(["neo-meting"]).validate(), Ok(()));
+        let args = Args::parse_from([
+            "neo-meting",
+            "--netease-concurrency",
+            "0",
+            "--auth",
+            " ",
+            "--proxy-hosts",
+            "cdn.example.com",
+            "--alias",
+            "a=netease,a=netease",
+        ]);
+        let problems = args.validate().unwrap_err();
+        let options = problems
+            .iter()
+            .map(|problem| problem.split(':').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            options,
+            [
+                "--netease-concurrency",
+                "--auth",
+                "--proxy-hosts",
+                "--alias"
+            ]
+        );
+
+        let args = Args::parse_from(["neo-meting", "--cache-gzip", "--cache-ttl", "0"]);
+        assert_eq!(args.validate().unwrap_err().len(), 1);
+        let args = Args::parse_from(["neo-meting", "--ca-file", "/nonexistent/ca.pem"]);
+        assert_eq!(args.validate().unwrap_err().len(), 1);
+
+        let args = Args::parse_from([
+            "neo-meting",
+            "--bind",
+            "127.0.0.1:5811,127.0.0.1:5811",
+            "--pic-placeholder",
+            "cover.png",
+            "--handler-timeout",
+            "5",
+            "--request-timeout",
+            "10",
+        ]);
+        let problems = args.validate().unwrap_err();
+        let options = problems
+            .iter()
+            .map(|problem| problem.split(':').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            options,
+            ["--pic-placeholder", "--handler-timeout", "--bind"]
+        );
+        let args = Args::parse_from(["neo-meting", "--bind", "0.0.0.0:5811,[::]:5811"]);
+        assert_eq!(args.validate().unwrap_err().len(), 1);
+        let args = Args::parse_from([
+            "neo-meting",
+            "--bind",
+            "0.0.0.0:5811,[::]:5812",
+            "--pic-placeholder",
+            "/static/cover.png",
+        ]);
+        assert_eq!(args.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_workers() {
+        assert_eq!(Args::parse_from(["neo-meting"]).workers, None);
+        let args = Args::parse_from(["neo-meting", "--workers", "2"]);
+        assert_eq!(args.workers.map(|workers| workers.get()), Some(2));
+        assert!(Args::try_parse_from(["neo-meting", "--workers", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_bind_list() {
+        let args = Args::parse_from(["neo-meting", "--bind", "127.0.0.1:5811,[::1]:5811"]);
+        assert_eq!(args.bind.len(), 2);
+        assert!(args.bind[1].is_ipv6());
+    }
+}
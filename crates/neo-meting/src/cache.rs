@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    future::Future,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+tokio::task_local! {
+    /// [`track`] 作用域内的命中 / 未命中次数
+    static LOOKUPS: Arc<[AtomicU64; 2]>;
+}
+
+/// 一次请求里缓存的使用情况，见 [`track`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Lookups {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Display for Lookups {
+    /// 全部命中为 `hit`，全部未命中为 `miss`，都有为 `partial`，没查过缓存为 `none`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match (self.hits, self.misses) {
+            (0, 0) => "none",
+            (_, 0) => "hit",
+            (0, _) => "miss",
+            _ => "partial",
+        })
+    }
+}
+
+/// 执行 `task` 并统计期间的缓存查询，spawn 出去的任务不计入
+pub async fn track<T>(task: impl Future<Output = T>) -> (T, Lookups) {
+    let counters = Arc::new([AtomicU64::new(0), AtomicU64::new(0)]);
+    let output = LOOKUPS.scope(counters.clone(), task).await;
+    let lookups = Lookups {
+        hits: counters[0].load(Ordering::Relaxed),
+        misses: counters[1].load(Ordering::Relaxed),
+    };
+    (output, lookups)
+}
+
+/// 按逻辑 key（`<provider>/<method>/<id>`）缓存的结果，过期时间统一为 `ttl`
+///
+/// key 不含加密后的请求体，所以命中时加密和网络请求都会跳过；
+/// 条目数超过 `capacity` 时淘汰最久没用过的
+#[derive(Debug)]
+pub struct Cache {
+    ttl: Duration,
+    capacity: usize,
+    /// 见 [`Cache::with_gzip`]
+    gzip: bool,
+    inner: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    cleared: AtomicU64,
+}
+
+/// 短于这个长度的值（url、pic 之类）压缩了也省不了多少，原样存
+const GZIP_MIN_LEN: usize = 256;
+
+/// 缓存里的值，开启压缩时长的值存 gzip，读的时候再解压
+#[derive(Debug, Clone)]
+enum Stored {
+    Plain(String),
+    Gzip { data: Vec<u8>, len: usize },
+}
+
+impl Stored {
+    /// 压缩后没变小时仍然原样存
+    fn new(value: String, gzip: bool) -> Self {
+        if !gzip || value.len() < GZIP_MIN_LEN {
+            return Stored::Plain(value);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        match encoder
+            .write_all(value.as_bytes())
+            .and_then(|_| encoder.finish())
+        {
+            Ok(data) if data.len() < value.len() => Stored::Gzip {
+                data,
+                len: value.len(),
+            },
+            _ => Stored::Plain(value),
+        }
+    }
+
+    /// 解压前的长度
+    fn len(&self) -> usize {
+        match self {
+            Stored::Plain(value) => value.len(),
+            Stored::Gzip { len, .. } => *len,
+        }
+    }
+
+    /// 实际占用的长度
+    fn stored_len(&self) -> usize {
+        match self {
+            Stored::Plain(value) => value.len(),
+            Stored::Gzip { data, .. } => data.len(),
+        }
+    }
+
+    fn into_string(self) -> Option<String> {
+        match self {
+            Stored::Plain(value) => Some(value),
+            Stored::Gzip { data, len } => {
+                let mut value = String::with_capacity(len);
+                GzDecoder::new(data.as_slice())
+                    .read_to_string(&mut value)
+                    .ok()
+                    .map(|_| value)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    /// key -> (过期时间, 值, 最近一次使用的序号)
+    entries: HashMap<String, (Instant, Stored, u64)>,
+    /// 使用序号 -> key，最小的就是最久没用过的
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    /// 所有值解压前 / 实际占用的字节数
+    bytes: usize,
+    stored_bytes: usize,
+}
+
+impl Lru {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: &str) -> Option<Stored> {
+        let tick = self.next_tick();
+        let (expire, value, used) = self.entries.get_mut(key)?;
+        if *expire <= Instant::now() {
+            self.remove(key);
+            return None;
+        }
+        let value = value.clone();
+        self.order.remove(used);
+        *used = tick;
+        self.order.insert(tick, key.to_string());
+        Some(value)
+    }
+
+    fn insert(&mut self, key: String, expire: Instant, value: Stored, capacity: usize) {
+        self.remove(&key);
+        while self.entries.len() >= capacity {
+            let Some(oldest) = self.order.first_key_value().map(|(_, key)| key.clone()) else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        let tick = self.next_tick();
+        self.bytes += value.len();
+        self.stored_bytes += value.stored_len();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (expire, value, tick));
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, value, used)) = self.entries.remove(key) {
+            self.bytes -= value.len();
+            self.stored_bytes -= value.stored_len();
+            self.order.remove(&used);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct CacheStats {
+    pub size: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// `hits / (hits + misses)`，还没有请求时为 0
+    pub hit_ratio: f64,
+    /// 启动以来 [`Cache::clear`] 删掉的条目数
+    pub cleared: u64,
+    /// 所有值解压后的字节数
+    pub bytes: usize,
+    /// 实际占用的字节数，没开 [`Cache::with_gzip`] 时等于 `bytes`
+    pub stored_bytes: usize,
+}
+
+/// [`Cache::clear`] 的条件，都为 `None` 时清空
+#[derive(Debug, Default)]
+pub struct ClearFilter {
+    pub provider: Option<String>,
+    pub method: Option<String>,
+    /// id 的前缀，url 的 id 可能带 `@<region>`
+    pub id: Option<String>,
+}
+
+impl ClearFilter {
+    fn matches(&self, key: &str) -> bool {
+        let mut parts = key.splitn(3, '/');
+        let (provider, method, id) = (parts.next(), parts.next(), parts.next());
+        self.provider.as_deref().is_none_or(|p| provider == Some(p))
+            && self.method.as_deref().is_none_or(|m| method == Some(m))
+            && self
+                .id
+                .as_deref()
+                .is_none_or(|prefix| id.is_some_and(|id| id.starts_with(prefix)))
+    }
+}
+
+impl Cache {
+    /// `ttl` 或 `capacity` 为 0 时不缓存
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            gzip: false,
+            inner: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            cleared: AtomicU64::new(0),
+        }
+    }
+
+    /// 较长的值（歌词、json）gzip 后存，读的时候解压，用 CPU 换内存
+    pub fn with_gzip(self, gzip: bool) -> Self {
+        Self { gzip, ..self }
+    }
+
+    fn disabled(&self) -> bool {
+        self.ttl.is_zero() || self.capacity == 0
+    }
+
+    pub fn key(provider: &str, method: &str, id: &str) -> String {
+        format!("{provider}/{method}/{id}")
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        if self.disabled() {
+            return None;
+        }
+        // 单独一条语句取出，锁在这里就释放了，解压放在锁外面
+        let stored = self.inner.lock().unwrap().get(key);
+        let value = stored.and_then(Stored::into_string);
+        let (counter, index) = match value {
+            Some(_) => (&self.hits, 0),
+            None => (&self.misses, 1),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let _ = LOOKUPS.try_with(|lookups| lookups[index].fetch_add(1, Ordering::Relaxed));
+        value
+    }
+
+    pub fn insert(&self, key: String, value: String) {
+        if self.disabled() {
+            return;
+        }
+        let expire = Instant::now() + self.ttl;
+        let value = Stored::new(value, self.gzip);
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(key, expire, value, self.capacity);
+    }
+
+    /// 命中直接返回，否则执行 `task` 并缓存成功的结果
+    pub async fn get_or_try_insert<E, Task>(&self, key: String, task: Task) -> Result<String, E>
+    where
+        Task: Future<Output = Result<String, E>>,
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let value = task.await?;
+        self.insert(key, value.clone());
+        Ok(value)
+    }
+
+    /// 删掉符合 `filter` 的条目，返回删掉的条数
+    pub fn clear(&self, filter: &ClearFilter) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let keys = inner
+            .entries
+            .keys()
+            .filter(|key| filter.matches(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.iter().for_each(|key| inner.remove(key));
+        self.cleared.fetch_add(keys.len() as u64, Ordering::Relaxed);
+        keys.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            size: inner.entries.len(),
+            capacity: self.capacity,
+            hits,
+            misses,
+            hit_ratio: match hits + misses {
+                0 => 0.0,
+                total => hits as f64 / total as f64,
+            },
+            cleared: self.cleared.load(Ordering::Relaxed),
+            bytes: inner.bytes,
+            stored_bytes: inner.stored_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{track, Cache, ClearFilter, Lookups};
+
+    #[tokio::test]
+    async fn test_hit_by_logical_key() {
+        let cache = Cache::new(Duration::from_secs(60), 16);
+        let key = || Cache::key("netease", "url", "1");
+        let first = cache
+            .get_or_try_insert(key(), async { Ok::<_, ()>("a".to_string()) })
+            .await;
+        let second = cache
+            .get_or_try_insert(key(), async { Ok::<_, ()>("b".to_string()) })
+            .await;
+        assert_eq!(first, Ok("a".to_string()));
+        assert_eq!(second, Ok("a".to_string()));
+        let stats = cache.stats();
+        assert_eq!((stats.size, stats.hits, stats.misses), (1, 1, 1));
+        assert_eq!(stats.hit_ratio, 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_disables() {
+        let cache = Cache::new(Duration::ZERO, 16);
+        cache.insert(Cache::key("netease", "pic", "1"), "a".to_string());
+        assert_eq!(cache.get(&Cache::key("netease", "pic", "1")), None);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = Cache::new(Duration::from_secs(60), 2);
+        cache.insert("a".to_string(), "1".to_string());
+        cache.insert("b".to_string(), "2".to_string());
+        // 用过 a 之后，最久没用的是 b
+        assert_eq!(cache.get("a").as_deref(), Some("1"));
+        cache.insert("c".to_string(), "3".to_string());
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a").as_deref(), Some("1"));
+        assert_eq!(cache.get("c").as_deref(), Some("3"));
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_clear() {
+        let cache = Cache::new(Duration::from_secs(60), 16);
+        for (provider, method, id) in [
+            ("netease", "url", "1@hk"),
+            ("netease", "url", "12"),
+            ("netease", "pic", "1"),
+            ("other", "url", "1"),
+        ] {
+            cache.insert(Cache::key(provider, method, id), id.to_string());
+        }
+        let filter = ClearFilter {
+            provider: Some("netease".to_string()),
+            method: Some("url".to_string()),
+            id: Some("1@".to_string()),
+        };
+        assert_eq!(cache.clear(&filter), 1);
+        let filter = ClearFilter {
+            method: Some("url".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(cache.clear(&filter), 2);
+        assert_eq!(
+            cache.get(&Cache::key("netease", "pic", "1")).as_deref(),
+            Some("1")
+        );
+        assert_eq!(cache.clear(&ClearFilter::default()), 1);
+        assert_eq!((cache.stats().size, cache.stats().cleared), (0, 4));
+    }
+
+    #[test]
+    fn test_gzip() {
+        let lrc = "[00:01.00]la la la\n".repeat(100);
+        let cache = Cache::new(Duration::from_secs(60), 16).with_gzip(true);
+        cache.insert("lrc".to_string(), lrc.clone());
+        cache.insert("url".to_string(), "https://a.mp3".to_string());
+        assert_eq!(cache.get("lrc").as_deref(), Some(lrc.as_str()));
+        assert_eq!(cache.get("url").as_deref(), Some("https://a.mp3"));
+        let stats = cache.stats();
+        assert_eq!(stats.bytes, lrc.len() + "https://a.mp3".len());
+        assert!(stats.stored_bytes < stats.bytes / 4);
+
+        cache.clear(&ClearFilter::default());
+        assert_eq!((cache.stats().bytes, cache.stats().stored_bytes), (0, 0));
+
+        let cache = Cache::new(Duration::from_secs(60), 16);
+        cache.insert("lrc".to_string(), lrc.clone());
+        assert_eq!(cache.stats().stored_bytes, lrc.len());
+    }
+
+    #[tokio::test]
+    async fn test_track_lookups() {
+        let cache = Cache::new(Duration::from_secs(60), 16);
+        cache.insert("a".to_string(), "1".to_string());
+        let (_, lookups) = track(async {
+            cache.get("a");
+            cache.get("b");
+        })
+        .await;
+        assert_eq!(lookups, Lookups { hits: 1, misses: 1 });
+        assert_eq!(lookups.to_string(), "partial");
+        let (_, lookups) = track(async { cache.get("a") }).await;
+        assert_eq!(lookups.to_string(), "hit");
+        assert_eq!(track(async {}).await.1.to_string(), "none");
+    }
+}
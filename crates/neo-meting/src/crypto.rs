@@ -0,0 +1,100 @@
+//! weapi 用到的 AES-128-CBC、无填充 RSA，以及注册匿名账号用到的 MD5
+//!
+//! 默认走 `openssl`，开启 `rustcrypto` feature 后换成纯 rust 实现，方便 Windows / musl 交叉编译
+
+#[cfg(not(any(feature = "openssl", feature = "rustcrypto")))]
+compile_error!("either feature \"openssl\" or \"rustcrypto\" must be enabled");
+
+#[cfg(all(feature = "openssl", not(feature = "rustcrypto")))]
+pub use self::openssl_backend::*;
+#[cfg(feature = "rustcrypto")]
+pub use self::rustcrypto_backend::*;
+
+#[cfg(all(feature = "openssl", not(feature = "rustcrypto")))]
+mod openssl_backend {
+    use openssl::{
+        error::ErrorStack,
+        hash::{hash, MessageDigest},
+        rsa::{Padding, Rsa},
+        symm::{encrypt, Cipher},
+    };
+
+    pub type CryptoError = ErrorStack;
+    pub type PublicKey = Rsa<openssl::pkey::Public>;
+
+    pub fn aes_128_cbc(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        encrypt(Cipher::aes_128_cbc(), key, Some(iv), data)
+    }
+
+    pub fn import_public_key(pem: &[u8]) -> Result<PublicKey, CryptoError> {
+        Rsa::public_key_from_pem(pem)
+    }
+
+    pub fn md5(data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        hash(MessageDigest::md5(), data).map(|digest| digest.to_vec())
+    }
+
+    pub fn rsa_no_padding(key: &PublicKey, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let mut output = vec![0; key.size() as usize];
+        key.public_encrypt(data, &mut output, Padding::NONE)?;
+        Ok(output)
+    }
+}
+
+#[cfg(feature = "rustcrypto")]
+mod rustcrypto_backend {
+    use aes::{
+        cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit},
+        Aes128,
+    };
+    use base64::{prelude::BASE64_STANDARD, Engine};
+    use md5::{Digest, Md5};
+    use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, BigUint, RsaPublicKey};
+
+    #[derive(Debug)]
+    pub struct CryptoError(pub String);
+
+    pub type PublicKey = RsaPublicKey;
+
+    pub fn aes_128_cbc(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        cbc::Encryptor::<Aes128>::new_from_slices(key, iv)
+            .map_err(|e| CryptoError(e.to_string()))
+            .map(|cipher| cipher.encrypt_padded_vec_mut::<Pkcs7>(data))
+    }
+
+    /// `cert/netease.pub` 不是按 64 列折行的，严格的 PEM 解析会失败，这里自己拼 base64 再按 DER 解析
+    pub fn import_public_key(pem: &[u8]) -> Result<PublicKey, CryptoError> {
+        let body = std::str::from_utf8(pem)
+            .map_err(|e| CryptoError(e.to_string()))?
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>();
+        BASE64_STANDARD
+            .decode(body)
+            .map_err(|e| CryptoError(e.to_string()))
+            .and_then(|der| {
+                RsaPublicKey::from_public_key_der(&der).map_err(|e| CryptoError(e.to_string()))
+            })
+    }
+
+    pub fn md5(data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        Ok(Md5::digest(data).to_vec())
+    }
+
+    /// 教科书 RSA：`data ^ e mod n`，输出左侧补零到模长
+    pub fn rsa_no_padding(key: &PublicKey, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let size = key.size();
+        if data.len() != size {
+            return Err(CryptoError(format!(
+                "data length {} != key size {size}",
+                data.len()
+            )));
+        }
+        let encrypted = BigUint::from_bytes_be(data)
+            .modpow(key.e(), key.n())
+            .to_bytes_be();
+        let mut output = vec![0; size - encrypted.len()];
+        output.extend(encrypted);
+        Ok(output)
+    }
+}
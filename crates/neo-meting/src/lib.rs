@@ -1,5 +1,15 @@
-use std::future::Future;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+pub mod cache;
+pub mod crypto;
 pub mod netease;
 
 pub trait Then {
@@ -35,28 +45,144 @@ pub enum Error {
         target: &'static str,
     },
     None,
+    /// 上游要求登录或 VIP 才能访问
+    NeedLogin,
+    /// 连上了上游，但没有在请求超时内返回
+    Timeout(String),
+    /// 上游返回了，但响应体不是 json 或结构不对
+    Decode(String),
+    /// 严格模式下歌单有 `missing` 首因为批次失败没拿到，见 [`STRICT`]
+    Incomplete {
+        missing: usize,
+    },
     Unimplemented,
 }
 
+tokio::task_local! {
+    /// 当前请求的地区（两位国家 / 地区代码），由 `?region=` 设置
+    pub static REGION: String;
+}
+
+/// 当前请求的地区，不在 [`REGION`] 作用域内时为 `None`
+pub fn current_region() -> Option<String> {
+    REGION.try_with(Clone::clone).ok()
+}
+
+tokio::task_local! {
+    /// 调试用，覆盖当前请求发给上游的 `X-Real-IP`，由 `?ip=` 设置
+    pub static REAL_IP: std::net::IpAddr;
+}
+
+/// 不在 [`REAL_IP`] 作用域内时为 `None`，按地区或随机 ip
+pub fn current_real_ip() -> Option<std::net::IpAddr> {
+    REAL_IP.try_with(|ip| *ip).ok()
+}
+
+tokio::task_local! {
+    /// 为 `true` 时歌单缺了曲目就返回 [`Error::Incomplete`]，由 `?strict=` 设置
+    pub static STRICT: bool;
+}
+
+/// 不在 [`STRICT`] 作用域内时为宽松模式
+pub fn strict() -> bool {
+    STRICT.try_with(|strict| *strict).unwrap_or(false)
+}
+
+/// 第一次重试前等待时间的上限，之后每次翻倍
+pub const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+/// 单次重试等待时间的上限
+pub const RETRY_MAX_DELAY: Duration = Duration::from_secs(2);
+/// 上游限流时要求等待的时间（`Retry-After`）的上限，超过时只等这么久
+pub const RETRY_AFTER_MAX: Duration = Duration::from_secs(10);
+
+/// full jitter：`[0, min(RETRY_BASE_DELAY * 2^attempt, RETRY_MAX_DELAY)]` 内随机，避免一起打上游
+fn retry_delay(attempt: u8) -> Duration {
+    let max = RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(RETRY_MAX_DELAY);
+    max.mul_f64(rand::random_range(0.0..=1.0))
+}
+
+/// 一次客户端请求里所有子请求共享的重试额度
+///
+/// 单个子请求最多重试 `per_call` 次，所有子请求加起来最多重试 `total` 次，
+/// 额度用完后剩下的子请求失败即返回，避免上游出问题时重试把请求量放大几百倍
+#[derive(Debug)]
+pub struct RetryBudget {
+    per_call: u8,
+    remaining: AtomicUsize,
+}
+
+impl RetryBudget {
+    pub fn new(per_call: u8, total: usize) -> Self {
+        Self {
+            per_call,
+            remaining: AtomicUsize::new(total),
+        }
+    }
+
+    /// 第 `attempt` 次重试前调用，成功时扣掉一次额度
+    fn try_take(&self, attempt: u8) -> bool {
+        attempt < self.per_call
+            && self
+                .remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                    remaining.checked_sub(1)
+                })
+                .is_ok()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Relaxed)
+    }
+}
+
+/// 失败后在 `budget` 允许的范围内重试，每次重试前随机等待一段时间
+///
+/// 等待期间不持有任何并发许可（许可由 `task` 自己在每次尝试内获取和释放），不会饿死其他请求
 pub async fn retry<I, O, E, Task, GenTaskFunc, OnErrFunc>(
-    limit: u8,
+    budget: &RetryBudget,
+    input: I,
+    task: GenTaskFunc,
+    on_error: OnErrFunc,
+) -> Result<O, E>
+where
+    I: Clone,
+    Task: Future<Output = Result<O, E>>,
+    GenTaskFunc: Fn(I) -> Task,
+    OnErrFunc: Fn(E),
+{
+    retry_with(budget, input, task, on_error, |_| None).await
+}
+
+/// 同 [`retry`]，`wait` 从错误里取出上游要求的等待时间（如 `Retry-After`），
+/// 有时按它等（不超过 [`RETRY_AFTER_MAX`]），没有时照常随机退避
+pub async fn retry_with<I, O, E, Task, GenTaskFunc, OnErrFunc, WaitFunc>(
+    budget: &RetryBudget,
     input: I,
     task: GenTaskFunc,
     on_error: OnErrFunc,
+    wait: WaitFunc,
 ) -> Result<O, E>
 where
     I: Clone,
     Task: Future<Output = Result<O, E>>,
     GenTaskFunc: Fn(I) -> Task,
     OnErrFunc: Fn(E),
+    WaitFunc: Fn(&E) -> Option<Duration>,
 {
     let mut counter = 0;
     loop {
         let result = task(input.clone()).await;
         match result {
             Ok(o) => break Ok(o),
-            Err(e) if counter < limit => {
+            Err(e) if budget.try_take(counter) => {
+                let delay = match wait(&e) {
+                    Some(delay) => delay.min(RETRY_AFTER_MAX),
+                    None => retry_delay(counter),
+                };
                 on_error(e);
+                tokio::time::sleep(delay).await;
                 counter += 1
             }
             Err(e) => break Err(e),
@@ -64,6 +190,25 @@ where
     }
 }
 
+/// 规范化搜索关键词
+///
+/// - 客户端重复编码时（如 `%25E4%25B8%25AD`）路由只解一层，这里把残留的百分号编码再解一次，解出来不是 utf-8 就保留原样
+/// - NFC 规范化，macOS 等输入的分解形式（NFD）和上游索引的组合形式一致
+/// - 去掉首尾空白，中间连续空白合并成一个空格
+pub fn normalize_keyword(keyword: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    let decoded = percent_encoding::percent_decode_str(keyword)
+        .decode_utf8()
+        .unwrap_or(keyword.into());
+    decoded
+        .nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct MetingSearchOptions {
     pub limit: usize,
@@ -71,13 +216,377 @@ pub struct MetingSearchOptions {
     pub r#type: usize,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// 派生的 `Ord` 按字段顺序比较，即先按 name 再按 artist 排序
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
 pub struct MetingSong {
     name: String,
     artist: String,
     url: String,
     pic: String,
     lrc: String,
+    /// 收费类型：0 免费，1 VIP，4 需购买专辑，8 低音质免费；没有这个概念的 provider 为 0
+    #[serde(default)]
+    fee: u8,
+    /// 当前身份能否播放完整版本，provider 不知道时不输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    playable: Option<bool>,
+    /// 当前身份能播放的最高码率（bps），0 为不能播放，provider 不知道时不输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_br: Option<u64>,
+    /// 来源标签，见 [`MetingApi::source`]；只有汇总接口混合多个 provider 的结果时才带上
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+/// NFKC、小写、合并空白
+fn normalize_title(input: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    input
+        .nfkc()
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl MetingSong {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn pic(&self) -> &str {
+        &self.pic
+    }
+
+    pub fn lrc(&self) -> &str {
+        &self.lrc
+    }
+
+    pub fn fee(&self) -> u8 {
+        self.fee
+    }
+
+    pub fn playable(&self) -> Option<bool> {
+        self.playable
+    }
+
+    pub fn max_br(&self) -> Option<u64> {
+        self.max_br
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn with_source(self, source: &str) -> Self {
+        Self {
+            source: Some(source.to_string()),
+            ..self
+        }
+    }
+
+    /// 跨 provider 匹配同一首歌用的 `(name, artist)`：NFKC、小写、合并空白
+    ///
+    /// 各 provider 的 url / pic / lrc 都不同，所以不参与比较
+    pub fn dedup_key(&self) -> (String, String) {
+        (normalize_title(&self.name), normalize_title(&self.artist))
+    }
+
+    /// 歌名相同，且 `artist` 为空或是这首歌的艺术家之一（多个艺术家以 `/` 分隔）
+    pub fn matches(&self, name: &str, artist: &str) -> bool {
+        let (self_name, self_artist) = self.dedup_key();
+        let artist = normalize_title(artist);
+        self_name == normalize_title(name)
+            && (artist.is_empty()
+                || self_artist == artist
+                || self_artist.split('/').any(|one| one.trim() == artist))
+    }
+}
+
+/// provider 支持的方法，对应路由里的 `<method>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Url,
+    Pic,
+    Lrc,
+    Lyrics,
+    Comments,
+    Song,
+    Playlist,
+    Artist,
+    ArtistAlbums,
+    ArtistMvs,
+    Album,
+    Search,
+    Suggest,
+    Similar,
+    UserPlaylists,
+    Daily,
+    Liked,
+    NewReleases,
+    Recommend,
+    Toplist,
+    Check,
+    Raw,
+}
+
+impl Capability {
+    /// 和序列化结果一致
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Url => "url",
+            Capability::Pic => "pic",
+            Capability::Lrc => "lrc",
+            Capability::Lyrics => "lyrics",
+            Capability::Comments => "comments",
+            Capability::Song => "song",
+            Capability::Playlist => "playlist",
+            Capability::Artist => "artist",
+            Capability::ArtistAlbums => "artist_albums",
+            Capability::ArtistMvs => "artist_mvs",
+            Capability::Album => "album",
+            Capability::Search => "search",
+            Capability::Suggest => "suggest",
+            Capability::Similar => "similar",
+            Capability::UserPlaylists => "user_playlists",
+            Capability::Daily => "daily",
+            Capability::Liked => "liked",
+            Capability::NewReleases => "new_releases",
+            Capability::Recommend => "recommend",
+            Capability::Toplist => "toplist",
+            Capability::Check => "check",
+            Capability::Raw => "raw",
+        }
+    }
+
+    pub const ALL: &'static [Capability] = &[
+        Capability::Url,
+        Capability::Pic,
+        Capability::Lrc,
+        Capability::Lyrics,
+        Capability::Comments,
+        Capability::Song,
+        Capability::Playlist,
+        Capability::Artist,
+        Capability::ArtistAlbums,
+        Capability::ArtistMvs,
+        Capability::Album,
+        Capability::Search,
+        Capability::Suggest,
+        Capability::Similar,
+        Capability::UserPlaylists,
+        Capability::Daily,
+        Capability::Liked,
+        Capability::NewReleases,
+        Capability::Recommend,
+        Capability::Toplist,
+        Capability::Check,
+        Capability::Raw,
+    ];
+}
+
+/// 歌单摘要，不含曲目
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MetingPlaylist {
+    pub id: String,
+    pub name: String,
+    pub cover: String,
+    /// 创建者昵称
+    pub creator: String,
+    pub count: u64,
+}
+
+/// 推荐歌单，曲目用 [`MetingApi::playlist`] 取
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MetingRecommend {
+    pub id: String,
+    pub name: String,
+    pub cover: String,
+    pub play_count: u64,
+}
+
+/// 通用的音质等级，从低到高，各 provider 自己映射到上游参数
+pub const URL_LEVELS: [&str; 5] = ["standard", "higher", "exhigh", "lossless", "hires"];
+
+/// 新歌速递的地区：全部 / 华语 / 欧美 / 韩国 / 日本
+pub const NEW_RELEASE_AREAS: [&str; 5] = ["ALL", "ZH", "EA", "KR", "JP"];
+
+/// 播放地址和实际拿到的音质，上游没给的为 `None`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingUrl {
+    pub url: String,
+    /// 文件格式，如 `mp3` / `flac`
+    pub r#type: Option<String>,
+    pub br: Option<u64>,
+    /// 字节数
+    pub size: Option<u64>,
+}
+
+/// 歌单里的一首曲目，只有歌单接口本身带回的信息，没有 pic / lrc / url
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingTrack {
+    pub id: String,
+    /// 歌单响应里没有这首的详情时为 `None`
+    pub name: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// 一次请求拿到的各种歌词，上游没有的为 `None`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetingLyrics {
+    pub lrc: Option<String>,
+    /// 翻译
+    pub tlyric: Option<String>,
+    /// 罗马音
+    pub romalrc: Option<String>,
+    /// 逐字歌词
+    pub yrc: Option<String>,
+}
+
+/// 歌词的上传者
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingContributor {
+    pub id: String,
+    pub nickname: String,
+    /// 上传时间，毫秒时间戳
+    pub uptime: Option<u64>,
+}
+
+/// `lrc?meta=1`：原文和翻译歌词，以及上传者和版本号，上游没有的为 `None`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetingLrcMeta {
+    pub lyric: Option<String>,
+    pub tlyric: Option<String>,
+    pub contributor: Option<MetingContributor>,
+    /// 原文歌词的版本号，每次修改后递增
+    pub version: Option<u64>,
+}
+
+/// 歌曲的评论数和热门评论
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetingComments {
+    pub total: u64,
+    pub hot: Vec<MetingComment>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MetingComment {
+    /// 评论者昵称
+    pub user: String,
+    pub content: String,
+    pub likes: u64,
+}
+
+/// 专辑摘要，不含曲目
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MetingAlbum {
+    pub id: String,
+    pub name: String,
+    pub artist: String,
+    pub cover: String,
+    pub count: u64,
+    /// 发行时间，毫秒时间戳
+    pub publish_time: u64,
+}
+
+/// MV 摘要，不含播放地址
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingMv {
+    pub id: String,
+    pub name: String,
+    pub cover: String,
+    /// 时长，毫秒
+    pub duration: u64,
+}
+
+/// 搜索到的艺术家，`id` 可以继续请求 artist 相关接口
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingArtist {
+    pub id: String,
+    pub name: String,
+    /// 头像，上游没有时为空
+    pub pic: String,
+}
+
+/// 搜索建议的一项，`artist` 只有歌曲和专辑有
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingSuggestion {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub artist: String,
+}
+
+/// 输入联想用的搜索建议，只有名字，比完整搜索便宜
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingSuggest {
+    pub songs: Vec<MetingSuggestion>,
+    pub artists: Vec<MetingSuggestion>,
+    pub albums: Vec<MetingSuggestion>,
+}
+
+/// 一首歌能不能完整播放
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetingCheck {
+    pub playable: bool,
+    /// 不能播放的原因，如 `vip`、`trial`、`unavailable`、`need_login`，能播放时为 `None`
+    pub reason: Option<String>,
+    /// 能拿到的码率
+    pub br: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct MetingStats {
+    /// 剩余的并发许可
+    pub available_permits: usize,
+    /// 正在排队等许可的请求数
+    pub queue_depth: usize,
+    /// 按上游接口（路径常量）分开的延迟，不含排队
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub upstream: BTreeMap<&'static str, LatencyHistogram>,
+}
+
+/// [`LatencyHistogram`] 各个桶的上界（毫秒），超过最后一个的只计入 `count`
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// 延迟分布，桶是累计的：`le` 里每个桶为耗时不超过上界的次数，同 Prometheus
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct LatencyHistogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    #[serde(serialize_with = "le_buckets")]
+    pub le: [u64; LATENCY_BUCKETS_MS.len()],
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis().try_into().unwrap_or(u64::MAX);
+        self.count += 1;
+        self.sum_ms = self.sum_ms.saturating_add(ms);
+        LATENCY_BUCKETS_MS
+            .iter()
+            .zip(&mut self.le)
+            .filter(|(bound, _)| ms <= **bound)
+            .for_each(|(_, count)| *count += 1);
+    }
+}
+
+/// `{ "25": n, ..., "5000": n }`
+fn le_buckets<S: serde::Serializer>(
+    le: &[u64; LATENCY_BUCKETS_MS.len()],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    let mut map = serializer.serialize_map(Some(le.len()))?;
+    for (bound, count) in LATENCY_BUCKETS_MS.iter().zip(le) {
+        map.serialize_entry(&bound.to_string(), count)?;
+    }
+    map.end()
 }
 
 pub trait MetingApi
@@ -85,15 +594,75 @@ where
     Self: Sized + Clone + Sync + Send + 'static,
 {
     fn name() -> &'static str;
+    /// 实际实现了的方法，没覆盖的默认方法返回 [`Error::Unimplemented`]
+    fn capabilities() -> &'static [Capability] {
+        Capability::ALL
+    }
+    /// 路由里 `{id}` 的格式检查，不通过时直接 400，不请求上游；默认不检查
+    fn valid_id(_id: &str) -> bool {
+        true
+    }
+    /// 汇总接口里歌曲的来源标签，默认为 [`MetingApi::name`]；
+    /// 同一个 provider 用不同镜像 / Cookie 注册多个实例时用来区分
+    fn source(&self) -> &str {
+        Self::name()
+    }
+    /// 把客户端给的 id 或分享链接规范成路由里的 `{id}`，认不出时为 `None`；默认只接受 [`MetingApi::valid_id`]
+    fn parse_id(input: &str) -> Option<String> {
+        let input = input.trim();
+        (!input.is_empty() && Self::valid_id(input)).then(|| input.to_string())
+    }
+    /// `--selftest` 用的公开曲目 id 和能搜到它的关键词，没有时跳过自检
+    fn selftest_sample() -> Option<(&'static str, &'static str)> {
+        None
+    }
+    fn stats(&self) -> MetingStats {
+        MetingStats::default()
+    }
+    /// 服务端转发音频时允许请求的上游 host，子域名也算；默认为空，即一律不转发
+    fn proxy_hosts(&self) -> Vec<String> {
+        Vec::new()
+    }
     fn url(&self, _id: &str) -> impl Future<Output = Result<String, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    /// 按 [`URL_LEVELS`] 里的等级取播放地址，不支持等级时同 [`MetingApi::url`]
+    fn url_with_level(
+        &self,
+        id: &str,
+        _level: &str,
+    ) -> impl Future<Output = Result<MetingUrl, Error>> + Send {
+        let url = self.url(id);
+        async move {
+            Ok(MetingUrl {
+                url: url.await?,
+                r#type: None,
+                br: None,
+                size: None,
+            })
+        }
+    }
     fn pic(&self, _id: &str) -> impl Future<Output = Result<String, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    /// 把 [`MetingApi::pic`] 返回的链接换成边长 `size` 的缩略图，不支持时原样返回
+    fn resize_pic(&self, url: &str, _size: u32) -> String {
+        url.to_string()
+    }
     fn lrc(&self, _id: &str) -> impl Future<Output = Result<String, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    fn lyrics(&self, _id: &str) -> impl Future<Output = Result<MetingLyrics, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 给歌词编辑用的元数据，和 [`MetingApi::lrc`] 属于同一个能力
+    fn lrc_meta(&self, _id: &str) -> impl Future<Output = Result<MetingLrcMeta, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 评论总数和热门评论，不需要登录
+    fn comments(&self, _id: &str) -> impl Future<Output = Result<MetingComments, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
     fn song(
         &self,
         _id: &str,
@@ -103,6 +672,18 @@ where
     ) -> impl Future<Output = Result<MetingSong, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    /// 一次查多首，重复的 id 只查一次、只返回一次，按 id 第一次出现的顺序返回，查不到的跳过
+    ///
+    /// 每首歌和 `ids` 里原样的 id 成对返回，调用方据此对应回输入
+    fn songs(
+        &self,
+        _ids: &[&str],
+        _pic: impl Fn(&str) -> String + Sync + Send,
+        _lrc: impl Fn(&str) -> String + Sync + Send,
+        _url: impl Fn(&str) -> String + Sync + Send,
+    ) -> impl Future<Output = Result<Vec<(String, MetingSong)>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
 
     fn artist(
         &self,
@@ -116,13 +697,50 @@ where
     fn playlist(
         &self,
         _id: &str,
-        _retry: u8,
+        _retry: Arc<RetryBudget>,
         _pic: impl Fn(&str) -> String + Send + Sync,
         _lrc: impl Fn(&str) -> String + Send + Sync,
         _url: impl Fn(&str) -> String + Send + Sync,
     ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    /// 一次查多个歌单的信息，不含曲目；重复的 id 只查一次，按第一次出现的顺序返回，查不到的跳过
+    fn playlists(
+        &self,
+        _ids: &[&str],
+    ) -> impl Future<Output = Result<Vec<MetingPlaylist>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 同 [`MetingApi::playlist`]，额外带上歌单本身的信息
+    fn playlist_with_info(
+        &self,
+        _id: &str,
+        _retry: Arc<RetryBudget>,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<(MetingPlaylist, Vec<MetingSong>), Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 同 [`MetingApi::playlist_with_info`]，但曲目每解析完一批就交给 `sink`，不等全部完成
+    fn playlist_each(
+        &self,
+        _id: &str,
+        _retry: Arc<RetryBudget>,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+        _sink: impl FnMut(Vec<MetingSong>) + Send,
+    ) -> impl Future<Output = Result<MetingPlaylist, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 只返回歌单里的曲目 id（和顺带的名称），不再逐首请求详情
+    fn playlist_tracks(
+        &self,
+        _id: &str,
+    ) -> impl Future<Output = Result<Vec<MetingTrack>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
     fn search(
         &self,
         _keyword: &str,
@@ -133,4 +751,180 @@ where
     ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
         async { Err(Error::Unimplemented) }
     }
+    /// 按艺术家搜索，`option.r#type` 会被忽略
+    fn search_artists(
+        &self,
+        _keyword: &str,
+        _option: MetingSearchOptions,
+    ) -> impl Future<Output = Result<Vec<MetingArtist>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 关键词的搜索建议，用于输入联想
+    fn suggest(&self, _keyword: &str) -> impl Future<Output = Result<MetingSuggest, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    fn similar(
+        &self,
+        _id: &str,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 调试用，返回上游未解析的 json
+    fn raw(
+        &self,
+        _type: &str,
+        _id: &str,
+    ) -> impl Future<Output = Result<serde_json::Value, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    fn user_playlists(
+        &self,
+        _uid: &str,
+    ) -> impl Future<Output = Result<Vec<MetingPlaylist>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 最近发布的新歌，`area` 为 [`NEW_RELEASE_AREAS`] 之一，不需要登录
+    fn new_releases(
+        &self,
+        _area: &str,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 推荐歌单，不需要登录
+    fn recommended_playlists(
+        &self,
+    ) -> impl Future<Output = Result<Vec<MetingRecommend>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 官方排行榜，每个榜单就是一个歌单，曲目用 [`MetingApi::playlist`] 取
+    fn toplist(&self) -> impl Future<Output = Result<Vec<MetingPlaylist>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 每日推荐，需要登录
+    fn daily(
+        &self,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 登录用户喜欢的歌，需要登录
+    fn liked(
+        &self,
+        _retry: Arc<RetryBudget>,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    fn artist_albums(
+        &self,
+        _id: &str,
+        _limit: usize,
+        _offset: usize,
+    ) -> impl Future<Output = Result<Vec<MetingAlbum>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 艺术家的 MV，按 `limit` / `offset` 分页
+    fn artist_mvs(
+        &self,
+        _id: &str,
+        _limit: usize,
+        _offset: usize,
+    ) -> impl Future<Output = Result<Vec<MetingMv>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 专辑的曲目，默认直接用专辑接口带的信息；`enrich` 时按歌单的方式逐批请求歌曲详情，字段和歌单曲目一致
+    fn album(
+        &self,
+        _id: &str,
+        _enrich: bool,
+        _retry: Arc<RetryBudget>,
+        _pic: impl Fn(&str) -> String + Send + Sync,
+        _lrc: impl Fn(&str) -> String + Send + Sync,
+        _url: impl Fn(&str) -> String + Send + Sync,
+    ) -> impl Future<Output = Result<Vec<MetingSong>, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+    /// 不下载，只看一首歌能不能播放
+    fn check(&self, _id: &str) -> impl Future<Output = Result<MetingCheck, Error>> + Send {
+        async { Err(Error::Unimplemented) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{normalize_keyword, MetingSong};
+
+    fn song(name: &str, artist: &str, url: &str) -> MetingSong {
+        MetingSong {
+            name: name.to_string(),
+            artist: artist.to_string(),
+            url: url.to_string(),
+            pic: String::new(),
+            lrc: String::new(),
+            fee: 0,
+            playable: None,
+            max_br: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_capability_as_str() {
+        for capability in crate::Capability::ALL {
+            assert_eq!(
+                serde_json::to_value(capability).unwrap(),
+                capability.as_str()
+            );
+        }
+    }
+
+    #[test]
+    fn test_dedup_key_and_sort() {
+        let a = song("Hello  World", "ＡＢＣ", "netease");
+        let b = song("hello world", "abc", "other");
+        assert_eq!(a.dedup_key(), b.dedup_key());
+        assert_ne!(a, b);
+
+        let mut songs = [song("b", "a", ""), song("a", "b", ""), song("a", "a", "")];
+        songs.sort();
+        let order = songs
+            .iter()
+            .map(|song| (song.name.as_str(), song.artist.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(order, [("a", "a"), ("a", "b"), ("b", "a")]);
+    }
+
+    #[test]
+    fn test_matches() {
+        let a = song("Hello World", "ＡＢＣ/Def", "");
+        assert!(a.matches("hello  world", ""));
+        assert!(a.matches("hello world", "abc"));
+        assert!(a.matches("hello world", "def"));
+        assert!(!a.matches("hello world", "ab"));
+        assert!(!a.matches("hello", "abc"));
+    }
+
+    #[test]
+    fn test_normalize_keyword() {
+        assert_eq!(normalize_keyword("周杰伦"), "周杰伦");
+        assert_eq!(normalize_keyword("%E5%91%A8%E6%9D%B0%E4%BC%A6"), "周杰伦");
+        assert_eq!(
+            normalize_keyword("  AC/DC   back in  black "),
+            "AC/DC back in black"
+        );
+        assert_eq!(normalize_keyword("a+b"), "a+b");
+        assert_eq!(normalize_keyword("100%"), "100%");
+        // NFD 的 が -> NFC
+        assert_eq!(normalize_keyword("\u{304B}\u{3099}"), "\u{304C}");
+    }
 }
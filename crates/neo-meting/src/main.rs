@@ -1,20 +1,51 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
     ops::Deref,
     sync::{Arc, LazyLock},
+    time::{Duration, Instant},
 };
 
-use neo_meting::{netease::Netease, MetingApi, MetingSearchOptions};
+use neo_meting::{
+    cache::{self, Cache, CacheStats, ClearFilter},
+    current_real_ip, current_region,
+    netease::{Netease, WeapiEncoder},
+    normalize_keyword, Capability, MetingApi, MetingPlaylist, MetingSearchOptions, MetingSong,
+    MetingStats, MetingUrl, RetryBudget, NEW_RELEASE_AREAS, REAL_IP, REGION, STRICT, URL_LEVELS,
+};
 use salvo::{
     async_trait,
     conn::TcpListener,
     handler,
-    http::StatusError,
-    writing::{Json, Redirect},
+    http::{
+        header::{
+            ACCEPT, ACCEPT_RANGES, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+            HOST, RANGE,
+        },
+        StatusCode, StatusError,
+    },
+    websocket::{Message, WebSocket, WebSocketUpgrade},
+    writing::{Redirect, Text},
     Depot, FlowCtrl, Handler, Listener, Request, Response, Router, Server,
 };
-use tokio::sync::{RwLock, Semaphore};
+use tokio::{
+    sync::{mpsc, Notify, Semaphore},
+    task::JoinSet,
+};
 use tracing::warn;
 
+mod aggregate;
+mod args;
+mod render;
+mod selftest;
+mod warm;
+
+use args::args;
+use render::{
+    pretty_scope, render_keyed_songs, render_nested_songs, render_songs, song_writer, stream_songs,
+    Json,
+};
+
 pub trait Then {
     fn then<O>(self, f: impl FnOnce(Self) -> O) -> O
     where
@@ -38,7 +69,7 @@ fn prosess_meting_error(file: &str, line: u32, e: neo_meting::Error) -> StatusEr
     warn!("{file}:{line}: {e:?}");
     match e {
         E::Remote(_) => StatusError::bad_gateway(),
-        E::Server(_) => StatusError::internal_server_error(),
+        E::Server(_) => StatusError::service_unavailable(),
         E::Encode { engine: _, msg: _ } => StatusError::internal_server_error(),
         E::NoField(_) => StatusError::bad_gateway(),
         E::TypeMismatch {
@@ -46,6 +77,13 @@ fn prosess_meting_error(file: &str, line: u32, e: neo_meting::Error) -> StatusEr
             target: _,
         } => StatusError::bad_gateway(),
         E::None => StatusError::not_found(),
+        E::NeedLogin => {
+            StatusError::forbidden().brief("upstream requires login or VIP for this resource")
+        }
+        E::Timeout(_) => StatusError::gateway_timeout(),
+        E::Decode(_) => StatusError::bad_gateway().brief("unexpected upstream response"),
+        E::Incomplete { missing } => StatusError::bad_gateway()
+            .brief(format!("playlist incomplete: {missing} songs missing")),
         E::Unimplemented => StatusError::not_implemented(),
     }
 }
@@ -56,7 +94,244 @@ macro_rules! handle_error {
     };
 }
 
-static RETRY: LazyLock<Arc<RwLock<u8>>> = LazyLock::new(|| Arc::new(RwLock::new(0)));
+/// url / pic / lrc 的缓存，`--cache-ttl` 或 `--cache-size` 为 0 时关闭
+static CACHE: LazyLock<Cache> = LazyLock::new(|| {
+    Cache::new(args().cache_ttl(), args().cache_size).with_gzip(args().cache_gzip)
+});
+/// 调试 / 管理接口的 token，没设置时这些接口一律拒绝
+static AUTH: LazyLock<Option<&'static str>> =
+    LazyLock::new(|| args().auth.as_deref().filter(|token| !token.is_empty()));
+
+/// 检查 `Authorization: Bearer <NEO_METING_AUTH>`
+fn check_auth(req: &Request) -> Result<(), StatusError> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (*AUTH, token) {
+        (Some(auth), Some(token)) if auth == token => Ok(()),
+        (None, _) => Err(StatusError::forbidden().brief("NEO_METING_AUTH is not configured")),
+        _ => Err(StatusError::unauthorized()),
+    }
+}
+
+/// 要求 `Authorization: Bearer <NEO_METING_AUTH>`
+#[handler]
+async fn require_auth(req: &mut Request, res: &mut Response, ctrl: &mut FlowCtrl) {
+    if let Err(e) = check_auth(req) {
+        res.render(e);
+        ctrl.skip_rest();
+    }
+}
+
+/// 批量接口一次最多的 id 数
+const MAX_BATCH_IDS: usize = 50;
+
+/// 挂在反向代理子路径下时的前缀，子链接和路由都会带上
+static BASE_PATH: LazyLock<String> = LazyLock::new(|| args().base_path());
+
+/// `?region=xx` 时后续 handler 都跑在 [`REGION`] 作用域内
+#[handler]
+async fn region_scope(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    if let Some(region) = req
+        .query::<String>("region")
+        .filter(|region| !region.is_empty())
+    {
+        REGION.scope(region, ctrl.call_next(req, depot, res)).await;
+    }
+}
+
+/// 调试用：`?ip=` 时后续 handler 都跑在 [`REAL_IP`] 作用域内，上游请求的 `X-Real-IP` 固定为该地址
+///
+/// 需要 `Authorization`，不是合法 ip 时返回 400
+#[handler]
+async fn ip_scope(req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let Some(ip) = req.query::<String>("ip").filter(|ip| !ip.is_empty()) else {
+        return;
+    };
+    if let Err(e) = check_auth(req) {
+        res.render(e);
+        ctrl.skip_rest();
+        return;
+    }
+    match ip.trim().parse::<IpAddr>() {
+        Ok(ip) => {
+            REAL_IP.scope(ip, ctrl.call_next(req, depot, res)).await;
+        }
+        Err(_) => {
+            res.render(StatusError::bad_request().brief(format!("ip: invalid address {ip:?}")));
+            ctrl.skip_rest();
+        }
+    }
+}
+
+/// 超过 `--slow-ms` 的请求打 warn 日志，0 为关闭
+static SLOW_THRESHOLD: LazyLock<Option<Duration>> =
+    LazyLock::new(|| (args().slow_ms != 0).then(|| Duration::from_millis(args().slow_ms)));
+
+/// 记录慢请求的 provider、方法、id 和缓存命中情况，`threshold` 一般为 [`SLOW_THRESHOLD`]
+struct SlowLog {
+    provider: &'static str,
+    capability: Capability,
+    threshold: Option<Duration>,
+}
+
+#[async_trait]
+impl Handler for SlowLog {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let Some(threshold) = self.threshold else {
+            ctrl.call_next(req, depot, res).await;
+            return;
+        };
+        let start = Instant::now();
+        let (_, lookups) = cache::track(ctrl.call_next(req, depot, res)).await;
+        let elapsed = start.elapsed();
+        if elapsed >= threshold {
+            let (provider, method) = (self.provider, self.capability.as_str());
+            let id = req.param::<&str>("id").unwrap_or("-");
+            warn!(
+                "slow request {provider}/{method}/{id}: {}ms, cache {lookups}",
+                elapsed.as_millis()
+            );
+        }
+    }
+}
+
+/// 整个请求的处理时限，见 `--handler-timeout`；超时时 drop 掉后续 handler，
+/// 它们还没完成的上游请求（包括 spawn 出去的分批任务）随之取消
+struct Deadline(Duration);
+
+#[async_trait]
+impl Handler for Deadline {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let next = ctrl.call_next(req, depot, res);
+        if tokio::time::timeout(self.0, next).await.is_err() {
+            warn!("request {} timed out after {:?}", req.uri().path(), self.0);
+            ctrl.skip_rest();
+            res.render(StatusError::gateway_timeout().brief("request deadline exceeded"));
+        }
+    }
+}
+
+/// `--proxy-audio` 时 url 由服务端转发音频，而不是重定向
+static PROXY_AUDIO: LazyLock<bool> = LazyLock::new(|| args().proxy_audio);
+/// url / pic 重定向的状态码，见 `--url-redirect` / `--pic-redirect`
+static URL_REDIRECT: LazyLock<StatusCode> = LazyLock::new(|| args().url_redirect);
+static PIC_REDIRECT: LazyLock<StatusCode> = LazyLock::new(|| args().pic_redirect);
+/// 见 `--pic-placeholder`
+static PIC_PLACEHOLDER: LazyLock<Option<&'static str>> = LazyLock::new(|| {
+    args()
+        .pic_placeholder
+        .as_deref()
+        .filter(|url| !url.is_empty())
+});
+static PROXY_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// 上游没有这张封面时用的占位图，上游不可用等其他错误照常返回
+fn pic_placeholder(
+    e: &neo_meting::Error,
+    placeholder: Option<&'static str>,
+) -> Option<&'static str> {
+    use neo_meting::Error as E;
+    placeholder.filter(|_| matches!(e, E::None | E::NoField(_)))
+}
+
+/// 把音质信息写进 `X-Meting-Type` / `X-Meting-Br` / `X-Meting-Size`，返回地址
+fn url_headers(res: &mut Response, url: MetingUrl) -> String {
+    let info = [
+        ("x-meting-type", url.r#type),
+        ("x-meting-br", url.br.map(|br| br.to_string())),
+        ("x-meting-size", url.size.map(|size| size.to_string())),
+    ];
+    for (name, value) in info {
+        if let Some(value) = value {
+            let _ = res.add_header(name, value, true);
+        }
+    }
+    url.url
+}
+
+/// 状态码在解析参数时已经校验过是重定向
+fn redirect(res: &mut Response, status: StatusCode, url: &str) {
+    match Redirect::with_status_code(status, url) {
+        Ok(redirect) => res.render(redirect),
+        Err(e) => res.render(StatusError::internal_server_error().brief(e.to_string())),
+    }
+}
+
+/// 只转发 http(s)，host 等于 `hosts` 中的一个或是它的子域名
+fn proxy_allowed(url: &str, hosts: &[String]) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let Some(host) = url
+        .host_str()
+        .filter(|_| matches!(url.scheme(), "http" | "https"))
+    else {
+        return false;
+    };
+    hosts.iter().any(|allowed| {
+        host.eq_ignore_ascii_case(allowed)
+            || host
+                .len()
+                .checked_sub(allowed.len() + 1)
+                .is_some_and(|dot| {
+                    host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(allowed)
+                })
+    })
+}
+
+/// 转发上游音频，带上客户端的 `Range`，并原样返回 206 / `Content-Range` / `Accept-Ranges`
+///
+/// 不在 [`proxy_allowed`] 里的地址直接 400，避免被当成任意 URL 的代理
+async fn proxy_audio(req: &Request, res: &mut Response, url: &str, hosts: &[String]) {
+    if !proxy_allowed(url, hosts) {
+        warn!("proxy {url}: host not allowed");
+        res.render(StatusError::bad_request().brief("proxy: upstream host not allowed"));
+        return;
+    }
+    let upstream = PROXY_CLIENT
+        .get(url)
+        .then(|builder| match req.headers().get(RANGE) {
+            Some(range) => builder.header(RANGE, range),
+            None => builder,
+        })
+        .send()
+        .await;
+    let upstream = match upstream {
+        Ok(o) => o,
+        Err(e) => {
+            warn!("proxy {url}: {e:?}");
+            res.render(StatusError::bad_gateway());
+            return;
+        }
+    };
+    res.status_code(upstream.status());
+    for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_RANGE, ACCEPT_RANGES] {
+        if let Some(value) = upstream.headers().get(&name) {
+            res.headers_mut().insert(name, value.clone());
+        }
+    }
+    res.stream(upstream.bytes_stream());
+}
 
 trait SalvoMeting: MetingApi
 where
@@ -85,10 +360,21 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let url = self.pic(param).await;
+                let url = CACHE
+                    .get_or_try_insert(Cache::key(S::name(), "pic", param), self.pic(param))
+                    .await;
+                // `?size=` 不进缓存 key，缓存的是原图链接
+                let url = match req.query::<u32>("size").filter(|size| *size != 0) {
+                    Some(size) => url.map(|url| self.resize_pic(&url, size)),
+                    None => url,
+                };
                 match url {
-                    Ok(o) => res.render(Redirect::found(o)),
-                    Err(e) => res.render(handle_error!(e)),
+                    Ok(o) => redirect(res, *PIC_REDIRECT, &o),
+                    // 占位图不是这首歌的封面，不用 `--pic-redirect` 的永久重定向
+                    Err(e) => match pic_placeholder(&e, *PIC_PLACEHOLDER) {
+                        Some(placeholder) => redirect(res, StatusCode::FOUND, placeholder),
+                        None => res.render(handle_error!(e)),
+                    },
                 }
             }
         }
@@ -117,15 +403,93 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let url = self.lrc(param).await;
+                if matches!(req.query::<&str>("meta"), Some("1" | "true")) {
+                    match self.lrc_meta(param).await {
+                        Ok(o) => res.render(Json(o)),
+                        Err(e) => res.render(handle_error!(e)),
+                    }
+                    return;
+                }
+                let url = CACHE
+                    .get_or_try_insert(Cache::key(S::name(), "lrc", param), self.lrc(param))
+                    .await;
                 match url {
-                    Ok(o) => res.render(o),
+                    Ok(o) => {
+                        // 显式带上 charset，不认 body 编码的客户端才不会乱码
+                        let _ =
+                            res.add_header(CONTENT_TYPE, args().lrc_content_type.as_str(), true);
+                        res.render(o)
+                    }
                     Err(e) => res.render(handle_error!(e)),
                 }
             }
         }
         Hendle(self.clone())
     }
+    /// `lrcs?ids=1,2,3`：并发请求多首歌词，返回 `{ id: lrc }`，失败的为 `null`
+    fn get_lrcs(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let ids = req
+                    .query::<&str>("ids")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string)
+                    .collect::<BTreeSet<_>>();
+                if ids.is_empty() || ids.len() > MAX_BATCH_IDS {
+                    StatusError::bad_request()
+                        .brief(format!("expected 1 to {MAX_BATCH_IDS} ids"))
+                        .then(|e| res.render(e));
+                    return;
+                }
+                // 和 `lrc/{id}` 的 [`ValidId`] 一致，不合法的 id 不请求上游，也不占缓存
+                if let Some(id) = ids.iter().find(|id| !S::valid_id(id)) {
+                    res.render(StatusError::bad_request().brief(format!("invalid id: {id:?}")));
+                    return;
+                }
+                // 并发数由 provider 自己的许可限制
+                let mut tasks = JoinSet::new();
+                for id in ids {
+                    let this = self.0.clone();
+                    tasks.spawn(async move {
+                        let key = Cache::key(S::name(), "lrc", &id);
+                        let lrc = CACHE.get_or_try_insert(key, this.lrc(&id)).await;
+                        (id, lrc)
+                    });
+                }
+                let mut lrcs = BTreeMap::new();
+                while let Some(task) = tasks.join_next().await {
+                    let Ok((id, lrc)) = task else {
+                        continue;
+                    };
+                    let lrc = lrc
+                        .inspect_err(|e| warn!("lrcs {}/{id}: {e:?}", S::name()))
+                        .ok();
+                    lrcs.insert(id, lrc);
+                }
+                res.render(Json(lrcs));
+            }
+        }
+        Hendle(self.clone())
+    }
     fn get_url(self: Arc<Self>) -> impl Handler {
         struct Hendle<S: SalvoMeting>(Arc<S>);
         impl<S: SalvoMeting> Deref for Hendle<S> {
@@ -149,9 +513,40 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let url = self.url(param).await;
+                // `?level=` 时按音质等级取，实际拿到的格式 / 码率 / 大小放在响应头里
+                let url = match req.query::<&str>("level") {
+                    None => {
+                        CACHE
+                            .get_or_try_insert(url_cache_key(S::name(), param), self.url(param))
+                            .await
+                    }
+                    Some(level) if !URL_LEVELS.contains(&level) => {
+                        let levels = URL_LEVELS.join(" | ");
+                        res.render(StatusError::bad_request().brief(format!("level: {levels}")));
+                        return;
+                    }
+                    Some(level) => {
+                        let key = url_cache_key(S::name(), &format!("{param}:{level}"));
+                        let url = CACHE
+                            .get_or_try_insert(key, async {
+                                let url = self.url_with_level(param, level).await?;
+                                serde_json::to_string(&url)
+                                    .map_err(|e| neo_meting::Error::Remote(e.to_string()))
+                            })
+                            .await
+                            .and_then(|json| {
+                                serde_json::from_str::<MetingUrl>(&json)
+                                    .map_err(|e| neo_meting::Error::Remote(e.to_string()))
+                            });
+                        url.map(|url| url_headers(res, url))
+                    }
+                };
                 match url {
-                    Ok(o) => res.render(Redirect::found(o)),
+                    Ok(o) if *PROXY_AUDIO => {
+                        let hosts = [self.proxy_hosts(), args().proxy_hosts.clone()].concat();
+                        proxy_audio(req, res, &o, &hosts).await
+                    }
+                    Ok(o) => redirect(res, *URL_REDIRECT, &o),
                     Err(e) => res.render(handle_error!(e)),
                 }
             }
@@ -174,7 +569,7 @@ where
             async fn handle(
                 &self,
                 req: &mut Request,
-                _depot: &mut Depot,
+                depot: &mut Depot,
                 res: &mut Response,
                 _ctrl: &mut FlowCtrl,
             ) {
@@ -182,25 +577,215 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let server = req.uri();
-                let schema = server
-                    .scheme_str()
-                    .map(|schema| format!("{schema}://"))
-                    .unwrap_or("http://".to_string());
-                let Some(auth) = server.authority().map(|auth| auth.as_str()) else {
+                let Some(base) = base_url(req) else {
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let client = S::name();
+                let client = mounted_at::<S>(depot);
                 let url = self
                     .song(
                         param,
-                        |pid| format!("{schema}{auth}/{client}/pic/{pid}",),
-                        |lid| format!("{schema}{auth}/{client}/lrc/{lid}",),
-                        |uid| format!("{schema}{auth}/{client}/url/{uid}",),
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
                     )
                     .await;
                 match url {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+
+    /// `songs?ids=1,1,2`：重复的 id 只返回一次，顺序按第一次出现
+    fn get_songs(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let ids = req
+                    .query::<&str>("ids")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .collect::<Vec<_>>();
+                let unique = ids.iter().collect::<BTreeSet<_>>().len();
+                if unique == 0 || unique > MAX_BATCH_IDS {
+                    StatusError::bad_request()
+                        .brief(format!("expected 1 to {MAX_BATCH_IDS} ids"))
+                        .then(|e| res.render(e));
+                    return;
+                }
+                // 和 `{id}` 路由的 [`ValidId`] 一致，不合法的 id 是客户端错误，不发给上游
+                if let Some(id) = ids.iter().find(|id| !S::valid_id(id)) {
+                    res.render(StatusError::bad_request().brief(format!("invalid id: {id:?}")));
+                    return;
+                }
+                let client = mounted_at::<S>(depot);
+                let songs = self
+                    .songs(
+                        &ids,
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match songs {
+                    Ok(o) => {
+                        let songs = o.into_iter().map(|(_, song)| song).collect::<Vec<_>>();
+                        render_songs(req, res, songs)
+                    }
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+
+    /// `POST songs`，body 为 `{ "ids": ["123", "<分享链接>"] }`，每项先经过 [`MetingApi::parse_id`]
+    ///
+    /// 返回 `{ <原样的输入>: song }`，认不出或没查到的为 `null`
+    fn post_songs(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[derive(serde::Deserialize)]
+        struct SongsBody {
+            ids: Vec<String>,
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let Ok(body) = req.parse_json::<SongsBody>().await else {
+                    res.render(StatusError::bad_request().brief(r#"expected { "ids": [...] }"#));
+                    return;
+                };
+                let inputs = body.ids.into_iter().collect::<BTreeSet<_>>();
+                if inputs.is_empty() || inputs.len() > MAX_BATCH_IDS {
+                    StatusError::bad_request()
+                        .brief(format!("expected 1 to {MAX_BATCH_IDS} ids"))
+                        .then(|e| res.render(e));
+                    return;
+                }
+                let parsed = inputs
+                    .into_iter()
+                    .map(|input| {
+                        let id = S::parse_id(&input);
+                        (input, id)
+                    })
+                    .collect::<Vec<_>>();
+                let ids = parsed
+                    .iter()
+                    .filter_map(|(_, id)| id.as_deref())
+                    .collect::<Vec<_>>();
+                let client = mounted_at::<S>(depot);
+                let songs = match ids.is_empty() {
+                    true => Ok(Vec::new()),
+                    false => {
+                        self.songs(
+                            &ids,
+                            |pid| format!("{base}/{client}/pic/{pid}"),
+                            |lid| format!("{base}/{client}/lrc/{lid}"),
+                            |uid| format!("{base}/{client}/url/{uid}"),
+                        )
+                        .await
+                    }
+                };
+                let songs = match songs {
+                    Ok(o) => o,
+                    Err(e) => {
+                        res.render(handle_error!(e));
+                        return;
+                    }
+                };
+                // 按 `parse_id` 的结果对应回输入
+                let songs = songs.into_iter().collect::<BTreeMap<_, _>>();
+                let body = parsed
+                    .into_iter()
+                    .map(|(input, id)| {
+                        let song = id.and_then(|id| songs.get(&id).cloned());
+                        (input, song)
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                render_keyed_songs(req, res, body);
+            }
+        }
+        Hendle(self.clone())
+    }
+
+    /// `playlists?ids=1,2,3`：多个歌单的封面和曲目数，不含曲目
+    fn get_playlists(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let ids = req
+                    .query::<&str>("ids")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .collect::<Vec<_>>();
+                let unique = ids.iter().collect::<BTreeSet<_>>().len();
+                if unique == 0 || unique > MAX_BATCH_IDS {
+                    StatusError::bad_request()
+                        .brief(format!("expected 1 to {MAX_BATCH_IDS} ids"))
+                        .then(|e| res.render(e));
+                    return;
+                }
+                match self.playlists(&ids).await {
                     Ok(o) => res.render(Json(o)),
                     Err(e) => res.render(handle_error!(e)),
                 }
@@ -224,7 +809,7 @@ where
             async fn handle(
                 &self,
                 req: &mut Request,
-                _depot: &mut Depot,
+                depot: &mut Depot,
                 res: &mut Response,
                 _ctrl: &mut FlowCtrl,
             ) {
@@ -232,33 +817,93 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let server = req.uri();
-                let schema = server
-                    .scheme_str()
-                    .map(|sheme| format!("{sheme}://"))
-                    .unwrap_or("http://".to_string());
-                let Some(auth) = server.authority().map(|auth| auth.as_str()) else {
+                let Some(base) = base_url(req) else {
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let client = S::name();
-                let url = self
-                    .playlist(
-                        param,
-                        *RETRY.read().await,
-                        |pid| format!("{schema}{auth}/{client}/pic/{pid}",),
-                        |lid| format!("{schema}{auth}/{client}/lrc/{lid}",),
-                        |uid| format!("{schema}{auth}/{client}/url/{uid}",),
-                    )
-                    .await;
-                match url {
-                    Ok(o) => res.render(Json(o)),
+                // `?details=0` 时只返回曲目 id 和歌单里顺带的名称，不逐首请求详情
+                if matches!(req.query::<&str>("details"), Some("0" | "false")) {
+                    match self.playlist_tracks(param).await {
+                        Ok(o) => res.render(Json(o)),
+                        Err(e) => res.render(handle_error!(e)),
+                    }
+                    return;
+                }
+                let client = mounted_at::<S>(depot);
+                let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+                let pic = |pid: &str| format!("{base}/{client}/pic/{pid}");
+                let lrc = |lid: &str| format!("{base}/{client}/lrc/{lid}");
+                let url = |uid: &str| format!("{base}/{client}/url/{uid}");
+                let strict = strict_playlist(req);
+                // `?meta=1` 时带上歌单信息，否则只返回曲目，见 [`stream_songs`]
+                if matches!(req.query::<&str>("meta"), Some("1" | "true")) {
+                    let fetch = self.playlist_with_info(param, retry, pic, lrc, url);
+                    match STRICT.scope(strict, fetch).await {
+                        Ok((info, songs)) => {
+                            render_nested_songs(req, res, PlaylistBody { info, songs }, "songs")
+                        }
+                        Err(e) => res.render(handle_error!(e)),
+                    }
+                    return;
+                }
+                let fetch = self.playlist(param, retry, pic, lrc, url);
+                match STRICT.scope(strict, fetch).await {
+                    Ok(o) => stream_songs(req, res, o),
                     Err(e) => res.render(handle_error!(e)),
                 }
             }
         }
         Hendle(self.clone())
     }
+    /// `playlist/{id}/ws`：每解析完一首就发一条 MetingSong，全部发完后正常关闭
+    fn get_playlist_ws(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<String>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let (this, write) = (self.0.clone(), song_writer(req));
+                let client = mounted_at::<S>(depot);
+                // 回调在新任务里执行，地区要重新进入
+                let (region, strict) = (current_region(), strict_playlist(req));
+                let upgraded = WebSocketUpgrade::new()
+                    .upgrade(req, res, move |ws| async move {
+                        let stream = stream_playlist(this, param, base, client, write, ws);
+                        let stream = STRICT.scope(strict, stream);
+                        match region {
+                            Some(region) => REGION.scope(region, stream).await,
+                            None => stream.await,
+                        }
+                    })
+                    .await;
+                if let Err(e) = upgraded {
+                    res.render(e);
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
     #[allow(unused)]
     fn get_artist(self: Arc<Self>) -> impl Handler {
         struct Hendle<S: SalvoMeting>(Arc<S>);
@@ -275,7 +920,7 @@ where
             async fn handle(
                 &self,
                 req: &mut Request,
-                _depot: &mut Depot,
+                depot: &mut Depot,
                 res: &mut Response,
                 _ctrl: &mut FlowCtrl,
             ) {
@@ -283,26 +928,21 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let server = req.uri();
-                let schema = server
-                    .scheme_str()
-                    .map(|shema| format!("{shema}://"))
-                    .unwrap_or("https://".to_string());
-                let Some(auth) = server.authority().map(|auth| auth.as_str()) else {
+                let Some(base) = base_url(req) else {
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let client = S::name();
+                let client = mounted_at::<S>(depot);
                 let url = self
                     .artist(
                         param,
-                        |pid| format!("{schema}{auth}/{client}/pic/{pid}",),
-                        |lid| format!("{schema}{auth}/{client}/lrc/{lid}",),
-                        |uid| format!("{schema}{auth}/{client}/url/{uid}",),
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
                     )
                     .await;
                 match url {
-                    Ok(o) => res.render(Json(o)),
+                    Ok(o) => render_songs(req, res, o),
                     Err(e) => res.render(handle_error!(e)),
                 }
             }
@@ -324,7 +964,7 @@ where
             async fn handle(
                 &self,
                 req: &mut Request,
-                _depot: &mut Depot,
+                depot: &mut Depot,
                 res: &mut Response,
                 _ctrl: &mut FlowCtrl,
             ) {
@@ -332,67 +972,2260 @@ where
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let server = req.uri();
-                let schema = server
-                    .scheme_str()
-                    .map(|shema| format!("{shema}://"))
-                    .unwrap_or("https://".to_string());
-                let Some(auth) = server.authority().map(|auth| auth.as_str()) else {
+                let Some(base) = base_url(req) else {
                     res.render(StatusError::bad_request());
                     return;
                 };
-                let client = S::name();
+                let keyword = normalize_keyword(param);
+                // 空关键词上游会返回奇怪的结果，不往上游发
+                if keyword.is_empty() {
+                    res.render(StatusError::bad_request().brief("empty keyword"));
+                    return;
+                }
+                let client = mounted_at::<S>(depot);
                 let options = MetingSearchOptions {
                     limit: 30,
                     page: 1,
                     r#type: 1,
                 };
+                // `?type=artist` 时返回艺术家，id 可以继续请求 artist 接口
+                if req.query::<&str>("type") == Some("artist") {
+                    match self.search_artists(&keyword, options).await {
+                        Ok(o) => res.render(Json(o)),
+                        Err(e) => res.render(handle_error!(e)),
+                    }
+                    return;
+                }
                 let url = self
                     .search(
-                        param,
+                        &keyword,
                         options,
-                        |pid| format!("{schema}{auth}/{client}/pic/{pid}",),
-                        |lid| format!("{schema}{auth}/{client}/lrc/{lid}",),
-                        |uid| format!("{schema}{auth}/{client}/url/{uid}",),
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
                     )
                     .await;
                 match url {
-                    Ok(o) => res.render(Json(o)),
+                    Ok(o) => render_songs(req, res, o),
                     Err(e) => res.render(handle_error!(e)),
                 }
             }
         }
         Hendle(self.clone())
     }
-    fn into_router(self: Arc<Self>) -> Router {
-        Router::with_path(Self::name())
-            .push(Router::with_path("pic/{id}").get(self.clone().get_pic()))
-            .push(Router::with_path("lrc/{id}").get(self.clone().get_lrc()))
-            .push(Router::with_path("url/{id}").get(self.clone().get_url()))
-            .push(Router::with_path("song/{id}").get(self.clone().get_song()))
-            .push(Router::with_path("playlist/{id}").get(self.clone().get_playlist()))
-            .push(Router::with_path("artist/{id}").get(self.clone().get_artist()))
-            .push(Router::with_path("search/{id}").get(self.clone().get_search()))
-    }
-}
-
-impl<T: MetingApi> SalvoMeting for T {}
+    fn get_similar(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
 
-#[handler]
-fn help() -> &'static str {
-    include_str!("../help.txt")
-}
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
 
-#[tokio::main]
-async fn main() {
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let client = mounted_at::<S>(depot);
+                let url = self
+                    .similar(
+                        param,
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match url {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_daily(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let client = mounted_at::<S>(depot);
+                let url = self
+                    .daily(
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match url {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `album/{id}`，`?enrich=1` 时曲目逐批请求歌曲详情，和歌单曲目字段一致
+    fn get_album(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let enrich = matches!(req.query::<&str>("enrich"), Some("1" | "true"));
+                let client = mounted_at::<S>(depot);
+                let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+                let songs = self
+                    .album(
+                        param,
+                        enrich,
+                        retry,
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match songs {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `new?area=`：新歌速递，不需要登录
+    fn get_new_releases(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let area = req
+                    .query::<&str>("area")
+                    .unwrap_or("ALL")
+                    .to_ascii_uppercase();
+                if !NEW_RELEASE_AREAS.contains(&area.as_str()) {
+                    let areas = NEW_RELEASE_AREAS.join(" | ");
+                    res.render(StatusError::bad_request().brief(format!("area: {areas}")));
+                    return;
+                }
+                let client = mounted_at::<S>(depot);
+                let url = self
+                    .new_releases(
+                        &area,
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match url {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_liked(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(base) = base_url(req) else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let client = mounted_at::<S>(depot);
+                let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+                let url = self
+                    .liked(
+                        retry,
+                        |pid| format!("{base}/{client}/pic/{pid}"),
+                        |lid| format!("{base}/{client}/lrc/{lid}"),
+                        |uid| format!("{base}/{client}/url/{uid}"),
+                    )
+                    .await;
+                match url {
+                    Ok(o) => render_songs(req, res, o),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_user_playlists(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                match self.user_playlists(param).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `recommend`：推荐歌单，不需要登录，曲目走 `playlist/{id}`
+    fn get_recommend(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                _req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                match self.recommended_playlists().await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `toplist`：榜单列表，榜单曲目走 `playlist/{id}`
+    fn get_toplist(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                _req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                match self.toplist().await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_lyrics(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                match self.lyrics(param).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `comments/{id}`：评论总数和热门评论
+    fn get_comments(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                match self.comments(param).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_check(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                match self.check(param).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_artist_albums(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let limit = req.query::<usize>("limit").unwrap_or(30);
+                let offset = req.query::<usize>("offset").unwrap_or(0);
+                match self.artist_albums(param, limit, offset).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `artist/{id}/mvs?limit=30&offset=0`
+    fn get_artist_mvs(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let limit = req.query::<usize>("limit").unwrap_or(30);
+                let offset = req.query::<usize>("offset").unwrap_or(0);
+                match self.artist_mvs(param, limit, offset).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// `suggest/{keyword}`，只有名字的搜索建议
+    fn get_suggest(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let Some(param) = req.param::<&str>("id") else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                let keyword = normalize_keyword(param);
+                if keyword.is_empty() {
+                    res.render(StatusError::bad_request().brief("empty keyword"));
+                    return;
+                }
+                match self.suggest(&keyword).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    fn get_raw(self: Arc<Self>) -> impl Handler {
+        struct Hendle<S: SalvoMeting>(Arc<S>);
+        impl<S: SalvoMeting> Deref for Hendle<S> {
+            type Target = Arc<S>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        #[async_trait]
+        impl<S: SalvoMeting + Sync + Send + 'static> Handler for Hendle<S> {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let (Some(r#type), Some(id)) = (req.param::<&str>("type"), req.param::<&str>("id"))
+                else {
+                    res.render(StatusError::bad_request());
+                    return;
+                };
+                match self.raw(r#type, id).await {
+                    Ok(o) => res.render(Json(o)),
+                    Err(e) => res.render(handle_error!(e)),
+                }
+            }
+        }
+        Hendle(self.clone())
+    }
+    /// 没声明 `capability` 的 provider 挂 [`Unsupported`]，而不是走默认实现返回 501
+    fn route(path: &str, capability: Capability, handler: impl Handler) -> Router {
+        let router = Router::with_path(path).hoop(SlowLog {
+            provider: Self::name(),
+            capability,
+            threshold: *SLOW_THRESHOLD,
+        });
+        match Self::capabilities().contains(&capability) {
+            // 搜索的 `{**id}` 和 `raw/search/{id}` 是关键词，不检查
+            true if path.contains("{id}") && capability != Capability::Raw => {
+                router.hoop(ValidId(Self::valid_id)).get(handler)
+            }
+            true => router.get(handler),
+            false => router.get(Unsupported {
+                provider: Self::name(),
+                capability,
+            }),
+        }
+    }
+    /// 挂在 `/{name}` 和每个 `aliases` 下，生成的子链接跟随请求进来的前缀
+    fn into_router(self: Arc<Self>, aliases: &[&'static str]) -> Router {
+        std::iter::once(Self::name())
+            .chain(aliases.iter().copied())
+            .fold(Router::new(), |router, prefix| {
+                router.push(self.clone().routes(prefix))
+            })
+    }
+    fn routes(self: Arc<Self>, prefix: &'static str) -> Router {
+        use Capability as C;
+        Router::with_path(prefix)
+            .hoop(Mount(prefix))
+            .push(Self::route("pic/{id}", C::Pic, self.clone().get_pic()))
+            .push(Self::route("lrc/{id}", C::Lrc, self.clone().get_lrc()))
+            .push(Self::route("lrcs", C::Lrc, self.clone().get_lrcs()))
+            .push(Self::route(
+                "lyric/{id}",
+                C::Lyrics,
+                self.clone().get_lyrics(),
+            ))
+            .push(Self::route(
+                "comments/{id}",
+                C::Comments,
+                self.clone().get_comments(),
+            ))
+            .push(Self::route("url/{id}", C::Url, self.clone().get_url()))
+            .push(Self::route(
+                "check/{id}",
+                C::Check,
+                self.clone().get_check(),
+            ))
+            .push(Self::route("song/{id}", C::Song, self.clone().get_song()))
+            .push(
+                Self::route("songs", C::Song, self.clone().get_songs()).then(|router| {
+                    match Self::capabilities().contains(&C::Song) {
+                        true => router.post(self.clone().post_songs()),
+                        false => router,
+                    }
+                }),
+            )
+            .push(Self::route(
+                "playlists",
+                C::Playlist,
+                self.clone().get_playlists(),
+            ))
+            .push(Self::route(
+                "playlist/{id}",
+                C::Playlist,
+                self.clone().get_playlist(),
+            ))
+            .push(Self::route(
+                "playlist/{id}/ws",
+                C::Playlist,
+                self.clone().get_playlist_ws(),
+            ))
+            .push(Self::route(
+                "artist/{id}",
+                C::Artist,
+                self.clone().get_artist(),
+            ))
+            .push(Self::route(
+                "artist/{id}/albums",
+                C::ArtistAlbums,
+                self.clone().get_artist_albums(),
+            ))
+            .push(Self::route(
+                "artist/{id}/mvs",
+                C::ArtistMvs,
+                self.clone().get_artist_mvs(),
+            ))
+            .push(Self::route(
+                "album/{id}",
+                C::Album,
+                self.clone().get_album(),
+            ))
+            .push(Self::route(
+                "search/{**id}",
+                C::Search,
+                self.clone().get_search(),
+            ))
+            .push(Self::route(
+                "suggest/{**id}",
+                C::Suggest,
+                self.clone().get_suggest(),
+            ))
+            .push(Self::route(
+                "similar/{id}",
+                C::Similar,
+                self.clone().get_similar(),
+            ))
+            .push(Self::route("daily", C::Daily, self.clone().get_daily()))
+            .push(Self::route("liked", C::Liked, self.clone().get_liked()))
+            .push(Self::route(
+                "new",
+                C::NewReleases,
+                self.clone().get_new_releases(),
+            ))
+            .push(Self::route(
+                "recommend",
+                C::Recommend,
+                self.clone().get_recommend(),
+            ))
+            .push(Self::route(
+                "toplist",
+                C::Toplist,
+                self.clone().get_toplist(),
+            ))
+            .push(Self::route(
+                "user/{id}/playlists",
+                C::UserPlaylists,
+                self.clone().get_user_playlists(),
+            ))
+            .push(Self::route("raw/{type}/{id}", C::Raw, self.clone().get_raw()).hoop(require_auth))
+    }
+}
+
+/// 把请求进来时的 provider 前缀记进 depot，见 [`mounted_at`]
+struct Mount(&'static str);
+
+const MOUNT_KEY: &str = "mount";
+
+#[async_trait]
+impl Handler for Mount {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        depot.insert(MOUNT_KEY, self.0);
+    }
+}
+
+/// 请求进来时的 provider 前缀（`name()` 或某个别名），生成子链接用
+fn mounted_at<S: MetingApi>(depot: &Depot) -> &'static str {
+    depot
+        .get::<&'static str>(MOUNT_KEY)
+        .copied()
+        .unwrap_or(S::name())
+}
+
+/// `?strict=1|0`，没有时用 `--strict-playlist`
+fn strict_playlist(req: &Request) -> bool {
+    match req.query::<&str>("strict") {
+        Some("1" | "true") => true,
+        Some("0" | "false") => false,
+        _ => args().strict_playlist,
+    }
+}
+
+/// 边拉取歌单边把曲目写进 `ws`；出错时以 1011 关闭，客户端断开后停止发送
+async fn stream_playlist<S: MetingApi>(
+    provider: Arc<S>,
+    id: String,
+    base: String,
+    client: &'static str,
+    write: impl Fn(&MetingSong) -> serde_json::Result<String> + Send,
+    mut ws: WebSocket,
+) {
+    let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let disconnected = Notify::new();
+    let fetch = provider.playlist_each(
+        &id,
+        retry,
+        |pid| format!("{base}/{client}/pic/{pid}"),
+        |lid| format!("{base}/{client}/lrc/{lid}"),
+        |uid| format!("{base}/{client}/url/{uid}"),
+        move |batch| drop(tx.send(batch)),
+    );
+    // 客户端断开后 drop 掉拉取的 future，还没跑完的分批请求一起取消
+    let fetch = async {
+        tokio::select! {
+            fetched = fetch => Some(fetched),
+            _ = disconnected.notified() => None,
+        }
+    };
+    let forward = async {
+        while let Some(batch) = rx.recv().await {
+            for song in batch {
+                let Ok(text) = write(&song) else {
+                    continue;
+                };
+                if ws.send(Message::text(text)).await.is_err() {
+                    disconnected.notify_one();
+                    return false;
+                }
+            }
+        }
+        true
+    };
+    let (fetched, connected) = tokio::join!(fetch, forward);
+    let (Some(fetched), true) = (fetched, connected) else {
+        return;
+    };
+    let close = match fetched {
+        Ok(_) => Message::close(),
+        Err(e) => {
+            warn!("playlist ws {client}/{id}: {e:?}");
+            Message::close_with(1011u16, format!("{e:?}"))
+        }
+    };
+    ws.send(close).await.ok();
+}
+
+/// 路径里的 `{id}` 不符合 [`MetingApi::valid_id`] 时直接 400
+struct ValidId(fn(&str) -> bool);
+
+#[async_trait]
+impl Handler for ValidId {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let id = req.param::<&str>("id").unwrap_or_default();
+        if !(self.0)(id) {
+            res.render(StatusError::bad_request().brief(format!("invalid id: {id:?}")));
+            ctrl.skip_rest();
+        }
+    }
+}
+
+/// provider 不支持的方法：404 加上说明，而不是笼统的 501
+struct Unsupported {
+    provider: &'static str,
+    capability: Capability,
+}
+
+#[async_trait]
+impl Handler for Unsupported {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let Self {
+            provider,
+            capability,
+        } = self;
+        StatusError::not_found()
+            .brief(format!(
+                "{provider} does not support {}, see /providers",
+                capability.as_str()
+            ))
+            .then(|e| res.render(e));
+    }
+}
+
+impl<T: MetingApi> SalvoMeting for T {}
+
+/// `?meta=1` 时的歌单响应
+#[derive(serde::Serialize)]
+struct PlaylistBody {
+    info: MetingPlaylist,
+    songs: Vec<MetingSong>,
+}
+
+/// 可以放进 `Vec<Arc<dyn Provider>>` 的 provider 视图，给汇总类接口用
+#[async_trait]
+trait Provider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn capabilities(&self) -> &'static [Capability];
+    fn stats(&self) -> MetingStats;
+    fn source(&self) -> &str;
+    /// `base` 为 `{scheme}://{authority}{base_path}`，用来生成 pic / lrc / url 子链接
+    async fn search(
+        &self,
+        keyword: &str,
+        options: MetingSearchOptions,
+        base: &str,
+    ) -> Result<Vec<MetingSong>, neo_meting::Error>;
+    /// 搜索 `name artist`，取第一个 [`MetingSong::matches`] 的结果的上游播放地址
+    async fn find_url(&self, name: &str, artist: &str) -> Result<String, neo_meting::Error>;
+    /// 把歌单 `id` 里每首歌的 url / pic / lrc 解析进缓存，进度记在 `job` 里
+    async fn warm_playlist(&self, id: &str, job: &warm::Job) -> Result<(), neo_meting::Error>;
+    /// 只把封面解析进缓存，返回歌曲 id 到上游封面地址的映射，拿不到的为 `None`
+    ///
+    /// 给了歌单 `playlist` 时用歌单里的曲目，否则用 `ids`；同时最多 `tasks` 个请求
+    async fn warm_pics(
+        &self,
+        playlist: Option<&str>,
+        ids: Vec<String>,
+        tasks: usize,
+    ) -> Result<BTreeMap<String, Option<String>>, neo_meting::Error>;
+    fn selftest_sample(&self) -> Option<(&'static str, &'static str)>;
+    /// 用样本请求一次 `method`，只看是否成功，不经过缓存；见 [`selftest`]
+    async fn probe(
+        &self,
+        method: Capability,
+        id: &str,
+        keyword: &str,
+    ) -> Result<(), neo_meting::Error>;
+}
+
+#[async_trait]
+impl<T: MetingApi> Provider for T {
+    fn name(&self) -> &'static str {
+        T::name()
+    }
+    fn capabilities(&self) -> &'static [Capability] {
+        T::capabilities()
+    }
+    fn stats(&self) -> MetingStats {
+        MetingApi::stats(self)
+    }
+    fn source(&self) -> &str {
+        MetingApi::source(self)
+    }
+    async fn search(
+        &self,
+        keyword: &str,
+        options: MetingSearchOptions,
+        base: &str,
+    ) -> Result<Vec<MetingSong>, neo_meting::Error> {
+        let client = T::name();
+        MetingApi::search(
+            self,
+            keyword,
+            options,
+            |pid| format!("{base}/{client}/pic/{pid}"),
+            |lid| format!("{base}/{client}/lrc/{lid}"),
+            |uid| format!("{base}/{client}/url/{uid}"),
+        )
+        .await
+    }
+    async fn find_url(&self, name: &str, artist: &str) -> Result<String, neo_meting::Error> {
+        let options = MetingSearchOptions {
+            limit: 10,
+            page: 1,
+            r#type: 1,
+        };
+        let keyword = normalize_keyword(&format!("{name} {artist}"));
+        // 这里只要 id，url 字段直接放 id
+        let songs = MetingApi::search(
+            self,
+            &keyword,
+            options,
+            |_| String::new(),
+            |_| String::new(),
+            str::to_string,
+        )
+        .await?;
+        let id = songs
+            .iter()
+            .find(|song| song.matches(name, artist))
+            .ok_or(neo_meting::Error::None)?
+            .url();
+        CACHE
+            .get_or_try_insert(url_cache_key(T::name(), id), self.url(id))
+            .await
+    }
+    async fn warm_playlist(&self, id: &str, job: &warm::Job) -> Result<(), neo_meting::Error> {
+        use futures_util::StreamExt;
+
+        let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+        // 只要 id，三个字段直接放 id，和 `/pic/{id}` 等接口的缓存 key 一致
+        let songs = MetingApi::playlist(
+            self,
+            id,
+            retry,
+            str::to_string,
+            str::to_string,
+            str::to_string,
+        )
+        .await?;
+        job.start(songs.len());
+        futures_util::stream::iter(songs)
+            .for_each_concurrent(warm::TASKS, |song| async move {
+                let name = T::name();
+                let (url, pic, lrc) = (song.url(), song.pic(), song.lrc());
+                let (url, pic, lrc) = tokio::join!(
+                    CACHE.get_or_try_insert(url_cache_key(name, url), self.url(url)),
+                    CACHE.get_or_try_insert(Cache::key(name, "pic", pic), self.pic(pic)),
+                    CACHE.get_or_try_insert(Cache::key(name, "lrc", lrc), self.lrc(lrc)),
+                );
+                job.finish_one(url.is_ok() && pic.is_ok() && lrc.is_ok());
+            })
+            .await;
+        Ok(())
+    }
+    async fn warm_pics(
+        &self,
+        playlist: Option<&str>,
+        ids: Vec<String>,
+        tasks: usize,
+    ) -> Result<BTreeMap<String, Option<String>>, neo_meting::Error> {
+        use futures_util::StreamExt;
+
+        // 歌单里 url 字段放歌曲 id，pic 字段放 pic 接口用的 id
+        let pairs = match playlist {
+            Some(id) => {
+                let retry = Arc::new(RetryBudget::new(args().retry, args().retry_budget));
+                let songs = MetingApi::playlist(
+                    self,
+                    id,
+                    retry,
+                    str::to_string,
+                    |_| String::new(),
+                    str::to_string,
+                )
+                .await?;
+                songs
+                    .iter()
+                    .map(|song| (song.url().to_string(), song.pic().to_string()))
+                    .collect::<Vec<_>>()
+            }
+            None => ids.into_iter().map(|id| (id.clone(), id)).collect(),
+        };
+        let pics = futures_util::stream::iter(pairs)
+            .map(|(id, pic)| async move {
+                let key = Cache::key(T::name(), "pic", &pic);
+                (id, CACHE.get_or_try_insert(key, self.pic(&pic)).await.ok())
+            })
+            .buffer_unordered(tasks.max(1))
+            .collect()
+            .await;
+        Ok(pics)
+    }
+    fn selftest_sample(&self) -> Option<(&'static str, &'static str)> {
+        T::selftest_sample()
+    }
+    async fn probe(
+        &self,
+        method: Capability,
+        id: &str,
+        keyword: &str,
+    ) -> Result<(), neo_meting::Error> {
+        let empty = |_: &str| String::new();
+        match method {
+            Capability::Song => self.song(id, empty, empty, empty).await.map(drop),
+            Capability::Pic => self.pic(id).await.map(drop),
+            Capability::Lrc => self.lrc(id).await.map(drop),
+            Capability::Search => {
+                let options = MetingSearchOptions {
+                    limit: 10,
+                    page: 1,
+                    r#type: 1,
+                };
+                // url 字段直接放 id，样本要出现在结果里
+                MetingApi::search(self, keyword, options, empty, empty, str::to_string)
+                    .await?
+                    .iter()
+                    .any(|song| song.url() == id)
+                    .then_some(())
+                    .ok_or(neo_meting::Error::None)
+            }
+            _ => Err(neo_meting::Error::Unimplemented),
+        }
+    }
+}
+
+struct Stats {
+    providers: Vec<Arc<dyn Provider>>,
+    aggregate: Arc<aggregate::FanOutLimit>,
+}
+
+#[derive(serde::Serialize)]
+struct StatsBody {
+    providers: BTreeMap<&'static str, MetingStats>,
+    /// 汇总接口共用的并发额度
+    aggregate: MetingStats,
+    cache: CacheStats,
+}
+
+#[async_trait]
+impl Handler for Stats {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let providers = self
+            .providers
+            .iter()
+            .map(|provider| (provider.name(), provider.stats()))
+            .collect();
+        res.render(Json(StatsBody {
+            providers,
+            aggregate: self.aggregate.stats(),
+            cache: CACHE.stats(),
+        }));
+    }
+}
+
+struct Providers(Vec<Arc<dyn Provider>>);
+
+#[derive(serde::Serialize)]
+struct ProviderInfo {
+    name: &'static str,
+    capabilities: &'static [Capability],
+}
+
+#[async_trait]
+impl Handler for Providers {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        self.0
+            .iter()
+            .map(|provider| ProviderInfo {
+                name: provider.name(),
+                capabilities: provider.capabilities(),
+            })
+            .collect::<Vec<_>>()
+            .then(|providers| res.render(Json(providers)));
+    }
+}
+
+/// 播放地址和地区有关，缓存也按地区分开
+fn url_cache_key(provider: &str, id: &str) -> String {
+    // 调试用的 `?ip=` 也会影响上游返回的地址，同样分开缓存
+    let scope = [current_region(), current_real_ip().map(|ip| ip.to_string())]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    match scope.is_empty() {
+        true => Cache::key(provider, "url", id),
+        false => Cache::key(provider, "url", &format!("{id}@{}", scope.join("@"))),
+    }
+}
+
+/// 请求的 `{scheme}://{authority}{base_path}`，用于生成 pic / lrc / url 子链接
+///
+/// uri 里没有时 scheme 取监听端的协议，authority 取 `Host` 头，都没有时为 `None`
+fn base_url(req: &Request) -> Option<String> {
+    let uri = req.uri();
+    let scheme = uri.scheme_str().unwrap_or(req.scheme().as_str());
+    let authority = match uri.authority() {
+        Some(authority) => authority.as_str(),
+        None => req.header::<&str>(HOST)?,
+    };
+    Some(format!("{scheme}://{authority}{}", *BASE_PATH))
+}
+
+#[handler]
+fn help() -> &'static str {
+    include_str!("../help.txt")
+}
+
+/// 浏览器（`Accept` 里有 `text/html`）给一个带链接的页面，其他客户端仍然是纯文本的 help
+#[handler]
+fn index(req: &mut Request, res: &mut Response) {
+    let html = req
+        .header::<&str>(ACCEPT)
+        .is_some_and(|accept| accept.contains("text/html"));
+    if html {
+        let page = include_str!("../index.html").replace("{base}", &BASE_PATH);
+        res.render(Text::Html(page));
+    } else {
+        res.render(include_str!("../help.txt"));
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ClearBody {
+    removed: usize,
+    /// 清理前后的条目数
+    before: usize,
+    after: usize,
+}
+
+/// `POST /cache/clear?provider=&method=&id=`，都不给时清空；`id` 按前缀匹配
+#[handler]
+async fn cache_clear(req: &mut Request, res: &mut Response) {
+    let query = |name| req.query::<String>(name).filter(|value| !value.is_empty());
+    let filter = ClearFilter {
+        provider: query("provider"),
+        method: query("method"),
+        id: query("id"),
+    };
+    let before = CACHE.stats().size;
+    let removed = CACHE.clear(&filter);
+    let after = CACHE.stats().size;
+    res.render(Json(ClearBody {
+        removed,
+        before,
+        after,
+    }));
+}
+
+/// 调试用：`POST /debug/weapi`，把 body 里的 json 原样按 weapi 加密，返回 `{ params, encSecKey }`
+///
+/// 每次随机 skey，结果和实际发给上游的一样只是不可复现；加密失败时返回 [`ParseErr`] 的变体
+///
+/// [`ParseErr`]: neo_meting::netease::ParseErr
+#[handler]
+async fn debug_weapi(req: &mut Request, res: &mut Response) {
+    let body = match req.payload().await {
+        Ok(body) => String::from_utf8_lossy(body).into_owned(),
+        Err(e) => {
+            res.render(StatusError::bad_request().brief(format!("{e}")));
+            return;
+        }
+    };
+    if serde_json::from_str::<serde::de::IgnoredAny>(&body).is_err() {
+        res.render(StatusError::bad_request().brief("expected a json body"));
+        return;
+    }
+    match WeapiEncoder::try_from_str(&body) {
+        Ok(encoded) => res.render(Json(encoded)),
+        Err(e) => res.render(StatusError::internal_server_error().brief(format!("{e:?}"))),
+    }
+}
+
+/// 没有图标，204 免得浏览器每次都落到 404
+#[handler]
+fn favicon(res: &mut Response) {
+    res.status_code(StatusCode::NO_CONTENT);
+}
+
+fn main() {
+    let args = args::init();
+    if let Err(problems) = args.validate() {
+        args::exit(format!(
+            "invalid configuration:\n  - {}",
+            problems.join("\n  - ")
+        ));
+    }
     tracing_subscriber::fmt().init();
-    let netease = Semaphore::const_new(8)
+    // 不设置 `--workers` 时和 `#[tokio::main]` 一样按 CPU 数
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = args.workers {
+        runtime.worker_threads(workers.get());
+    }
+    let runtime = runtime
+        .enable_all()
+        .build()
+        .unwrap_or_else(|e| args::exit(format!("failed to start runtime: {e}")));
+    if args.selftest {
+        let providers: Vec<Arc<dyn Provider>> = vec![netease(args)];
+        let checks = runtime.block_on(selftest::run(&providers));
+        std::process::exit(if selftest::report(&checks) { 0 } else { 1 });
+    }
+    runtime.block_on(serve(args));
+}
+
+/// 根路径下固定的第一段，`--provider-sets` 的前缀不能和它们重名
+const TOP_LEVEL_ROUTES: &[&str] = &[
+    "help",
+    "favicon.ico",
+    "stats",
+    "providers",
+    "search",
+    "url",
+    "cache",
+    "debug",
+];
+
+/// 按 provider 集合工作的接口：`/providers`、`/search/{keyword}`、`/url/multi`
+fn aggregate_routes(
+    providers: Vec<Arc<dyn Provider>>,
+    limit: &Arc<aggregate::FanOutLimit>,
+    timeout: Duration,
+) -> Router {
+    let search = aggregate::Search::new(providers.clone(), limit.clone(), timeout);
+    let multi_url = aggregate::MultiUrl::new(providers.clone(), limit.clone(), timeout);
+    Router::new()
+        .push(Router::with_path("providers").get(Providers(providers)))
+        .push(Router::with_path("search/{**keyword}").get(search))
+        .push(Router::with_path("url/multi").get(multi_url))
+}
+
+/// 按启动参数配置的 netease，`serve` 和 `--selftest` 共用
+fn netease(args: &'static args::Args) -> Arc<Netease> {
+    // 每个 provider 独立的并发额度
+    Semaphore::new(args.netease_concurrency)
         .then(Arc::new)
-        .then(Netease::new)
+        .then(|counter| {
+            let options = args.client_options().unwrap_or_else(|e| args::exit(e));
+            Netease::with_client_options(counter, options).unwrap_or_else(|e| args::exit(e))
+        })
+        .with_queue_timeout(args.queue_timeout())
+        .with_playlist_tasks(args.playlist_tasks)
+        .with_retry_permit(args.retry_permit)
+        .with_pic_cdn(args.pic_cdn.as_deref())
+        .with_source(args.netease_source.as_deref())
+        .with_region(args.region.as_deref())
+        .with_login_cookie(args.cookie.as_deref())
+        .then(|netease| {
+            #[cfg(feature = "random-ip")]
+            return netease.with_random_ip(args.random_ip.clone());
+            #[cfg(not(feature = "random-ip"))]
+            return netease;
+        })
         .then(Arc::new)
-        .into_router();
-    let acceptor = TcpListener::new("127.0.0.1:5811").bind().await;
-    Server::new(acceptor)
-        .serve(Router::new().get(help).push(netease))
-        .await;
+}
+
+async fn serve(args: &'static args::Args) {
+    let netease = netease(args);
+    // 配置了登录 Cookie 时不需要匿名 token
+    if let Some(interval) = args.token_refresh().filter(|_| !netease.logged_in()) {
+        netease.spawn_token_refresh(interval);
+    }
+    if args.daily_signin {
+        netease.spawn_daily_signin();
+    }
+    let providers: Vec<Arc<dyn Provider>> = vec![netease.clone()];
+    if let Some((alias, provider)) = args
+        .alias
+        .iter()
+        .find(|(_, provider)| !providers.iter().any(|p| p.name() == provider))
+    {
+        args::exit(format!("--alias {alias}={provider}: unknown provider"));
+    }
+    let aliases = args
+        .alias
+        .iter()
+        .filter(|(_, provider)| provider == <Netease as MetingApi>::name())
+        .map(|(alias, _)| alias.as_str())
+        .collect::<Vec<_>>();
+    let provider_sets = args.provider_sets().unwrap_or_else(|e| args::exit(e));
+    for (prefix, filter) in &provider_sets {
+        let known = |name: &str| providers.iter().any(|p| p.name() == name);
+        if let Some(unknown) = filter.names().iter().find(|name| !known(name)) {
+            args::exit(format!(
+                "--provider-sets {prefix}: unknown provider {unknown}"
+            ));
+        }
+        let aliased = args.alias.iter().any(|(alias, _)| alias == prefix);
+        if known(prefix) || aliased || TOP_LEVEL_ROUTES.contains(&prefix.as_str()) {
+            args::exit(format!(
+                "--provider-sets {prefix}: conflicts with an existing route"
+            ));
+        }
+    }
+    let fan_out_limit = Arc::new(aggregate::FanOutLimit::new(args.aggregate_concurrency));
+    let stats = Stats {
+        providers: providers.clone(),
+        aggregate: fan_out_limit.clone(),
+    };
+    let warm = warm::Warm::new(providers.clone());
+    let warm_status = warm.status();
+    let warm_pics = warm::WarmPics::new(providers.clone(), args.warm_pic_tasks);
+    let root = match BASE_PATH.as_str() {
+        "" => Router::new(),
+        base_path => Router::with_path(base_path),
+    };
+    let root = match args.handler_timeout() {
+        Some(timeout) => root.hoop(Deadline(timeout)),
+        None => root,
+    };
+    let root = provider_sets
+        .iter()
+        .map(|(prefix, filter)| {
+            let providers = filter.select(&providers);
+            let routes = aggregate_routes(providers, &fan_out_limit, args.aggregate_timeout());
+            Router::with_path(prefix).push(routes)
+        })
+        .fold(root, Router::push);
+    let router = Arc::new(
+        root.hoop(pretty_scope)
+            .hoop(region_scope)
+            .hoop(ip_scope)
+            .get(index)
+            .push(Router::with_path("help").get(help))
+            .push(Router::with_path("favicon.ico").get(favicon))
+            .push(Router::with_path("stats").get(stats))
+            .push(aggregate_routes(
+                providers.clone(),
+                &fan_out_limit,
+                args.aggregate_timeout(),
+            ))
+            .push(
+                Router::with_path("cache/clear")
+                    .hoop(require_auth)
+                    .post(cache_clear),
+            )
+            .push(
+                Router::with_path("debug/weapi")
+                    .hoop(require_auth)
+                    .post(debug_weapi),
+            )
+            .push(
+                Router::with_path("cache/warm")
+                    .hoop(require_auth)
+                    .post(warm)
+                    .push(Router::with_path("pics").post(warm_pics))
+                    .push(Router::with_path("{job}").get(warm_status)),
+            )
+            .push(netease.into_router(&aliases)),
+    );
+    // 每个监听地址一个 Server，共用同一套路由
+    let mut servers = JoinSet::new();
+    for addr in args.bind.iter().copied() {
+        let acceptor = TcpListener::new(addr)
+            .try_bind()
+            .await
+            .unwrap_or_else(|e| args::exit(format!("--bind {addr}: {e}")));
+        servers.spawn(Server::new(acceptor).serve(router.clone()));
+    }
+    servers.join_all().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use neo_meting::netease::Netease;
+    use salvo::{
+        handler,
+        http::{
+            header::{ACCEPT, ACCEPT_RANGES, CONTENT_RANGE, CONTENT_TYPE, HOST, RANGE},
+            StatusCode,
+        },
+        test::{ResponseExt, TestClient},
+        Request, Response, Router, Service,
+    };
+    use serde_json::json;
+    use tokio::sync::Semaphore;
+    use wiremock::{
+        matchers::{header, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        base_url, cache_clear, debug_weapi, favicon, index, ip_scope, proxy_allowed, proxy_audio,
+        region_scope, require_auth, Deadline, Provider, Providers, SalvoMeting, SlowLog, Then,
+    };
+
+    /// 指向 mock 上游的 netease 路由，另外挂在别名 `/default` 下
+    fn netease_service(upstream: &MockServer) -> Service {
+        let netease = Netease::new(Arc::new(Semaphore::new(8)))
+            .with_base_url(&upstream.uri())
+            .then(Arc::new);
+        Service::new(Router::new().push(netease.into_router(&["default"])))
+    }
+
+    #[tokio::test]
+    async fn test_index() {
+        let service = Service::new(
+            Router::new()
+                .get(index)
+                .push(Router::with_path("favicon.ico").get(favicon)),
+        );
+        let mut res = TestClient::get("http://127.0.0.1/")
+            .add_header(ACCEPT, "text/html,application/xhtml+xml", true)
+            .send(&service)
+            .await;
+        let content_type = res.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        assert!(content_type.starts_with("text/html"));
+        assert!(res.take_string().await.unwrap().contains(r#"href="/help""#));
+
+        let mut res = TestClient::get("http://127.0.0.1/").send(&service).await;
+        assert_eq!(
+            res.take_string().await.unwrap(),
+            include_str!("../help.txt")
+        );
+
+        let res = TestClient::get("http://127.0.0.1/favicon.ico")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NO_CONTENT));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_audio_range() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/audio.mp3"))
+            .and(header(RANGE, "bytes=0-3"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header(CONTENT_RANGE, "bytes 0-3/10")
+                    .insert_header(ACCEPT_RANGES, "bytes")
+                    .set_body_bytes(b"0123".to_vec()),
+            )
+            .mount(&upstream)
+            .await;
+
+        #[handler]
+        async fn audio(req: &mut Request, res: &mut Response) {
+            let url = req.query::<String>("upstream").unwrap();
+            proxy_audio(req, res, &url, &["127.0.0.1".to_string()]).await
+        }
+        let service = Service::new(Router::new().get(audio));
+
+        let mut res = TestClient::get("http://127.0.0.1:5811/")
+            .query("upstream", format!("{}/audio.mp3", upstream.uri()))
+            .add_header(RANGE, "bytes=0-3", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes 0-3/10");
+        assert_eq!(res.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+        assert_eq!(res.take_string().await.unwrap(), "0123");
+
+        let res = TestClient::get("http://127.0.0.1:5811/")
+            .query("upstream", "http://169.254.169.254/latest/meta-data")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_proxy_allowed() {
+        let hosts = ["music.126.net".to_string()];
+        for url in [
+            "https://m701.music.126.net/a.mp3",
+            "http://MUSIC.126.net/a.mp3",
+        ] {
+            assert!(proxy_allowed(url, &hosts), "{url}");
+        }
+        for url in [
+            "https://evilmusic.126.net/a.mp3",
+            "https://music.126.net.example.com/a.mp3",
+            "file:///etc/passwd",
+            "ftp://m1.music.126.net/a.mp3",
+            "not a url",
+        ] {
+            assert!(!proxy_allowed(url, &hosts), "{url}");
+        }
+        assert!(!proxy_allowed("https://m701.music.126.net/a.mp3", &[]));
+    }
+
+    /// 测试环境没有 `NEO_METING_AUTH`，受保护接口应直接拒绝
+    #[tokio::test]
+    async fn test_auth_unconfigured() {
+        #[handler]
+        async fn secret() -> &'static str {
+            "secret"
+        }
+        let service = Service::new(Router::new().hoop(require_auth).get(secret));
+        let res = TestClient::get("http://127.0.0.1:5811/")
+            .bearer_auth("guess")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+    }
+
+    /// 全局缓存，用单独的 provider 名避免影响其他测试
+    #[tokio::test]
+    async fn test_cache_clear() {
+        use neo_meting::cache::Cache;
+        use serde_json::Value;
+
+        use crate::CACHE;
+
+        for id in ["1", "2"] {
+            CACHE.insert(Cache::key("clear-test", "lrc", id), id.to_string());
+        }
+        let service = Service::new(Router::new().post(cache_clear));
+        let body = TestClient::post("http://127.0.0.1:5811/?provider=clear-test&id=1&method=")
+            .send(&service)
+            .await
+            .take_json::<Value>()
+            .await
+            .unwrap();
+        assert_eq!(body["removed"], 1);
+        assert_eq!(
+            CACHE.get(&Cache::key("clear-test", "lrc", "2")).as_deref(),
+            Some("2")
+        );
+        assert_eq!(CACHE.get(&Cache::key("clear-test", "lrc", "1")), None);
+    }
+
+    #[tokio::test]
+    async fn test_debug_weapi() {
+        use serde_json::Value;
+
+        let service = Service::new(Router::new().post(debug_weapi));
+        let mut res = TestClient::post("http://127.0.0.1:5811/")
+            .json(&json!({ "ids": "[1]", "br": 320000 }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let body = res.take_json::<Value>().await.unwrap();
+        // params 是两层 AES 后的 base64，encSecKey 是 1024 位 RSA 的 hex
+        assert!(!body["params"].as_str().unwrap().is_empty());
+        assert_eq!(body["encSecKey"].as_str().unwrap().len(), 256);
+
+        let res = TestClient::post("http://127.0.0.1:5811/")
+            .raw_form("not json")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_pic_placeholder() {
+        use neo_meting::Error as E;
+
+        let placeholder = Some("https://example.com/cover.png");
+        assert_eq!(crate::pic_placeholder(&E::None, placeholder), placeholder);
+        let no_field = E::NoField(".songs.0.al.picUrl");
+        assert_eq!(crate::pic_placeholder(&no_field, placeholder), placeholder);
+        let server = E::Server("down".to_string());
+        assert_eq!(crate::pic_placeholder(&server, placeholder), None);
+        assert_eq!(crate::pic_placeholder(&E::None, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        #[handler]
+        async fn slow(req: &mut Request) -> &'static str {
+            let ms = req.query::<u64>("ms").unwrap_or_default();
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            "done"
+        }
+        let deadline = Deadline(std::time::Duration::from_millis(50));
+        let service = Service::new(Router::new().hoop(deadline).get(slow));
+        let res = TestClient::get("http://127.0.0.1:5811/?ms=200")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::GATEWAY_TIMEOUT));
+        let mut res = TestClient::get("http://127.0.0.1:5811/?ms=0")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "done");
+    }
+
+    #[tokio::test]
+    async fn test_slow_log() {
+        use std::{
+            io,
+            sync::{Arc, Mutex},
+            time::Duration,
+        };
+
+        use neo_meting::Capability;
+
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[handler]
+        async fn song(req: &mut Request) -> &'static str {
+            if req.param::<&str>("id") == Some("slow") {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            "ok"
+        }
+        let logs = Logs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .with_ansi(false)
+            .finish();
+        // 单线程运行时，handler 都在当前线程上跑
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let slow_log = SlowLog {
+            provider: "netease",
+            capability: Capability::Song,
+            threshold: Some(Duration::from_millis(20)),
+        };
+        let service = Service::new(Router::with_path("song/{id}").hoop(slow_log).get(song));
+        for id in ["fast", "slow"] {
+            TestClient::get(format!("http://127.0.0.1:5811/song/{id}"))
+                .send(&service)
+                .await;
+        }
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("WARN"), "{logs}");
+        assert!(logs.contains("slow request netease/song/slow"), "{logs}");
+        assert!(!logs.contains("netease/song/fast"), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn test_song_empty_songs() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/weapi/v3/song/detail"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "songs": [], "code": 200 })),
+            )
+            .mount(&upstream)
+            .await;
+        let res = TestClient::get("http://127.0.0.1:5811/netease/song/1")
+            .send(&netease_service(&upstream))
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_base_url() {
+        let request = |uri: &str, host: Option<&str>| {
+            let mut req = Request::new();
+            *req.uri_mut() = uri.parse().unwrap();
+            if let Some(host) = host {
+                req.headers_mut().insert(HOST, host.parse().unwrap());
+            }
+            req
+        };
+        for (uri, host, expected) in [
+            (
+                "https://a.example/netease/song/1",
+                None,
+                Some("https://a.example"),
+            ),
+            (
+                "http://a.example:5811/x",
+                Some("b.example"),
+                Some("http://a.example:5811"),
+            ),
+            // 没有 authority 时用 Host 头，scheme 用监听端的协议（这里默认 http）
+            (
+                "/netease/song/1",
+                Some("b.example"),
+                Some("http://b.example"),
+            ),
+            ("/netease/song/1", None, None),
+        ] {
+            assert_eq!(base_url(&request(uri, host)).as_deref(), expected, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sub_urls() {
+        let upstream = MockServer::start().await;
+        let song = json!({ "id": 7, "name": "a", "ar": [{ "name": "x" }] });
+        Mock::given(path("/weapi/v3/song/detail"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "songs": [song], "code": 200 })),
+            )
+            .mount(&upstream)
+            .await;
+        Mock::given(path("/weapi/v6/playlist/detail"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "playlist": { "id": 1, "name": "p", "trackIds": [{ "id": 7 }] },
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let prefixes = [
+            ("http://127.0.0.1:5811", "netease"),
+            ("https://music.example", "netease"),
+            ("http://127.0.0.1:5811", "default"),
+        ];
+        for (host, prefix) in prefixes {
+            let base = format!("{host}/{prefix}");
+            for route in ["song/7", "playlist/1"] {
+                let body = TestClient::get(format!("{base}/{route}"))
+                    .send(&service)
+                    .await
+                    .take_json::<serde_json::Value>()
+                    .await
+                    .unwrap();
+                let song = match &body {
+                    serde_json::Value::Array(songs) => &songs[0],
+                    song => song,
+                };
+                assert_eq!(song["pic"], format!("{base}/pic/7"), "{route}");
+                assert_eq!(song["lrc"], format!("{base}/lrc/7"), "{route}");
+                assert_eq!(song["url"], format!("{base}/url/7"), "{route}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_playlist_ws() {
+        use futures_util::StreamExt;
+        use salvo::{
+            conn::{Acceptor, TcpListener},
+            Listener, Server,
+        };
+        use tokio_tungstenite::tungstenite::Message;
+
+        let upstream = MockServer::start().await;
+        let songs = json!([
+            { "id": 1, "name": "a", "ar": [{ "name": "x" }] },
+            { "id": 2, "name": "b", "ar": [{ "name": "y" }] },
+        ]);
+        Mock::given(path("/weapi/v3/song/detail"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "songs": songs, "code": 200 })),
+            )
+            .mount(&upstream)
+            .await;
+        Mock::given(path("/weapi/v6/playlist/detail"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "playlist": { "id": 1, "name": "p", "trackIds": [{ "id": 1 }, { "id": 2 }] },
+            })))
+            .mount(&upstream)
+            .await;
+        let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+        let addr = acceptor.holdings()[0]
+            .local_addr
+            .clone()
+            .into_std()
+            .unwrap();
+        tokio::spawn(Server::new(acceptor).serve(netease_service(&upstream)));
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!(
+            "ws://{addr}/netease/playlist/1/ws?fields=name"
+        ))
+        .await
+        .unwrap();
+        let mut names = Vec::new();
+        while let Some(Ok(message)) = ws.next().await {
+            match message {
+                Message::Text(text) => {
+                    names.push(serde_json::from_str::<serde_json::Value>(&text).unwrap())
+                }
+                Message::Close(_) => break,
+                _ => (),
+            }
+        }
+        assert_eq!(names, [json!({ "name": "a" }), json!({ "name": "b" })]);
+
+        // 没有 Upgrade 头时是普通的 400
+        let res = TestClient::get(format!("http://{addr}/netease/playlist/1/ws"))
+            .send(&netease_service(&upstream))
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_lrc_content_type() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/weapi/song/lyric"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "lrc": { "lyric": "[00:00.00]歌词" },
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let mut res = TestClient::get("http://127.0.0.1:5811/netease/lrc/904")
+            .send(&service)
+            .await;
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.take_string().await.unwrap(), "[00:00.00]歌词");
+    }
+
+    #[tokio::test]
+    async fn test_lrcs() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/weapi/song/lyric"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "lrc": { "lyric": "[00:00.00]a" },
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let body = TestClient::get("http://127.0.0.1:5811/netease/lrcs")
+            .query("ids", "901, 902,901")
+            .send(&service)
+            .await
+            .take_json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(body, json!({ "901": "[00:00.00]a", "902": "[00:00.00]a" }));
+
+        for ids in [
+            String::new(),
+            "901,abc".to_string(),
+            (0..=super::MAX_BATCH_IDS)
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ] {
+            let res = TestClient::get("http://127.0.0.1:5811/netease/lrcs")
+                .query("ids", ids)
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        }
+        // 只有第一次的两首请求了上游
+        let requests = upstream.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_url_level() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/weapi/song/enhance/player/url/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "data": [{ "code": 200, "url": "http://a.flac", "type": "flac", "br": 999000, "size": 10 }],
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let res = TestClient::get("http://127.0.0.1:5811/netease/url/903")
+            .query("level", "lossless")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FOUND));
+        let header = |name| res.headers().get(name).unwrap().to_str().unwrap();
+        assert_eq!(header("location"), "https://a.flac");
+        assert_eq!(
+            (
+                header("x-meting-type"),
+                header("x-meting-br"),
+                header("x-meting-size")
+            ),
+            ("flac", "999000", "10")
+        );
+
+        let res = TestClient::get("http://127.0.0.1:5811/netease/url/903")
+            .query("level", "best")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_search_empty_keyword() {
+        let upstream = MockServer::start().await;
+        let service = netease_service(&upstream);
+        for keyword in ["", "%20", "%20%09%20"] {
+            let res = TestClient::get(format!("http://127.0.0.1:5811/netease/search/{keyword}"))
+                .send(&service)
+                .await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "{keyword:?}"
+            );
+        }
+        assert!(upstream.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_songs_dedup() {
+        let upstream = MockServer::start().await;
+        // 上游打乱顺序返回
+        Mock::given(path("/weapi/v3/song/detail"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 2, "name": "b", "ar": [] },
+                    { "id": 1, "name": "a", "ar": [] },
+                ],
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let body = TestClient::get("http://127.0.0.1:5811/netease/songs")
+            .query("ids", "2,1,2, 1")
+            .query("fields", "name")
+            .send(&service)
+            .await
+            .take_json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(body, json!([{ "name": "b" }, { "name": "a" }]));
+        // 上游只请求了去重后的 id
+        let requests = upstream.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+
+        // 重复的 id 不占数量上限
+        let ids = vec!["1"; super::MAX_BATCH_IDS + 1].join(",");
+        let res = TestClient::get("http://127.0.0.1:5811/netease/songs")
+            .query("ids", ids)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+
+        // 不合法的 id 是 400，且不请求上游
+        let mut res = TestClient::get("http://127.0.0.1:5811/netease/songs")
+            .query("ids", "1,abc")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+        assert!(res.take_string().await.unwrap().contains(r#""abc""#));
+        let requests = upstream.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+    }
+
+    /// 混合纯 id 和分享链接，结果按原样的输入对应
+    #[tokio::test]
+    async fn test_post_songs() {
+        let upstream = MockServer::start().await;
+        Mock::given(path("/weapi/v3/song/detail"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 2, "name": "b", "ar": [] },
+                    { "id": 1, "name": "a", "ar": [] },
+                ],
+            })))
+            .mount(&upstream)
+            .await;
+        let service = netease_service(&upstream);
+        let link = "https://music.163.com/#/song?id=2";
+        let body = TestClient::post("http://127.0.0.1:5811/netease/songs?fields=name")
+            .json(&json!({ "ids": ["1", link, "https://music.163.com/song?id=3", "abc"] }))
+            .send(&service)
+            .await
+            .take_json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "1": { "name": "a" },
+                link: { "name": "b" },
+                "https://music.163.com/song?id=3": null,
+                "abc": null,
+            })
+        );
+
+        let res = TestClient::post("http://127.0.0.1:5811/netease/songs")
+            .json(&json!({ "ids": [] }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_providers_capabilities() {
+        let netease: Arc<dyn Provider> = Arc::new(Netease::new(Arc::new(Semaphore::new(8))));
+        let service = Service::new(Router::with_path("providers").get(Providers(vec![netease])));
+        let body = TestClient::get("http://127.0.0.1:5811/providers")
+            .send(&service)
+            .await
+            .take_json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(body[0]["name"], "netease");
+        let capabilities = body[0]["capabilities"].as_array().unwrap();
+        assert!(capabilities.contains(&json!("search")));
+        assert!(!capabilities.contains(&json!("artist")));
+    }
+
+    #[tokio::test]
+    async fn test_region_scope() {
+        #[handler]
+        async fn region() -> String {
+            neo_meting::current_region().unwrap_or_default()
+        }
+        let service = Service::new(Router::new().hoop(region_scope).get(region));
+        let body = TestClient::get("http://127.0.0.1:5811/?region=hk")
+            .send(&service)
+            .await
+            .take_string()
+            .await
+            .unwrap();
+        assert_eq!(body, "hk");
+    }
+
+    /// 没有配置 `NEO_METING_AUTH` 时 `?ip=` 一律拒绝，不带时不影响
+    #[tokio::test]
+    async fn test_ip_scope_requires_auth() {
+        #[handler]
+        async fn real_ip() -> String {
+            neo_meting::current_real_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_default()
+        }
+        let service = Service::new(Router::new().hoop(ip_scope).get(real_ip));
+        let res = TestClient::get("http://127.0.0.1:5811/?ip=1.2.3.4")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+        let mut res = TestClient::get("http://127.0.0.1:5811/")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "");
+    }
+
+    /// 根路径下挂的固定接口都要登记，否则同名的 `--provider-sets` 前缀会把它们挡住
+    #[test]
+    fn test_top_level_routes() {
+        for path in [
+            "help",
+            "favicon.ico",
+            "stats",
+            "providers",
+            "search/{**keyword}",
+            "url/multi",
+            "cache/clear",
+            "cache/warm",
+            "debug/weapi",
+        ] {
+            let first = path.split('/').next().unwrap();
+            assert!(crate::TOP_LEVEL_ROUTES.contains(&first), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_capability() {
+        let upstream = MockServer::start().await;
+        let mut res = TestClient::get("http://127.0.0.1:5811/netease/artist/1")
+            .send(&netease_service(&upstream))
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        assert!(res
+            .take_string()
+            .await
+            .unwrap()
+            .contains("netease does not support artist"));
+    }
+
+    /// 每条路由都挂到了对应的 handler：状态码和响应结构符合预期
+    #[tokio::test]
+    async fn test_router_wiring() {
+        use serde_json::Value;
+
+        let upstream = MockServer::start().await;
+        let song = json!({ "id": 905, "name": "a", "ar": [{ "name": "x" }], "al": { "picUrl": "http://p1.music.126.net/a.jpg" } });
+        let file = json!({ "code": 200, "url": "http://m.example/a.mp3", "br": 320000, "fee": 8 });
+        let playlist =
+            json!({ "id": 9, "name": "p", "trackCount": 1, "trackIds": [{ "id": 905 }] });
+        let upstream_bodies = [
+            (
+                "/weapi/v3/song/detail",
+                json!({ "code": 200, "songs": [song] }),
+            ),
+            (
+                "/weapi/song/enhance/player/url",
+                json!({ "code": 200, "data": [file] }),
+            ),
+            (
+                "/weapi/song/lyric",
+                json!({ "code": 200, "lrc": { "lyric": "[00:00.00]a" } }),
+            ),
+            (
+                "/weapi/v6/playlist/detail",
+                json!({ "code": 200, "playlist": playlist }),
+            ),
+            (
+                "/weapi/cloudsearch/pc",
+                json!({ "code": 200, "result": { "songs": [song] } }),
+            ),
+            (
+                "/weapi/v1/discovery/simiSong",
+                json!({ "code": 200, "songs": [song] }),
+            ),
+            (
+                "/weapi/user/playlist",
+                json!({ "code": 200, "playlist": [playlist] }),
+            ),
+            ("/weapi/toplist", json!({ "code": 200, "list": [playlist] })),
+            (
+                "/weapi/personalized/playlist",
+                json!({ "code": 200, "result": [{ "id": 9, "name": "p", "playCount": 7 }] }),
+            ),
+            (
+                "/weapi/v1/resource/comments/R_SO_4_905",
+                json!({ "code": 200, "total": 3, "hotComments": [] }),
+            ),
+            (
+                "/weapi/v1/discovery/new/songs",
+                json!({ "code": 200, "data": [song] }),
+            ),
+            (
+                "/weapi/v1/album/3",
+                json!({ "code": 200, "album": { "id": 3 }, "songs": [song] }),
+            ),
+            (
+                "/weapi/artist/albums/1",
+                json!({ "code": 200, "hotAlbums": [] }),
+            ),
+            (
+                "/weapi/artist/mvs",
+                json!({ "code": 200, "mvs": [{ "id": 5, "name": "m", "duration": 1000 }] }),
+            ),
+            (
+                "/weapi/search/suggest/web",
+                json!({ "code": 200, "result": { "songs": [{ "id": 905, "name": "a" }] } }),
+            ),
+        ];
+        for (route, body) in upstream_bodies {
+            Mock::given(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_json(body))
+                .mount(&upstream)
+                .await;
+        }
+        let service = netease_service(&upstream);
+
+        type Check = fn(&Value) -> bool;
+        let routes: [(&str, StatusCode, Check); 30] = [
+            ("pic/905", StatusCode::FOUND, |_| true),
+            ("url/905", StatusCode::FOUND, |_| true),
+            ("lrc/905", StatusCode::OK, |_| true),
+            ("lrcs?ids=905", StatusCode::OK, |body| {
+                body["905"] == "[00:00.00]a"
+            }),
+            ("lyric/905", StatusCode::OK, |body| {
+                body["lrc"] == "[00:00.00]a"
+            }),
+            ("comments/905", StatusCode::OK, |body| body["total"] == 3),
+            ("check/905", StatusCode::OK, |body| body["playable"] == true),
+            ("song/905", StatusCode::OK, |body| {
+                body["artist"] == "x" && body["url"] == "http://127.0.0.1:5811/netease/url/905"
+            }),
+            ("songs?ids=905", StatusCode::OK, |body| {
+                body[0]["artist"] == "x"
+            }),
+            ("playlist/9", StatusCode::OK, |body| body[0]["name"] == "a"),
+            ("playlist/9?meta=1", StatusCode::OK, |body| {
+                body["info"]["count"] == 1
+            }),
+            ("playlists?ids=9,9", StatusCode::OK, |body| {
+                body.as_array().unwrap().len() == 1 && body[0]["count"] == 1
+            }),
+            ("playlist/9?details=0", StatusCode::OK, |body| {
+                body[0]["id"] == "905"
+            }),
+            ("search/a", StatusCode::OK, |body| body[0]["name"] == "a"),
+            ("suggest/a", StatusCode::OK, |body| {
+                body["songs"][0]["id"] == "905" && body["albums"] == json!([])
+            }),
+            ("similar/905", StatusCode::OK, |body| body[0]["name"] == "a"),
+            ("user/1/playlists", StatusCode::OK, |body| {
+                body[0]["id"] == "9"
+            }),
+            ("toplist", StatusCode::OK, |body| body[0]["id"] == "9"),
+            ("recommend", StatusCode::OK, |body| {
+                body[0]["play_count"] == 7
+            }),
+            ("artist/1/albums", StatusCode::OK, |body| body == &json!([])),
+            ("artist/1/mvs?limit=10", StatusCode::OK, |body| {
+                body[0]["id"] == "5"
+            }),
+            ("album/3", StatusCode::OK, |body| body[0]["name"] == "a"),
+            ("album/3?enrich=1", StatusCode::OK, |body| {
+                body[0]["url"] == "http://127.0.0.1:5811/netease/url/905"
+            }),
+            ("new?area=jp", StatusCode::OK, |body| body[0]["name"] == "a"),
+            ("new?area=mars", StatusCode::BAD_REQUEST, |_| true),
+            ("song/abc", StatusCode::BAD_REQUEST, |_| true),
+            ("playlist/1%20OR%201/ws", StatusCode::BAD_REQUEST, |_| true),
+            ("daily", StatusCode::FORBIDDEN, |_| true),
+            ("raw/song/905", StatusCode::FORBIDDEN, |_| true),
+            // 关键词不是合法 id，也要先走鉴权而不是 400
+            ("raw/search/hello%20world", StatusCode::FORBIDDEN, |_| true),
+        ];
+        for (route, status, check) in routes {
+            let mut res = TestClient::get(format!("http://127.0.0.1:5811/netease/{route}"))
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(status), "{route}");
+            let body = res.take_string().await.unwrap();
+            if status == StatusCode::OK && !route.starts_with("lrc/") {
+                let body = serde_json::from_str::<Value>(&body).unwrap();
+                assert!(check(&body), "{route}: {body}");
+            }
+        }
+    }
 }
@@ -1,37 +1,53 @@
 use std::{
-    collections::HashMap,
-    fmt::{Display, Write},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    future::Future,
+    net::Ipv4Addr,
     string::FromUtf8Error,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use base64::{prelude::BASE64_STANDARD, Engine};
-use openssl::{
-    error::ErrorStack,
-    rsa::{Padding, Rsa},
-    symm::{encrypt, Cipher},
-};
 use rand::fill;
 use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    Client, ClientBuilder,
+    header::{HeaderMap, HeaderValue, RETRY_AFTER, SET_COOKIE},
+    tls, Certificate, Client, ClientBuilder, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::sync::{AcquireError, Semaphore};
+use tokio::{
+    sync::{AcquireError, RwLock, Semaphore, SemaphorePermit},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
 
-#[cfg(feature = "random-ip")]
-use rand::Rng;
+use crate::{
+    crypto::{self, CryptoError},
+    current_real_ip, current_region, Capability, Error, LatencyHistogram, MetingAlbum, MetingApi,
+    MetingArtist, MetingCheck, MetingComments, MetingLrcMeta, MetingLyrics, MetingMv,
+    MetingPlaylist, MetingRecommend, MetingSearchOptions, MetingSong, MetingStats, MetingSuggest,
+    MetingTrack, MetingUrl, RetryBudget, Then,
+};
 
-use crate::{Error, MetingApi, MetingSearchOptions, MetingSong, Then};
+mod resp;
+
+use resp::{
+    AccountResp, AlbumResp, ArtistMvsResp, CommentsResp, DailyResp, LikedResp, LyricUser,
+    NewSongsResp, PersonalizedResp, PlaylistResp, SearchResp, SigninResp, SongsResp, SuggestResp,
+    ToplistResp, UserPlaylistResp,
+};
 
 #[derive(Debug)]
 pub enum ParseErr {
-    ImportPubKey(ErrorStack),
-    EncodeSource(ErrorStack),
+    ImportPubKey(CryptoError),
+    EncodeSource(CryptoError),
     EncodeRevStr(FromUtf8Error),
-    EncodeData(ErrorStack),
-    EncodeKey(ErrorStack),
+    EncodeData(CryptoError),
+    EncodeKey(CryptoError),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -43,33 +59,34 @@ pub struct WeapiEncoder {
 
 impl WeapiEncoder {
     pub fn try_from_str(input: &str) -> Result<Self, ParseErr> {
-        let iv = b"0102030405060708";
-        // let mut body = Vec::new();
-        let cbc = Cipher::aes_128_cbc();
-        let mut full_skey = [0u8; 128];
-        let skey = &mut full_skey[..16];
-        fill(skey);
+        let mut skey = [0u8; 16];
+        fill(&mut skey);
         let base62 = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
         skey.iter_mut()
             .for_each(|index| *index = base62[(*index % 62u8) as usize]);
+        Self::with_secret_key(input, &skey)
+    }
 
+    /// 用指定的 16 字节 skey 加密，同样的输入输出固定
+    fn with_secret_key(input: &str, skey: &[u8; 16]) -> Result<Self, ParseErr> {
+        let iv = b"0102030405060708";
         let params = input
             .as_bytes()
-            .then(|source| encrypt(cbc, b"0CoJUm6Qyw8W8jud", Some(iv), source))
+            .then(|source| crypto::aes_128_cbc(b"0CoJUm6Qyw8W8jud", iv, source))
             .map_err(ParseErr::EncodeSource)?
             .then(|data| BASE64_STANDARD.encode(data))
             .as_bytes()
-            .then(|data| encrypt(cbc, skey, Some(iv), data))
+            .then(|data| crypto::aes_128_cbc(skey, iv, data))
             .map_err(ParseErr::EncodeData)?
             .then(|output| BASE64_STANDARD.encode(output));
+        let mut full_skey = [0u8; 128];
+        full_skey[..16].copy_from_slice(skey);
         full_skey.reverse();
-        let rsa = Rsa::public_key_from_pem(include_bytes!("cert/netease.pub"))
+        let rsa = crypto::import_public_key(include_bytes!("cert/netease.pub"))
             .map_err(ParseErr::ImportPubKey)?;
-        let mut enc_sec_key = vec![0; rsa.size() as usize];
-        full_skey
-            .then(|i| rsa.public_encrypt(&i, &mut enc_sec_key, Padding::NONE))
-            .map_err(ParseErr::EncodeKey)?;
-        let enc_sec_key = hex::encode(enc_sec_key);
+        let enc_sec_key = crypto::rsa_no_padding(&rsa, &full_skey)
+            .map_err(ParseErr::EncodeKey)?
+            .then(hex::encode);
         Ok(Self {
             params,
             enc_sec_key,
@@ -77,6 +94,10 @@ impl WeapiEncoder {
     }
 }
 
+/// 歌单详情的请求体
+///
+/// 字段都按字符串发送（`total` 是 Python 风格的 `"True"`），和网页端抓到的请求一致，
+/// 改成数字 / bool 没有在上游验证过，格式由 `test_playlist_req` 固定
 #[derive(Debug, Serialize, Deserialize)]
 struct Playlist<'a> {
     id: &'a str,
@@ -152,6 +173,22 @@ impl Display for SongFileReq {
     }
 }
 
+/// `song/enhance/player/url/v1`，按等级而不是码率取地址
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SongFileV1Req<'a> {
+    /// `[id]` 形式的字符串
+    ids: String,
+    level: &'a str,
+    encode_type: &'a str,
+}
+
+impl Display for SongFileV1Req<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct LrcReq<'a> {
     id: &'a str,
@@ -219,16 +256,256 @@ impl Display for SearchReq<'_> {
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UserPlaylistReq<'a> {
+    uid: &'a str,
+    limit: usize,
+    offset: usize,
+    include_video: bool,
+}
+
+impl<'a> UserPlaylistReq<'a> {
+    pub(crate) fn new(uid: &'a str) -> Self {
+        Self {
+            uid,
+            limit: 1000,
+            offset: 0,
+            include_video: true,
+        }
+    }
+}
+
+impl Display for UserPlaylistReq<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ArtistAlbumsReq {
+    limit: usize,
+    offset: usize,
+    total: bool,
+}
+
+impl ArtistAlbumsReq {
+    pub(crate) fn new(limit: usize, offset: usize) -> Self {
+        Self {
+            limit,
+            offset,
+            total: true,
+        }
+    }
+}
+
+impl Display for ArtistAlbumsReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtistMvsReq {
+    artist_id: u64,
+    limit: usize,
+    offset: usize,
+    total: bool,
+}
+
+impl Display for ArtistMvsReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SimilarReq<'a> {
+    songid: &'a str,
+    limit: usize,
+    offset: usize,
+}
+
+impl<'a> SimilarReq<'a> {
+    pub(crate) fn new(songid: &'a str) -> Self {
+        Self {
+            songid,
+            limit: SIMILAR_LIMIT,
+            offset: 0,
+        }
+    }
+}
+
+impl Display for SimilarReq<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
 #[derive(Debug)]
 pub enum ReqError {
     Limit(AcquireError),
+    /// 排队等许可超时
+    Busy(Duration),
     Req(reqwest::Error),
+    /// 响应是合法的 JSON，但结构不是调用方要的，如顶层是数组或数字
+    Shape {
+        root: &'static str,
+        msg: String,
+    },
+    /// 上游限流：HTTP 429 或 `code` 为 [`RATE_LIMIT_CODE`]，带秒数的 `Retry-After` 时为要求等待的时间
+    RateLimited(Option<Duration>),
+}
+
+impl ReqError {
+    /// 重试前上游要求等待的时间，见 [`crate::retry_with`]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ReqError::RateLimited(after) => *after,
+            _ => None,
+        }
+    }
+}
+
+impl From<ReqError> for Error {
+    fn from(e: ReqError) -> Self {
+        match e {
+            ReqError::Limit(_) | ReqError::Busy(_) | ReqError::RateLimited(_) => {
+                Error::Server(format!("{e:?}"))
+            }
+            // 连不上（含连接超时）是上游不可用，连上之后超时是上游太慢
+            ReqError::Req(ref req) if req.is_connect() => Error::Server(format!("{e:?}")),
+            ReqError::Req(ref req) if req.is_timeout() => Error::Timeout(format!("{e:?}")),
+            ReqError::Req(ref req) if req.is_decode() => Error::Decode(format!("{e:?}")),
+            ReqError::Req(_) => Error::Remote(format!("{e:?}")),
+            ReqError::Shape { root, msg } => {
+                Error::Decode(format!("unexpected response ({root} at top level): {msg}"))
+            }
+        }
+    }
+}
+
+/// 默认排队等待时间，超时返回 [`ReqError::Busy`]
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 上游 http 客户端的连接参数
+///
+/// 连接池默认值与 reqwest 一致：每个 host 不限空闲连接数、空闲 90 秒回收、靠 ALPN 协商 http2。
+/// `http2_prior_knowledge` 会跳过协商直接用 http2，只在确认上游支持时开启。
+///
+/// `connect_timeout` 只管 DNS + TCP + TLS，默认 5 秒，上游不可达时尽快失败；
+/// `request_timeout` 管整个请求，默认不限。
+///
+/// `root_certs` 不为空时只信任这些 CA（即固定上游证书链），不再使用系统内置的根证书
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: Option<Duration>,
+    pub http2_prior_knowledge: bool,
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    pub min_tls_version: Option<tls::Version>,
+    pub root_certs: Vec<Certificate>,
+}
+
+/// 默认一个歌单同时 spawn 的分批请求任务数
+pub const DEFAULT_PLAYLIST_TASKS: usize = 16;
+
+/// 默认连接超时
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            http2_prior_knowledge: false,
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            request_timeout: None,
+            min_tls_version: None,
+            root_certs: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Netease {
     client: Client,
     counter: Arc<Semaphore>,
+    /// 会随匿名 token 刷新变化的请求头
+    cookie: Arc<RwLock<HeaderMap>>,
+    /// 正在等许可的请求数
+    waiting: Arc<AtomicUsize>,
+    /// 按接口常量记录的上游延迟，key 只会是代码里的常量，数量有限
+    latency: Arc<std::sync::Mutex<BTreeMap<&'static str, LatencyHistogram>>>,
+    queue_timeout: Option<Duration>,
+    /// 一个歌单同时 spawn 的分批请求任务数，0 为不限
+    playlist_tasks: usize,
+    retry_permit: RetryPermit,
+    base: Arc<str>,
+    /// 替换 `p*.music.126.net` 的图片镜像
+    pic_cdn: Option<Arc<str>>,
+    /// 见 [`Netease::with_source`]
+    source: Option<Arc<str>>,
+    /// 默认地区，决定 `X-Real-IP`
+    region: Option<Arc<str>>,
+    /// 配置了登录 Cookie，此时不再注册匿名账号
+    logged_in: bool,
+    /// 没有指定地区时的 `X-Real-IP`
+    #[cfg(feature = "random-ip")]
+    random_ip: RandomIp,
+}
+
+/// 歌单分批请求重试时怎么处理并发许可，对应 `NEO_METING_RETRY_PERMIT`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetryPermit {
+    /// 每次尝试单独获取，退避等待期间归还。更公平，但重试中的批次要和其他请求重新排队
+    #[default]
+    Release,
+    /// 第一次尝试前获取，直到成功或放弃才归还。重试不用排队，但上游出问题时
+    /// 许可全被等待重试的批次占着，其他请求只能等到排队超时
+    Hold,
+}
+
+/// `release` / `hold`
+impl std::str::FromStr for RetryPermit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "release" => Ok(RetryPermit::Release),
+            "hold" => Ok(RetryPermit::Hold),
+            other => Err(format!("expected release or hold, got {other:?}")),
+        }
+    }
+}
+
+/// 没有指定地区时 `X-Real-IP` 怎么填，对应 `NEO_METING_RANDOM_IP`
+#[cfg(feature = "random-ip")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RandomIp {
+    /// 不带 `X-Real-IP`
+    Off,
+    /// 每次请求随机一个国内 ip
+    #[default]
+    Random,
+    /// 固定的 ip
+    Fixed(std::net::IpAddr),
+}
+
+/// `off` / `0`、`random` / `1`，或者一个 ip
+#[cfg(feature = "random-ip")]
+impl std::str::FromStr for RandomIp {
+    type Err = std::net::AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "0" | "false" => Ok(RandomIp::Off),
+            "random" | "1" | "true" => Ok(RandomIp::Random),
+            ip => ip.parse().map(RandomIp::Fixed),
+        }
+    }
 }
 
 #[cfg(feature = "random-ip")]
@@ -237,7 +514,7 @@ pub struct IpStr(String);
 #[cfg(feature = "random-ip")]
 impl IpStr {
     pub fn random_chinese_ip() -> Self {
-        IpStr::from(rand::rng().random_range(1884815360..1884890111))
+        IpStr::from(rand::random_range(1884815360..1884890111))
     }
 }
 
@@ -280,117 +557,256 @@ mod test_ip_str {
         let ip = IpStr::from(1884815360);
         assert_eq!(ip.0, "112.88.0.0");
     }
+
+    #[test]
+    fn test_random_ip_from_str() {
+        use crate::netease::RandomIp;
+
+        assert_eq!("off".parse(), Ok(RandomIp::Off));
+        assert_eq!("1".parse(), Ok(RandomIp::Random));
+        assert_eq!("1.2.3.4".parse(), Ok(RandomIp::Fixed([1, 2, 3, 4].into())));
+        assert!("nope".parse::<RandomIp>().is_err());
+    }
+}
+
+/// 离开时把排队数减回去，排队中途被外层超时取消也一样
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl Netease {
+    /// 默认参数构建客户端，和 `reqwest::Client::new` 一样失败时 panic（TLS 后端无法初始化）
     pub fn new(counter: Arc<Semaphore>) -> Netease {
+        Self::with_client_options(counter, ClientOptions::default())
+            .expect("build http client with default options")
+    }
+
+    pub fn with_client_options(
+        counter: Arc<Semaphore>,
+        options: ClientOptions,
+    ) -> Result<Netease, reqwest::Error> {
         let headers = HeaderMap::new().change_self(|hm|{
             hm.append("Referer" ,HeaderValue::from_static( "https://music.163.com/"));
-            hm.append("Cookie" ,HeaderValue::from_static("appver=8.2.30; os=iPhone OS; osver=15.0; EVNSM=1.0.0; buildver=2206; channel=distribution; machineid=iPhone13.3"));
             hm.append("User-Agent" ,HeaderValue::from_static("Mozilla/5.0 (iPhone; CPU iPhone OS 15_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148 CloudMusic/0.1.1 NeteaseMusic/8.2.30"));
             hm.append("Accept" , HeaderValue::from_static("*/*"));
             hm.append("Accept-Language" , HeaderValue::from_static("zh-CN,zh;q=0.8,gl;q=0.6,zh-TW;q=0.4"));
             hm.append("Connection" , HeaderValue::from_static("keep-alive"));
             hm.append("Content-Type" , HeaderValue::from_static("application/x-www-form-urlencoded"));
         });
-        let client = unsafe {
-            ClientBuilder::new()
-                .default_headers(headers)
-                .build()
-                .unwrap_unchecked()
+        let client = ClientBuilder::new()
+            .default_headers(headers)
+            .pool_max_idle_per_host(options.pool_max_idle_per_host)
+            .pool_idle_timeout(options.pool_idle_timeout)
+            .then(|builder| match options.http2_prior_knowledge {
+                true => builder.http2_prior_knowledge(),
+                false => builder,
+            })
+            .then(|builder| match options.connect_timeout {
+                Some(timeout) => builder.connect_timeout(timeout),
+                None => builder,
+            })
+            .then(|builder| match options.request_timeout {
+                Some(timeout) => builder.timeout(timeout),
+                None => builder,
+            })
+            .then(|builder| match options.min_tls_version {
+                Some(version) => builder.tls_version_min(version),
+                None => builder,
+            })
+            .then(|builder| match options.root_certs.is_empty() {
+                true => builder,
+                false => builder.tls_certs_only(options.root_certs),
+            })
+            .build()?;
+        let cookie = HeaderMap::new().change_self(|hm| {
+            hm.append("Cookie", HeaderValue::from_static(BASE_COOKIE));
+        });
+        Self {
+            client,
+            counter,
+            cookie: Arc::new(RwLock::new(cookie)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            latency: Arc::default(),
+            queue_timeout: Some(DEFAULT_QUEUE_TIMEOUT),
+            playlist_tasks: DEFAULT_PLAYLIST_TASKS,
+            retry_permit: RetryPermit::default(),
+            base: Arc::from(BASE_URL),
+            pic_cdn: None,
+            source: None,
+            region: None,
+            logged_in: false,
+            #[cfg(feature = "random-ip")]
+            random_ip: RandomIp::default(),
+        }
+        .then(Ok)
+    }
+
+    #[cfg(feature = "random-ip")]
+    pub fn with_random_ip(self, random_ip: RandomIp) -> Self {
+        self.change_self(|this| this.random_ip = random_ip)
+    }
+
+    /// 用登录后的 Cookie（至少包含 `MUSIC_U=...`）请求，每日推荐等接口需要
+    pub fn with_login_cookie(self, cookie: Option<&str>) -> Self {
+        let Some(cookie) = cookie
+            .map(str::trim)
+            .filter(|cookie| !cookie.is_empty())
+            .and_then(|cookie| HeaderValue::from_str(&format!("{BASE_COOKIE}; {cookie}")).ok())
+        else {
+            return self;
         };
-        Self { client, counter }
+        self.change_self(|this| {
+            this.cookie = HeaderMap::new()
+                .change_self(|hm| {
+                    hm.append("Cookie", cookie);
+                })
+                .then(RwLock::new)
+                .then(Arc::new);
+            this.logged_in = true;
+        })
     }
 
-    pub async fn exec<Output: for<'a> Deserialize<'a>>(
-        &self,
-        url: &str,
-        data: WeapiEncoder,
-    ) -> Result<Output, ReqError> {
-        let _limit = self.counter.acquire().await.map_err(ReqError::Limit)?;
-        self.client
-            .post(url)
-            .form(&data)
-            .then(|req| {
-                #[cfg(feature = "random-ip")]
-                return req.header("X-Real-IP", IpStr::random_chinese_ip().as_ref());
-                #[cfg(not(feature = "random-ip"))]
-                return req;
-            })
-            .send()
-            .await
-            .map_err(ReqError::Req)?
-            .json()
-            .await
-            .map_err(ReqError::Req)
+    pub fn logged_in(&self) -> bool {
+        self.logged_in
+    }
+
+    /// 图片地址的镜像 host，如 `https://img.example.com`，不带协议时用 https
+    pub fn with_pic_cdn(self, cdn: Option<&str>) -> Self {
+        self.change_self(|this| {
+            this.pic_cdn = cdn
+                .map(|cdn| cdn.trim_end_matches('/'))
+                .filter(|cdn| !cdn.is_empty())
+                .map(|cdn| match cdn.contains("://") {
+                    true => Arc::from(cdn),
+                    false => Arc::from(format!("https://{cdn}")),
+                })
+        })
     }
-}
 
-const GET_ID_NAME_PIC_ARTIST_ERR_MSG: &str = "
-.id as u64
-| .name as str
-| .al.pic_str as str / .al.pic as u64
-| .ar as array
-";
+    /// 汇总接口里的来源标签，如 `mirror1:netease`，不设置时为 `netease`
+    pub fn with_source(self, source: Option<&str>) -> Self {
+        self.change_self(|this| {
+            this.source = source.filter(|source| !source.is_empty()).map(Arc::from)
+        })
+    }
 
-/// # 获取 songs 对象的 id、名称、图片 id、艺术家（们）
-///
-/// ## None:
-///
-/// - .id as u64
-/// - .name as str
-/// - .ar as array
-fn get_id_name_artist(input: &Value) -> Option<(String, String, String)> {
-    let id = input.get("id")?.as_u64()?.to_string();
-    let name = input.get("name")?.as_str()?.to_string();
-    let artist = input
-        .get("ar")?
-        .as_array()?
-        .iter()
-        .filter_map(|x| x.get("name")?.as_str())
-        .enumerate()
-        .fold(String::new(), |mut acc, (index, now)| {
-            if index != 0 {
-                let _ = write!(acc, "/{now}");
-                return acc;
-            }
-            now.to_string()
-        });
-    Some((id, name, artist))
-}
+    /// 没有 `?region=` 时使用的地区，见 [`REGION_IPS`]
+    pub fn with_region(self, region: Option<&str>) -> Self {
+        self.change_self(|this| {
+            this.region = region.filter(|region| !region.is_empty()).map(Arc::from)
+        })
+    }
+
+    /// 等待许可的最长时间，`None` 为一直等
+    pub fn with_queue_timeout(self, timeout: Option<Duration>) -> Self {
+        self.change_self(|this| this.queue_timeout = timeout)
+    }
 
-const PLAYLIST_URL: &str = "https://music.163.com/weapi/v6/playlist/detail";
-const SONG_INFO_URL: &str = "https://music.163.com/weapi/v3/song/detail";
-const SONG_URL: &str = "https://music.163.com/weapi/song/enhance/player/url";
-const LRC_URL: &str = "https://music.163.com/weapi/song/lyric";
-const SEARCH_URL: &str = "https://music.163.com/weapi/cloudsearch/pc";
+    /// 一个歌单同时 spawn 的分批请求任务数，0 为不限
+    pub fn with_playlist_tasks(self, limit: usize) -> Self {
+        self.change_self(|this| this.playlist_tasks = limit)
+    }
 
-const MUSIC_QUALITY: u64 = 320 * 1000;
-const ITEM_PRE_REQUEST: usize = 512;
-const ENCODER_NAME: &str = "netease";
+    /// 歌单分批请求重试期间是否一直占着许可，见 [`RetryPermit`]
+    pub fn with_retry_permit(self, retry_permit: RetryPermit) -> Self {
+        self.change_self(|this| this.retry_permit = retry_permit)
+    }
 
-impl MetingApi for Netease {
-    fn name() -> &'static str {
-        "netease"
+    /// 替换上游地址，主要给测试和 bench 指向本地 mock 用
+    pub fn with_base_url(self, base: &str) -> Self {
+        self.change_self(|this| this.base = Arc::from(base.trim_end_matches('/')))
     }
 
-    async fn url(&self, id: &str) -> Result<String, Error> {
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>, ReqError> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let _waiting = Waiting(&self.waiting);
+        match self.queue_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.counter.acquire())
+                .await
+                .map_err(|_| ReqError::Busy(timeout))
+                .and_then(|permit| permit.map_err(ReqError::Limit)),
+            None => self.counter.acquire().await.map_err(ReqError::Limit),
+        }
+    }
+
+    /// 调试用的 `?ip=` 优先，然后是请求的 `?region=`、默认地区，都没有时按 [`RandomIp`]（需要 `random-ip` feature）
+    fn real_ip(&self) -> Option<String> {
+        if let Some(ip) = current_real_ip() {
+            return Some(ip.to_string());
+        }
+        let region = current_region().or_else(|| self.region.as_deref().map(str::to_string));
+        if let Some(ip) = region.as_deref().and_then(region_ip) {
+            return Some(ip);
+        }
+        #[cfg(feature = "random-ip")]
+        return match &self.random_ip {
+            RandomIp::Off => None,
+            RandomIp::Random => Some(IpStr::random_chinese_ip().into()),
+            RandomIp::Fixed(ip) => Some(ip.to_string()),
+        };
+        #[cfg(not(feature = "random-ip"))]
+        return None;
+    }
+
+    async fn send(&self, url: &str, data: WeapiEncoder) -> Result<Response, ReqError> {
+        let _limit = self.acquire().await?;
+        self.send_held(url, data).await
+    }
+
+    /// 不获取许可直接发送，调用方已经持有许可
+    async fn send_held(&self, url: &str, data: WeapiEncoder) -> Result<Response, ReqError> {
+        self.client
+            .post(format!("{}{url}", self.base))
+            .headers(self.cookie.read().await.clone())
+            .form(&data)
+            .then(|req| match self.real_ip() {
+                Some(ip) => req.header("X-Real-IP", ip),
+                None => req,
+            })
+            .send()
+            .await
+            .map_err(ReqError::Req)
+    }
+
+    /// `song/enhance/player/url` 返回的 `data[0]`
+    async fn song_file(&self, id: &str) -> Result<Value, Error> {
         let data = SongFileReq {
             ids: vec![id.to_string()],
             br: MUSIC_QUALITY,
-        }
-        .to_string()
-        .then(|str| WeapiEncoder::try_from_str(&str))
-        .map_err(|e| Error::Encode {
-            engine: ENCODER_NAME,
-            msg: format!("{e:?}"),
-        })?
-        .then(|we_data| async move { self.exec::<HashMap<String, Value>>(SONG_URL, we_data).await })
-        .await
-        .map_err(|e| Error::Remote(format!("{e:?}")))?;
+        };
+        self.player_url(SONG_URL, data.to_string()).await
+    }
+
+    /// `song/enhance/player/url/v1` 返回的 `data[0]`，`level` 见 [`crate::URL_LEVELS`]
+    async fn song_file_v1(&self, id: &str, level: &str) -> Result<Value, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            feild: "<id>",
+            target: "u64",
+        })?;
+        let data = SongFileV1Req {
+            ids: format!("[{id}]"),
+            level,
+            encode_type: "flac",
+        };
+        self.player_url(SONG_URL_V1, data.to_string()).await
+    }
+
+    async fn player_url(&self, url: &'static str, data: String) -> Result<Value, Error> {
+        let data = WeapiEncoder::try_from_str(&data)
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move { self.exec::<HashMap<String, Value>>(url, we_data).await })
+            .await
+            .map_err(Error::from)?;
+        check_login(&data)?;
 
-        let json = data
-            .get("data")
+        data.get("data")
             .ok_or(Error::NoField("data"))?
             .as_array()
             .ok_or(Error::TypeMismatch {
@@ -398,41 +814,30 @@ impl MetingApi for Netease {
                 feild: "data",
             })?
             .first()
-            .ok_or(Error::None)?;
-        json.get("code")
-            .ok_or(Error::NoField("code"))?
-            .as_u64()
-            .ok_or(Error::TypeMismatch {
-                feild: "code",
-                target: "u64",
-            })
-            .and_then(|x| match x {
-                200 => Ok(()),
-                _ => Err(Error::None),
-            })?;
-        json.get("url")
-            .or_else(|| json.get("uf")?.get("url"))
-            .ok_or(Error::NoField("json.url / json.uf.url"))?
-            .as_str()
-            .ok_or(Error::TypeMismatch {
-                target: "str",
-                feild: "json.url / json.uf.url",
-            })?
-            .replace("http://", "https://")
-            .then(Ok)
+            .cloned()
+            .ok_or(Error::None)
     }
 
-    async fn pic(&self, id: &str) -> Result<String, Error> {
-        let hash_map = id
-            .parse::<u64>()
-            .map_err(|_| Error::TypeMismatch {
-                target: "u64",
-                feild: "<id>",
-            })?
-            .then(SongItem::new)
-            .then(|it| [it])
+    /// 配置了 [`Netease::with_pic_cdn`] 时换成镜像 host
+    fn cdn_pic(&self, pic: &str) -> String {
+        match &self.pic_cdn {
+            Some(cdn) => rewrite_pic_host(pic, cdn),
+            None => pic.to_string(),
+        }
+    }
+
+    /// 一次 `song/detail` 请求查多首歌，没查到的不在结果里
+    async fn song_details(&self, ids: &[u64]) -> Result<Vec<resp::Song>, Error> {
+        let resp = ids
+            .iter()
+            .copied()
+            .map(SongItem::new)
+            .collect::<Vec<_>>()
             .then(|its| serde_json::to_string(&its))
-            .unwrap()
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
             .then(SongReq::new)
             .to_string()
             .then(|str| WeapiEncoder::try_from_str(&str))
@@ -440,231 +845,2893 @@ impl MetingApi for Netease {
                 engine: ENCODER_NAME,
                 msg: format!("{e:?}"),
             })?
-            .then(|weapi_data| async move {
-                self.exec::<HashMap<String, Value>>(SONG_INFO_URL, weapi_data)
-                    .await
-            })
+            .then(
+                |weapi_data| async move { self.exec::<SongsResp>(SONG_INFO_URL, weapi_data).await },
+            )
             .await
-            .map_err(|e| Error::Remote(format!("{e:?}")))?;
-        let i = hash_map
-            .get("songs")
-            .ok_or(Error::NoField("songs"))?
-            .as_array()
-            .ok_or(Error::TypeMismatch {
-                target: "array",
-                feild: ".songs",
-            })?;
-        i.first()
-            .map(|item| item.get("al")?.get("picUrl"))
-            .and_then(|x| x)
-            .ok_or(Error::NoField(".songs.0.al.picUrl"))?
-            .as_str()
-            .ok_or(Error::TypeMismatch {
-                target: "str",
-                feild: "songs.0.al.picUrl",
-            })?
-            .to_string()
-            .then(Ok)
-    }
-
-    async fn lrc(&self, id: &str) -> Result<String, Error> {
-        let json =
-            LrcReq::new(id)
-                .to_string()
-                .then(|req| WeapiEncoder::try_from_str(&req))
-                .map_err(|e| Error::Encode {
-                    engine: ENCODER_NAME,
-                    msg: format!("{e:?}"),
-                })?
-                .then(|we_data| async move {
-                    self.exec::<HashMap<String, Value>>(LRC_URL, we_data).await
-                })
-                .await
-                .map_err(|e| Error::Remote(format!("{e:?}")))?;
-        json.get("lrc")
-            .and_then(|lrc| lrc.get("lyric")?.as_str())
-            .unwrap_or("[00:00.00]暂无歌词")
-            .to_string()
-            .then(Ok)
+            .map_err(Error::from)?;
+        Ok(resp.into_songs())
     }
 
-    async fn song(
+    /// `cloudsearch/pc` 的 `.result`，按 `option.r#type` 里面是歌曲或艺术家
+    async fn search_result(
         &self,
-        id: &str,
-        pic: impl Fn(&str) -> String + Send,
-        lrc: impl Fn(&str) -> String + Send,
-        url: impl Fn(&str) -> String + Send,
-    ) -> Result<MetingSong, Error> {
-        let json = id
-            .parse::<u64>()
-            .map_err(|_| Error::TypeMismatch {
-                feild: "<id>",
-                target: "u64",
-            })?
-            .then(SongItem::new)
-            .then(|it| [it])
-            .then(|its| serde_json::to_string(&its))
-            .unwrap()
-            .then(SongReq::new)
+        keyword: &str,
+        option: MetingSearchOptions,
+    ) -> Result<resp::SearchResult, Error> {
+        SearchReq::new(keyword, option)
             .to_string()
-            .then(|str| WeapiEncoder::try_from_str(&str))
+            .then(|req| WeapiEncoder::try_from_str(&req))
             .map_err(|e| Error::Encode {
                 engine: ENCODER_NAME,
                 msg: format!("{e:?}"),
             })?
-            .then(|weapi_data| async move {
-                self.exec::<HashMap<String, Value>>(SONG_INFO_URL, weapi_data)
-                    .await
-            })
+            .then(|we_data| async move { self.exec::<SearchResp>(SEARCH_URL, we_data).await })
             .await
-            .map_err(|e| Error::Remote(format!("{e:?}")))?;
-        let (id, name, artist) = json
-            .get("songs")
-            .ok_or(Error::NoField("songs"))?
-            .as_array()
-            .ok_or(Error::TypeMismatch {
-                feild: "songs",
-                target: "array",
-            })?
-            .first()
-            .ok_or(Error::NoField("songs.[0]"))?
-            .then(get_id_name_artist)
-            .ok_or(Error::NoField(GET_ID_NAME_PIC_ARTIST_ERR_MSG))?;
-        MetingSong {
-            name,
-            artist,
-            url: url(&id),
-            pic: pic(&id),
-            lrc: lrc(&id),
-        }
-        .then(Ok)
+            .map_err(Error::from)?
+            .result
+            .ok_or(Error::NoField(".result"))
     }
 
-    async fn playlist(
-        &self,
-        id: &str,
-        retry: u8,
-        pic: impl Fn(&str) -> String,
-        lrc: impl Fn(&str) -> String,
-        url: impl Fn(&str) -> String,
-    ) -> Result<Vec<MetingSong>, Error> {
+    /// `playlist/detail` 的歌单对象，带 `trackIds`
+    async fn playlist_info(&self, id: &str) -> Result<resp::PlaylistInfo, Error> {
         let data = WeapiEncoder::try_from_str(&Playlist::new(id).to_string()).map_err(|e| {
             Error::Encode {
                 engine: ENCODER_NAME,
                 msg: format!("{e:?}"),
             }
         })?;
-        let (bucket, mut bucket_set) = self
-            .exec::<HashMap<String, Value>>(PLAYLIST_URL, data)
-            .await
-            .map_err(|e| Error::Remote(format!("{e:?}")))?
-            .get("playlist")
-            .and_then(|playlist| playlist.get("trackIds"))
-            .ok_or(Error::NoField(".playlist.trackIds"))?
-            .then(|track_ids| track_ids.as_array())
-            .ok_or(Error::TypeMismatch {
-                feild: ".player.trackIds",
-                target: "array",
-            })?
-            .iter()
-            .filter_map(|track_id| track_id.get("id").and_then(|id| id.as_u64()))
-            .map(SongItem::new)
-            .enumerate()
-            .fold(
-                (Vec::new(), Vec::new()),
-                |(mut bucket, mut bucket_set), (index, now)| {
-                    bucket.push(now);
-                    if index % ITEM_PRE_REQUEST == 0 && index != 0 {
-                        bucket_set.push(bucket);
-                        bucket = Vec::new()
-                    }
-                    (bucket, bucket_set)
-                },
-            );
-        bucket_set.push(bucket);
-        let tasks = bucket_set
-            .iter()
-            .map(|items| serde_json::to_string(items).unwrap())
-            .map(|bucket| SongReq::new(bucket).to_string())
-            .filter_map(|song_req| WeapiEncoder::try_from_str(&song_req).ok())
-            .map(|we_data| {
-                crate::retry(
-                    retry,
-                    (Arc::new(we_data), Arc::new(self.clone())),
-                    |(we_data, this)| async move {
-                        this.exec::<HashMap<String, Value>>(SONG_INFO_URL, we_data.as_ref().clone())
-                            .await
-                    },
-                    |_| (),
-                )
-            })
-            .map(|task| tokio::spawn(task));
-        let mut outputs = Vec::with_capacity(ITEM_PRE_REQUEST);
-        for task in tasks {
-            let Ok(Ok(json)) = task.await else {
-                continue;
-            };
+        let resp = self
+            .exec::<PlaylistResp>(PLAYLIST_URL, data)
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        resp.playlist.ok_or(Error::NoField(".playlist"))
+    }
 
-            json.get("songs")
-                .ok_or(Error::NoField("<song-detal>.songs"))?
-                .as_array()
-                .ok_or(Error::TypeMismatch {
-                    feild: "<song-detal>.songs",
-                    target: "array",
-                })?
-                .iter()
-                .filter_map(get_id_name_artist)
-                .map(|(id, name, artist)| MetingSong {
-                    name,
-                    artist,
-                    url: url(&id),
-                    pic: pic(&id),
-                    lrc: lrc(&id),
-                })
-                .for_each(|song| outputs.push(song));
+    /// 歌单信息和全部曲目，按歌单顺序逐批交给 `sink`，见 [`Netease::songs_in_batches`]
+    async fn playlist_detail(
+        &self,
+        id: &str,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+        sink: impl FnMut(Vec<MetingSong>),
+    ) -> Result<MetingPlaylist, Error> {
+        let playlist = self.playlist_info(id).await?;
+        let ids = playlist.track_ids.iter().map(|track| track.id);
+        let missing = self.songs_in_batches(ids, retry, pic, lrc, url, sink).await;
+        if missing > 0 {
+            warn!("netease playlist {id}: {missing} songs missing");
+            if crate::strict() {
+                return Err(Error::Incomplete { missing });
+            }
         }
-        Ok(outputs)
+        Ok(playlist.into())
     }
 
-    async fn search(
+    /// 按 [`ITEM_PRE_REQUEST`] 分批并发请求歌曲详情，按 `ids` 的顺序逐批交给 `sink`，
+    /// 失败的批次跳过，返回跳过的曲目数
+    async fn songs_in_batches(
         &self,
-        keyword: &str,
-        option: MetingSearchOptions,
+        ids: impl Iterator<Item = u64>,
+        retry: Arc<RetryBudget>,
         pic: impl Fn(&str) -> String,
         lrc: impl Fn(&str) -> String,
         url: impl Fn(&str) -> String,
-    ) -> Result<Vec<MetingSong>, Error> {
-        SearchReq::new(keyword, option)
+        mut sink: impl FnMut(Vec<MetingSong>),
+    ) -> usize {
+        let (bucket, mut bucket_set) = ids.map(SongItem::new).enumerate().fold(
+            (Vec::new(), Vec::new()),
+            |(mut bucket, mut bucket_set), (index, now)| {
+                bucket.push(now);
+                if index % ITEM_PRE_REQUEST == 0 && index != 0 {
+                    bucket_set.push(bucket);
+                    bucket = Vec::new()
+                }
+                (bucket, bucket_set)
+            },
+        );
+        bucket_set.push(bucket);
+        let mut missing = 0;
+        let tasks = bucket_set
+            .iter()
+            .filter_map(|items| {
+                let we_data = serde_json::to_string(items)
+                    .ok()
+                    .map(|bucket| SongReq::new(bucket).to_string())
+                    .and_then(|song_req| WeapiEncoder::try_from_str(&song_req).ok());
+                if we_data.is_none() {
+                    missing += items.len();
+                }
+                we_data.map(|we_data| (items.iter().map(|item| item.id).collect(), we_data))
+            })
+            .collect::<Vec<(Vec<u64>, _)>>()
+            .into_iter()
+            .map(|(ids, we_data)| {
+                let (retry, this) = (retry.clone(), self.clone());
+                async move {
+                    match this
+                        .exec_retry::<SongsResp>(&retry, SONG_INFO_URL, we_data, |_| ())
+                        .await
+                    {
+                        Ok(resp) => Ok((ids, resp)),
+                        Err(_) => Err(ids.len()),
+                    }
+                }
+            });
+        // 按歌单顺序交给 sink，同时在跑的分批任务不超过 `playlist_tasks`
+        spawn_ordered(tasks, self.playlist_tasks, |resp| {
+            let (ids, resp) = match resp {
+                Ok(resp) => resp,
+                Err(len) => {
+                    missing += len;
+                    return;
+                }
+            };
+            // 上游不保证按请求里的顺序返回，批内按 id 重排
+            let order = ids
+                .iter()
+                .enumerate()
+                .map(|(index, id)| (*id, index))
+                .collect::<HashMap<_, _>>();
+            let mut songs = resp.into_songs();
+            songs.sort_by_key(|song| order.get(&song.id).copied().unwrap_or(usize::MAX));
+            songs
+                .into_iter()
+                .map(|song| song.into_meting(&pic, &lrc, &url))
+                .collect::<Vec<_>>()
+                .then(&mut sink);
+        })
+        .await;
+        missing
+    }
+
+    /// 一次请求同时带回 lrc / tlyric / romalrc / yrc
+    async fn lyric_json(&self, id: &str) -> Result<HashMap<String, Value>, Error> {
+        LrcReq::new(id)
             .to_string()
             .then(|req| WeapiEncoder::try_from_str(&req))
             .map_err(|e| Error::Encode {
                 engine: ENCODER_NAME,
                 msg: format!("{e:?}"),
             })?
-            .then(|we_data| async move {
-                self.exec::<HashMap<String, Value>>(SEARCH_URL, we_data)
-                    .await
-            })
+            .then(|we_data| self.exec::<HashMap<String, Value>>(LRC_URL, we_data))
             .await
-            .map_err(|e| Error::Server(format!("{e:?}")))?
-            .get("result")
-            .and_then(|result| result.get("songs"))
-            .ok_or(Error::NoField(".result.songs"))?
-            .as_array()
-            .ok_or(Error::TypeMismatch {
-                feild: ".result.songs",
-                target: "array",
-            })?
+            .map_err(Error::from)
+    }
+
+    pub async fn exec<Output: for<'a> Deserialize<'a>>(
+        &self,
+        url: &'static str,
+        data: WeapiEncoder,
+    ) -> Result<Output, ReqError> {
+        self.exec_at(url, url, data).await
+    }
+
+    /// `url` 后面拼了 id 等参数时，延迟按 `endpoint`（对应的常量）统计
+    pub async fn exec_at<Output: for<'a> Deserialize<'a>>(
+        &self,
+        endpoint: &'static str,
+        url: &str,
+        data: WeapiEncoder,
+    ) -> Result<Output, ReqError> {
+        let _limit = self.acquire().await?;
+        self.exec_held(endpoint, url, data).await
+    }
+
+    /// 不获取许可直接请求并解析，调用方已经持有许可
+    async fn exec_held<Output: for<'a> Deserialize<'a>>(
+        &self,
+        endpoint: &'static str,
+        url: &str,
+        data: WeapiEncoder,
+    ) -> Result<Output, ReqError> {
+        let start = Instant::now();
+        let result = match self.send_held(url, data).await {
+            Ok(resp) => Self::decode(resp).await,
+            Err(e) => Err(e),
+        };
+        self.latency
+            .lock()
+            .unwrap()
+            .entry(endpoint)
+            .or_default()
+            .record(start.elapsed());
+        result
+    }
+
+    /// 失败后在 `retry` 允许的范围内重试的 [`Self::exec`]，重试之间是否归还许可见 [`RetryPermit`]
+    async fn exec_retry<Output: for<'a> Deserialize<'a>>(
+        &self,
+        retry: &RetryBudget,
+        url: &'static str,
+        data: WeapiEncoder,
+        on_error: impl Fn(ReqError),
+    ) -> Result<Output, ReqError> {
+        let wait = ReqError::retry_after;
+        match self.retry_permit {
+            RetryPermit::Release => {
+                let task = |data| self.exec(url, data);
+                crate::retry_with(retry, data, task, on_error, wait).await
+            }
+            RetryPermit::Hold => {
+                let _held = self.acquire().await?;
+                let task = |data| self.exec_held(url, url, data);
+                crate::retry_with(retry, data, task, on_error, wait).await
+            }
+        }
+    }
+
+    async fn decode<Output: for<'a> Deserialize<'a>>(resp: Response) -> Result<Output, ReqError> {
+        if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            // 只认秒数，HTTP 日期格式的按普通退避处理
+            let after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|after| after.to_str().ok()?.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err(ReqError::RateLimited(after));
+        }
+        // 先解析成 `Value`，结构不对时能说清楚是什么，而不是笼统的 decode 错误
+        let json = resp.json::<Value>().await.map_err(ReqError::Req)?;
+        if json.get("code").and_then(Value::as_i64) == Some(RATE_LIMIT_CODE) {
+            return Err(ReqError::RateLimited(None));
+        }
+        let root = json_kind(&json);
+        Output::deserialize(json).map_err(|e| ReqError::Shape {
+            root,
+            msg: e.to_string(),
+        })
+    }
+
+    /// 注册一个新的匿名账号，把拿到的 `MUSIC_A` 拼进之后请求的 Cookie，已登录时什么都不做
+    pub async fn register_anonymous(&self) -> Result<(), Error> {
+        if self.logged_in {
+            return Ok(());
+        }
+        let encode_err = |msg: String| Error::Encode {
+            engine: ENCODER_NAME,
+            msg,
+        };
+        let res = anonymous_username()
+            .map_err(|e| encode_err(format!("{e:?}")))?
+            .then(|username| serde_json::json!({ "username": username }).to_string())
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| encode_err(format!("{e:?}")))?
+            .then(|we_data| self.send(ANONYMOUS_URL, we_data))
+            .await
+            .map_err(Error::from)?;
+        let token = res
+            .headers()
+            .get_all(SET_COOKIE)
             .iter()
-            .filter_map(get_id_name_artist)
-            .map(|(id, name, artist)| MetingSong {
-                name,
-                artist,
-                url: url(&id),
-                pic: pic(&id),
-                lrc: lrc(&id),
-            })
-            .collect::<Vec<MetingSong>>()
-            .then(Ok)
+            .filter_map(|cookie| cookie.to_str().ok()?.split(';').next())
+            .find(|cookie| cookie.starts_with("MUSIC_A="))
+            .ok_or(Error::NoField("Set-Cookie: MUSIC_A"))?
+            .to_string();
+        let cookie = HeaderValue::from_str(&format!("{BASE_COOKIE}; {token}"))
+            .map_err(|e| Error::Remote(format!("{e:?}")))?;
+        self.cookie.write().await.insert("Cookie", cookie);
+        Ok(())
+    }
+
+    /// 登录账号的每日签到，返回拿到的积分，今天已经签过时为 `None`
+    pub async fn daily_signin(&self) -> Result<Option<u64>, Error> {
+        if !self.logged_in {
+            return Err(Error::NeedLogin);
+        }
+        let resp = serde_json::json!({ "type": SIGNIN_TYPE })
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| self.exec::<SigninResp>(SIGNIN_URL, we_data))
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        match resp.code {
+            Some(200) => Ok(Some(resp.point.unwrap_or_default())),
+            Some(SIGNIN_REPEATED_CODE) => Ok(None),
+            code => Err(Error::Remote(format!(
+                "daily signin: code {code:?}, {}",
+                resp.msg.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// 立即签到一次，之后每天一次，失败时只记日志
+    pub fn spawn_daily_signin(&self) -> JoinHandle<()> {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match this.daily_signin().await {
+                    Ok(Some(point)) => info!("netease daily signin: +{point} points"),
+                    Ok(None) => info!("netease daily signin: already signed in today"),
+                    Err(e) => warn!("netease daily signin failed: {e:?}"),
+                }
+                tokio::time::sleep(SIGNIN_INTERVAL).await;
+            }
+        })
+    }
+
+    /// 立即注册一次，之后每隔 `interval` 重新注册，失败时沿用旧 Cookie
+    pub fn spawn_token_refresh(&self, interval: Duration) -> JoinHandle<()> {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match this.register_anonymous().await {
+                    Ok(()) => info!("netease anonymous token refreshed"),
+                    Err(e) => warn!("netease anonymous token refresh failed: {e:?}"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+const ID_XOR_KEY: &[u8] = b"3go8&$8*3*3h0k(2)2";
+
+/// 专辑封面的 pic id 至少 16 位（如 `109951163076136658`），歌曲 id 远小于它
+const MIN_PIC_ID: u64 = 1_000_000_000_000_000;
+
+/// `base64("<device_id> <base64(md5(device_id ^ ID_XOR_KEY))>")`
+fn anonymous_username() -> Result<String, CryptoError> {
+    let mut device_id = [0u8; 26];
+    fill(&mut device_id);
+    let device_id = hex::encode_upper(device_id);
+    let xored = device_id
+        .bytes()
+        .zip(ID_XOR_KEY.iter().cycle())
+        .map(|(byte, key)| byte ^ key)
+        .collect::<Vec<u8>>();
+    crypto::md5(&xored)?
+        .then(|digest| BASE64_STANDARD.encode(digest))
+        .then(|encoded| format!("{device_id} {encoded}"))
+        .then(|username| BASE64_STANDARD.encode(username))
+        .then(Ok)
+}
+
+/// 由 pic id 拼出封面地址：`/<base64(md5(pic_id ^ ID_XOR_KEY))>/<pic_id>.jpg`
+fn album_pic_url(pic_id: u64) -> Result<String, CryptoError> {
+    let pic_id = pic_id.to_string();
+    let xored = pic_id
+        .bytes()
+        .zip(ID_XOR_KEY.iter().cycle())
+        .map(|(byte, key)| byte ^ key)
+        .collect::<Vec<u8>>();
+    crypto::md5(&xored)?
+        .then(|digest| BASE64_STANDARD.encode(digest))
+        .replace('/', "_")
+        .replace('+', "-")
+        .then(|encoded| format!("https://p3.music.126.net/{encoded}/{pic_id}.jpg"))
+        .then(Ok)
+}
+
+/// `.<kind>.lyric`，没有或为空时为 `None`
+fn get_lyric(json: &HashMap<String, Value>, kind: &str) -> Option<String> {
+    json.get(kind)?
+        .get("lyric")?
+        .as_str()
+        .filter(|lyric| !lyric.is_empty())
+        .map(str::to_string)
+}
+
+fn json_kind(json: &Value) -> &'static str {
+    match json {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// 上游的 id 有时是数字，有时是数字字符串
+fn get_id(id: &Value) -> Option<u64> {
+    id.as_u64().or_else(|| id.as_str()?.parse().ok())
+}
+
+const BASE_URL: &str = "https://music.163.com";
+const BASE_COOKIE: &str = "appver=8.2.30; os=iPhone OS; osver=15.0; EVNSM=1.0.0; buildver=2206; channel=distribution; machineid=iPhone13.3";
+/// 顶层 `code` 为 301 / -462 时表示需要登录（或 VIP 验证）
+const NEED_LOGIN_CODES: [i64; 2] = [301, -462];
+/// 请求太频繁，`-462` 需要验证码，算作 [`NEED_LOGIN_CODES`]
+const RATE_LIMIT_CODE: i64 = -460;
+
+fn check_code(code: Option<i64>) -> Result<(), Error> {
+    match code {
+        Some(code) if NEED_LOGIN_CODES.contains(&code) => Err(Error::NeedLogin),
+        _ => Ok(()),
+    }
+}
+
+fn check_login(json: &HashMap<String, Value>) -> Result<(), Error> {
+    check_code(json.get("code").and_then(Value::as_i64))
+}
+
+/// 需要 VIP 的 `fee`：1 为 VIP 歌曲，4 为付费专辑
+const VIP_FEES: [u64; 2] = [1, 4];
+
+/// `song/enhance/player/url` 里 `data[]` 的一项能不能完整播放
+///
+/// `code` 不是 200 的是版权 / 地区不可用，有 `freeTrialInfo` 的只能试听
+fn get_check(item: &Value) -> MetingCheck {
+    let present = |x: Option<&Value>| x.is_some_and(|x| !x.is_null());
+    let br = item.get("br").and_then(Value::as_u64).filter(|br| *br != 0);
+    let reason = match item.get("code").and_then(Value::as_u64) {
+        Some(200) if present(item.get("freeTrialInfo")) => Some("trial"),
+        Some(200)
+            if present(item.get("url")) || present(item.get("uf").and_then(|uf| uf.get("url"))) =>
+        {
+            None
+        }
+        Some(200)
+            if item
+                .get("fee")
+                .and_then(Value::as_u64)
+                .is_some_and(|fee| VIP_FEES.contains(&fee)) =>
+        {
+            Some("vip")
+        }
+        Some(200) => Some("need_login"),
+        _ => Some("unavailable"),
+    };
+    MetingCheck {
+        playable: reason.is_none(),
+        reason: reason.map(str::to_string),
+        br: br.filter(|_| reason.is_none()),
+    }
+}
+
+/// # 获取专辑对象的 id、名称、艺术家、封面、曲目数、发行时间
+///
+/// ## None:
+///
+/// - .id as u64 / numeric str
+/// - .name as str
+fn get_album_summary(input: &Value) -> Option<MetingAlbum> {
+    Some(MetingAlbum {
+        id: input.get("id").and_then(get_id)?.to_string(),
+        name: input.get("name")?.as_str()?.to_string(),
+        artist: input
+            .get("artist")
+            .and_then(|artist| artist.get("name"))
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        cover: input
+            .get("picUrl")
+            .and_then(|cover| cover.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        count: input
+            .get("size")
+            .and_then(|count| count.as_u64())
+            .unwrap_or_default(),
+        publish_time: input
+            .get("publishTime")
+            .and_then(|time| time.as_u64())
+            .unwrap_or_default(),
+    })
+}
+
+/// 还在跑的任务，调用方被取消（如整个请求超时）时一起 abort
+struct Running<T>(VecDeque<JoinHandle<T>>);
+
+impl<T> Drop for Running<T> {
+    fn drop(&mut self) {
+        self.0.iter().for_each(JoinHandle::abort);
+    }
+}
+
+/// 同时最多 spawn `limit` 个任务（0 为不限），按 `tasks` 的顺序把结果交给 `each`
+///
+/// 最早的任务完成后才 spawn 下一个，panic 的任务跳过；返回的 future 被 drop 时没跑完的任务也会取消
+async fn spawn_ordered<T, Task>(
+    tasks: impl IntoIterator<Item = Task>,
+    limit: usize,
+    mut each: impl FnMut(T),
+) where
+    T: Send + 'static,
+    Task: Future<Output = T> + Send + 'static,
+{
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let mut tasks = tasks.into_iter();
+    let mut running = Running(VecDeque::new());
+    loop {
+        while running.0.len() < limit {
+            let Some(task) = tasks.next() else {
+                break;
+            };
+            running.0.push_back(tokio::spawn(task));
+        }
+        // 等待期间留在队列里，被取消时才能 abort 到
+        let Some(oldest) = running.0.front_mut() else {
+            break;
+        };
+        let output = oldest.await;
+        running.0.pop_front();
+        if let Ok(output) = output {
+            each(output);
+        }
+    }
+}
+
+/// 从 `song/enhance/player/url(/v1)` 的 `data[0]` 里取地址和音质
+fn get_url(item: &Value) -> Result<MetingUrl, Error> {
+    item.get("code")
+        .ok_or(Error::NoField("code"))?
+        .as_u64()
+        .ok_or(Error::TypeMismatch {
+            feild: "code",
+            target: "u64",
+        })
+        .and_then(|x| match x {
+            200 => Ok(()),
+            _ => Err(Error::None),
+        })?;
+    // code 为 200 但没有 url 的是需要 VIP 的歌
+    let url = item
+        .get("url")
+        .filter(|url| !url.is_null())
+        .or_else(|| item.get("uf")?.get("url").filter(|url| !url.is_null()))
+        .ok_or(Error::NeedLogin)?
+        .as_str()
+        .ok_or(Error::TypeMismatch {
+            target: "str",
+            feild: "json.url / json.uf.url",
+        })?
+        .replace("http://", "https://");
+    let number = |key| item.get(key).and_then(Value::as_u64).filter(|n| *n != 0);
+    Ok(MetingUrl {
+        url,
+        r#type: item
+            .get("type")
+            .and_then(Value::as_str)
+            .filter(|t| !t.is_empty())
+            .map(str::to_lowercase),
+        br: number("br"),
+        size: number("size"),
+    })
+}
+
+/// raw 接口的 `<type>` 对应的上游地址和请求体
+fn raw_request(r#type: &str, id: &str) -> Option<(&'static str, String)> {
+    match r#type {
+        "song" | "pic" => id
+            .parse::<u64>()
+            .ok()?
+            .then(|id| serde_json::to_string(&[SongItem::new(id)]))
+            .ok()?
+            .then(|items| (SONG_INFO_URL, SongReq::new(items).to_string())),
+        "url" => SongFileReq {
+            ids: vec![id.to_string()],
+            br: MUSIC_QUALITY,
+        }
+        .then(|req| (SONG_URL, req.to_string())),
+        "lrc" => (LRC_URL, LrcReq::new(id).to_string()),
+        "playlist" => (PLAYLIST_URL, Playlist::new(id).to_string()),
+        "search" => MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        }
+        .then(|options| (SEARCH_URL, SearchReq::new(id, options).to_string())),
+        "user_playlists" => (USER_PLAYLIST_URL, UserPlaylistReq::new(id).to_string()),
+        _ => return None,
+    }
+    .then(Some)
+}
+
+/// 把 `p*.music.126.net` 换成 `cdn`，保留路径和参数，其他 host 原样返回
+fn rewrite_pic_host(url: &str, cdn: &str) -> String {
+    let Some((_, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    match host.starts_with('p') && host.ends_with(".music.126.net") {
+        true => format!("{cdn}{path}"),
+        false => url.to_string(),
+    }
+}
+
+/// 播放地址和封面所在的 CDN
+const PROXY_HOSTS: [&str; 1] = ["music.126.net"];
+
+/// 支持的地区和对应的 IPv4 段，netease 按 `X-Real-IP` 判断请求来自哪里
+const REGION_IPS: [(&str, Ipv4Addr, Ipv4Addr); 5] = [
+    (
+        "cn",
+        Ipv4Addr::new(112, 88, 0, 0),
+        Ipv4Addr::new(112, 89, 36, 255),
+    ),
+    (
+        "hk",
+        Ipv4Addr::new(219, 76, 0, 0),
+        Ipv4Addr::new(219, 79, 255, 255),
+    ),
+    (
+        "tw",
+        Ipv4Addr::new(1, 160, 0, 0),
+        Ipv4Addr::new(1, 175, 255, 255),
+    ),
+    (
+        "jp",
+        Ipv4Addr::new(126, 0, 0, 0),
+        Ipv4Addr::new(126, 255, 255, 255),
+    ),
+    (
+        "us",
+        Ipv4Addr::new(12, 0, 0, 0),
+        Ipv4Addr::new(12, 255, 255, 255),
+    ),
+];
+
+/// 在地区的 IPv4 段里随机取一个，不认识的地区为 `None`
+fn region_ip(region: &str) -> Option<String> {
+    REGION_IPS
+        .iter()
+        .find(|(code, _, _)| code.eq_ignore_ascii_case(region))
+        .map(|(_, start, end)| rand::random_range(start.to_bits()..=end.to_bits()))
+        .map(|ip| Ipv4Addr::from_bits(ip).to_string())
+}
+
+const PLAYLIST_URL: &str = "/weapi/v6/playlist/detail";
+const SONG_INFO_URL: &str = "/weapi/v3/song/detail";
+const SONG_URL: &str = "/weapi/song/enhance/player/url";
+const SONG_URL_V1: &str = "/weapi/song/enhance/player/url/v1";
+const LRC_URL: &str = "/weapi/song/lyric";
+const SEARCH_URL: &str = "/weapi/cloudsearch/pc";
+const SUGGEST_URL: &str = "/weapi/search/suggest/web";
+const USER_PLAYLIST_URL: &str = "/weapi/user/playlist";
+const ANONYMOUS_URL: &str = "/weapi/register/anonimous";
+const SIMILAR_URL: &str = "/weapi/v1/discovery/simiSong";
+const ACCOUNT_URL: &str = "/weapi/w/nuser/account/get";
+const LIKED_URL: &str = "/weapi/song/like/get";
+/// 后面接 `R_SO_4_<歌曲 id>`
+const COMMENTS_URL: &str = "/weapi/v1/resource/comments/";
+const NEW_SONGS_URL: &str = "/weapi/v1/discovery/new/songs";
+const TOPLIST_URL: &str = "/weapi/toplist";
+const PERSONALIZED_URL: &str = "/weapi/personalized/playlist";
+const DAILY_URL: &str = "/weapi/v3/discovery/recommend/songs";
+/// 后面拼 `<album_id>`
+const ALBUM_URL: &str = "/weapi/v1/album/";
+/// 后面拼 `/<artist_id>`
+const ARTIST_ALBUMS_URL: &str = "/weapi/artist/albums";
+const ARTIST_MVS_URL: &str = "/weapi/artist/mvs";
+const SIGNIN_URL: &str = "/weapi/point/dailyTask";
+/// `point/dailyTask` 的 `type`：0 为移动端，积分比网页端（1）多
+const SIGNIN_TYPE: u8 = 0;
+/// 今天已经签过
+const SIGNIN_REPEATED_CODE: i64 = -2;
+const SIGNIN_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 推荐歌单的个数
+const PERSONALIZED_LIMIT: usize = 30;
+/// `cloudsearch` 的 `type`：艺术家
+const SEARCH_TYPE_ARTIST: usize = 100;
+const MUSIC_QUALITY: u64 = 320 * 1000;
+const ITEM_PRE_REQUEST: usize = 512;
+const SIMILAR_LIMIT: usize = 50;
+const ENCODER_NAME: &str = "netease";
+
+impl MetingApi for Netease {
+    fn name() -> &'static str {
+        "netease"
+    }
+
+    fn capabilities() -> &'static [Capability] {
+        &[
+            Capability::Url,
+            Capability::Pic,
+            Capability::Lrc,
+            Capability::Lyrics,
+            Capability::Comments,
+            Capability::Song,
+            Capability::Playlist,
+            Capability::ArtistAlbums,
+            Capability::ArtistMvs,
+            Capability::Album,
+            Capability::Search,
+            Capability::Suggest,
+            Capability::Similar,
+            Capability::UserPlaylists,
+            Capability::Daily,
+            Capability::Liked,
+            Capability::NewReleases,
+            Capability::Recommend,
+            Capability::Toplist,
+            Capability::Check,
+            Capability::Raw,
+        ]
+    }
+
+    /// 歌曲、歌单、专辑、用户和图片的 id 都是 `u64`
+    fn valid_id(id: &str) -> bool {
+        id.bytes().all(|b| b.is_ascii_digit()) && id.parse::<u64>().is_ok()
+    }
+
+    fn source(&self) -> &str {
+        self.source.as_deref().unwrap_or(Self::name())
+    }
+
+    /// 纯数字 id，或歌曲的分享链接，如 `https://music.163.com/#/song?id=456`
+    fn parse_id(input: &str) -> Option<String> {
+        let input = input.trim();
+        if Self::valid_id(input) {
+            return Some(input.to_string());
+        }
+        let url = reqwest::Url::parse(input).ok()?;
+        let host = url.host_str()?;
+        if host != "music.163.com" && !host.ends_with(".music.163.com") {
+            return None;
+        }
+        // 网页版的路由在 fragment 里
+        let (path, query) = match url.fragment() {
+            Some(fragment) => fragment.split_once('?').unwrap_or((fragment, "")),
+            None => (url.path(), url.query().unwrap_or_default()),
+        };
+        if !path.ends_with("/song") {
+            return None;
+        }
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("id="))
+            .filter(|id| Self::valid_id(id))
+            .map(str::to_string)
+    }
+
+    /// 周杰伦《晴天》，免费试听、有歌词，不需要登录
+    fn selftest_sample() -> Option<(&'static str, &'static str)> {
+        Some(("186016", "晴天 周杰伦"))
+    }
+
+    fn stats(&self) -> MetingStats {
+        MetingStats {
+            available_permits: self.counter.available_permits(),
+            queue_depth: self.waiting.load(Ordering::Relaxed),
+            upstream: self.latency.lock().unwrap().clone(),
+        }
+    }
+
+    /// 配置了 [`Netease::with_pic_cdn`] 时加上镜像的 host
+    fn proxy_hosts(&self) -> Vec<String> {
+        let cdn = self
+            .pic_cdn
+            .as_deref()
+            .and_then(|cdn| reqwest::Url::parse(cdn).ok())
+            .and_then(|cdn| cdn.host_str().map(str::to_string));
+        PROXY_HOSTS
+            .map(str::to_string)
+            .into_iter()
+            .chain(cdn)
+            .collect()
+    }
+
+    async fn url(&self, id: &str) -> Result<String, Error> {
+        get_url(&self.song_file(id).await?).map(|url| url.url)
+    }
+
+    /// 先用 v1 接口按等级请求，上游出错、超时或没有结果时退回只支持码率的旧接口
+    ///
+    /// 需要登录、参数不对之类的错误直接返回，不用旧接口掩盖配置问题
+    async fn url_with_level(&self, id: &str, level: &str) -> Result<MetingUrl, Error> {
+        match self
+            .song_file_v1(id, level)
+            .await
+            .and_then(|item| get_url(&item))
+        {
+            Ok(url) => Ok(url),
+            Err(e @ (Error::Server(_) | Error::Timeout(_) | Error::None)) => {
+                warn!("netease url/v1 {id} ({level}) failed, falling back: {e:?}");
+                get_url(&self.song_file(id).await?)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `id` 是歌曲 id 或专辑封面的 pic id，两者按 [`MIN_PIC_ID`] 区分，pic id 不请求上游
+    async fn pic(&self, id: &str) -> Result<String, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            target: "u64",
+            feild: "<id>",
+        })?;
+        if id >= MIN_PIC_ID {
+            return album_pic_url(id)
+                .map_err(|e| Error::Encode {
+                    engine: ENCODER_NAME,
+                    msg: format!("{e:?}"),
+                })?
+                .then(|pic| self.cdn_pic(&pic))
+                .then(Ok);
+        }
+        let hash_map = id
+            .then(SongItem::new)
+            .then(|it| [it])
+            .then(|its| serde_json::to_string(&its))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(SongReq::new)
+            .to_string()
+            .then(|str| WeapiEncoder::try_from_str(&str))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|weapi_data| async move {
+                self.exec::<HashMap<String, Value>>(SONG_INFO_URL, weapi_data)
+                    .await
+            })
+            .await
+            .map_err(Error::from)?;
+        let i = hash_map
+            .get("songs")
+            .ok_or(Error::NoField("songs"))?
+            .as_array()
+            .ok_or(Error::TypeMismatch {
+                target: "array",
+                feild: ".songs",
+            })?;
+        let song = i.first().ok_or(Error::None)?;
+        song.get("al")
+            .and_then(|al| al.get("picUrl"))
+            .ok_or(Error::NoField(".songs.0.al.picUrl"))?
+            .as_str()
+            .ok_or(Error::TypeMismatch {
+                target: "str",
+                feild: "songs.0.al.picUrl",
+            })?
+            .then(|pic| self.cdn_pic(pic))
+            .then(Ok)
+    }
+
+    /// netease 的图片支持 `?param=<宽>y<高>`，原有参数会被替换
+    fn resize_pic(&self, url: &str, size: u32) -> String {
+        let (path, _) = url.split_once('?').unwrap_or((url, ""));
+        format!("{path}?param={size}y{size}")
+    }
+
+    async fn lrc(&self, id: &str) -> Result<String, Error> {
+        self.lyric_json(id)
+            .await?
+            .then(|json| get_lyric(&json, "lrc"))
+            .unwrap_or("[00:00.00]暂无歌词".to_string())
+            .then(Ok)
+    }
+
+    async fn check(&self, id: &str) -> Result<MetingCheck, Error> {
+        match self.song_file(id).await {
+            Ok(item) => Ok(get_check(&item)),
+            Err(Error::NeedLogin) => Ok(MetingCheck {
+                playable: false,
+                reason: Some("need_login".to_string()),
+                br: None,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn comments(&self, id: &str) -> Result<MetingComments, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            feild: "<id>",
+            target: "u64",
+        })?;
+        let rid = format!("R_SO_4_{id}");
+        let resp = serde_json::json!({ "rid": rid, "limit": 20, "offset": 0 })
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move {
+                self.exec_at::<CommentsResp>(COMMENTS_URL, &format!("{COMMENTS_URL}{rid}"), we_data)
+                    .await
+            })
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        Ok(MetingComments {
+            total: resp.total,
+            hot: resp.hot_comments.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    async fn lyrics(&self, id: &str) -> Result<MetingLyrics, Error> {
+        let json = self.lyric_json(id).await?;
+        Ok(MetingLyrics {
+            lrc: get_lyric(&json, "lrc"),
+            tlyric: get_lyric(&json, "tlyric"),
+            romalrc: get_lyric(&json, "romalrc"),
+            yrc: get_lyric(&json, "yrc"),
+        })
+    }
+
+    async fn lrc_meta(&self, id: &str) -> Result<MetingLrcMeta, Error> {
+        let mut json = self.lyric_json(id).await?;
+        Ok(MetingLrcMeta {
+            lyric: get_lyric(&json, "lrc"),
+            tlyric: get_lyric(&json, "tlyric"),
+            version: json
+                .get("lrc")
+                .and_then(|lrc| lrc.get("version"))
+                .and_then(Value::as_u64),
+            contributor: json
+                .remove("lyricUser")
+                .and_then(|user| serde_json::from_value::<LyricUser>(user).ok())
+                .map(Into::into),
+        })
+    }
+
+    async fn song(
+        &self,
+        id: &str,
+        pic: impl Fn(&str) -> String + Send,
+        lrc: impl Fn(&str) -> String + Send,
+        url: impl Fn(&str) -> String + Send,
+    ) -> Result<MetingSong, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            feild: "<id>",
+            target: "u64",
+        })?;
+        // id 无效时上游返回空的 `songs`
+        self.song_details(&[id])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(Error::None)?
+            .into_meting(pic, lrc, url)
+            .then(Ok)
+    }
+
+    async fn songs(
+        &self,
+        ids: &[&str],
+        pic: impl Fn(&str) -> String + Send,
+        lrc: impl Fn(&str) -> String + Send,
+        url: impl Fn(&str) -> String + Send,
+    ) -> Result<Vec<(String, MetingSong)>, Error> {
+        let mut seen = HashSet::new();
+        let ids = ids
+            .iter()
+            .map(|&id| {
+                id.parse::<u64>()
+                    .map(|parsed| (id, parsed))
+                    .map_err(|_| Error::TypeMismatch {
+                        feild: "<id>",
+                        target: "u64",
+                    })
+            })
+            .filter(|id| id.as_ref().map_or(true, |(_, parsed)| seen.insert(*parsed)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parsed = ids.iter().map(|(_, parsed)| *parsed).collect::<Vec<_>>();
+        // 上游不保证按请求顺序返回
+        let mut songs = self
+            .song_details(&parsed)
+            .await?
+            .into_iter()
+            .map(|song| (song.id, song))
+            .collect::<HashMap<_, _>>();
+        ids.into_iter()
+            .filter_map(|(id, parsed)| Some((id, songs.remove(&parsed)?)))
+            .map(|(id, song)| (id.to_string(), song.into_meting(&pic, &lrc, &url)))
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn playlist(
+        &self,
+        id: &str,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        let mut songs = Vec::new();
+        self.playlist_detail(id, retry, pic, lrc, url, |batch| songs.extend(batch))
+            .await?;
+        Ok(songs)
+    }
+
+    async fn playlist_with_info(
+        &self,
+        id: &str,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<(MetingPlaylist, Vec<MetingSong>), Error> {
+        let mut songs = Vec::new();
+        let info = self
+            .playlist_detail(id, retry, pic, lrc, url, |batch| songs.extend(batch))
+            .await?;
+        Ok((info, songs))
+    }
+
+    async fn playlist_each(
+        &self,
+        id: &str,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+        sink: impl FnMut(Vec<MetingSong>) + Send,
+    ) -> Result<MetingPlaylist, Error> {
+        self.playlist_detail(id, retry, pic, lrc, url, sink).await
+    }
+
+    async fn playlists(&self, ids: &[&str]) -> Result<Vec<MetingPlaylist>, Error> {
+        let mut seen = HashSet::new();
+        let tasks = ids
+            .iter()
+            .filter(|id| seen.insert(**id))
+            .map(|id| {
+                let (this, id) = (self.clone(), id.to_string());
+                async move {
+                    let info = this.playlist_info(&id).await;
+                    (id, info)
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut playlists = Vec::new();
+        spawn_ordered(tasks, self.playlist_tasks, |(id, info)| match info {
+            Ok(info) => playlists.push(info.into()),
+            Err(e) => warn!("netease playlists {id}: {e:?}"),
+        })
+        .await;
+        Ok(playlists)
+    }
+
+    async fn playlist_tracks(&self, id: &str) -> Result<Vec<MetingTrack>, Error> {
+        let playlist = self.playlist_info(id).await?;
+        let known = playlist
+            .tracks
+            .into_iter()
+            .map(|song| (song.id, (song.artist(), song.name)))
+            .collect::<HashMap<_, _>>();
+        playlist
+            .track_ids
+            .iter()
+            .map(|track| {
+                let (artist, name) = known.get(&track.id).cloned().unzip();
+                MetingTrack {
+                    id: track.id.to_string(),
+                    name,
+                    artist,
+                }
+            })
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn search(
+        &self,
+        keyword: &str,
+        option: MetingSearchOptions,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        let result = self.search_result(keyword, option).await?;
+        resp::Song::with_privileges(result.songs, result.privileges)
+            .into_iter()
+            .map(|song| song.into_meting(&pic, &lrc, &url))
+            .collect::<Vec<MetingSong>>()
+            .then(Ok)
+    }
+
+    async fn search_artists(
+        &self,
+        keyword: &str,
+        option: MetingSearchOptions,
+    ) -> Result<Vec<MetingArtist>, Error> {
+        let option = MetingSearchOptions {
+            r#type: SEARCH_TYPE_ARTIST,
+            ..option
+        };
+        self.search_result(keyword, option)
+            .await?
+            .artists
+            .into_iter()
+            .map(MetingArtist::from)
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn suggest(&self, keyword: &str) -> Result<MetingSuggest, Error> {
+        let resp = serde_json::json!({ "s": keyword })
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| self.exec::<SuggestResp>(SUGGEST_URL, we_data))
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        Ok(resp.result.unwrap_or_default().into())
+    }
+
+    async fn similar(
+        &self,
+        id: &str,
+        pic: impl Fn(&str) -> String + Send + Sync,
+        lrc: impl Fn(&str) -> String + Send + Sync,
+        url: impl Fn(&str) -> String + Send + Sync,
+    ) -> Result<Vec<MetingSong>, Error> {
+        SimilarReq::new(id)
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move { self.exec::<SongsResp>(SIMILAR_URL, we_data).await })
+            .await
+            .map_err(Error::from)?
+            .songs
+            .into_iter()
+            .take(SIMILAR_LIMIT)
+            .map(|song| song.into_meting(&pic, &lrc, &url))
+            .collect::<Vec<MetingSong>>()
+            .then(Ok)
+    }
+
+    async fn raw(&self, r#type: &str, id: &str) -> Result<Value, Error> {
+        let (url, req) = raw_request(r#type, id).ok_or(Error::None)?;
+        WeapiEncoder::try_from_str(&req)
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move { self.exec::<Value>(url, we_data).await })
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn user_playlists(&self, uid: &str) -> Result<Vec<MetingPlaylist>, Error> {
+        UserPlaylistReq::new(uid)
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move {
+                self.exec::<UserPlaylistResp>(USER_PLAYLIST_URL, we_data)
+                    .await
+            })
+            .await
+            .map_err(Error::from)?
+            .then(|resp| check_code(resp.code).map(|_| resp))?
+            .playlist
+            .into_iter()
+            .map(MetingPlaylist::from)
+            .collect::<Vec<MetingPlaylist>>()
+            .then(Ok)
+    }
+
+    async fn new_releases(
+        &self,
+        area: &str,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        let area_id = match area {
+            "ZH" => 7,
+            "EA" => 96,
+            "KR" => 16,
+            "JP" => 8,
+            _ => 0,
+        };
+        let resp = serde_json::json!({ "areaId": area_id, "total": true })
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move { self.exec::<NewSongsResp>(NEW_SONGS_URL, we_data).await })
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        resp.data
+            .into_iter()
+            .map(|song| song.into_meting(&pic, &lrc, &url))
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn album(
+        &self,
+        id: &str,
+        enrich: bool,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            feild: "<id>",
+            target: "u64",
+        })?;
+        let resp = WeapiEncoder::try_from_str("{}")
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move {
+                self.exec_at::<AlbumResp>(ALBUM_URL, &format!("{ALBUM_URL}{id}"), we_data)
+                    .await
+            })
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        if resp.album.is_none() {
+            return Err(Error::None);
+        }
+        if !enrich {
+            return resp
+                .songs
+                .into_iter()
+                .map(|song| song.into_meting(&pic, &lrc, &url))
+                .collect::<Vec<_>>()
+                .then(Ok);
+        }
+        let mut songs = Vec::new();
+        let ids = resp.songs.iter().map(|song| song.id);
+        let missing = self
+            .songs_in_batches(ids, retry, pic, lrc, url, |batch| songs.extend(batch))
+            .await;
+        if missing > 0 {
+            warn!("netease album {id}: {missing} songs missing");
+        }
+        Ok(songs)
+    }
+
+    async fn recommended_playlists(&self) -> Result<Vec<MetingRecommend>, Error> {
+        serde_json::json!({ "limit": PERSONALIZED_LIMIT, "total": true, "n": 1000 })
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move {
+                self.exec::<PersonalizedResp>(PERSONALIZED_URL, we_data)
+                    .await
+            })
+            .await
+            .map_err(Error::from)?
+            .then(|resp| check_code(resp.code).map(|_| resp))?
+            .result
+            .into_iter()
+            .map(MetingRecommend::from)
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn toplist(&self) -> Result<Vec<MetingPlaylist>, Error> {
+        WeapiEncoder::try_from_str("{}")
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move { self.exec::<ToplistResp>(TOPLIST_URL, we_data).await })
+            .await
+            .map_err(Error::from)?
+            .then(|resp| check_code(resp.code).map(|_| resp))?
+            .list
+            .into_iter()
+            .map(MetingPlaylist::from)
+            .collect::<Vec<MetingPlaylist>>()
+            .then(Ok)
+    }
+
+    async fn daily(
+        &self,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        if !self.logged_in {
+            return Err(Error::NeedLogin);
+        }
+        let resp = WeapiEncoder::try_from_str("{}")
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| self.exec::<DailyResp>(DAILY_URL, we_data))
+            .await
+            .map_err(Error::from)?;
+        check_code(resp.code)?;
+        resp.data
+            .ok_or(Error::NoField(".data.dailySongs"))?
+            .daily_songs
+            .into_iter()
+            .map(|song| song.into_meting(&pic, &lrc, &url))
+            .collect::<Vec<_>>()
+            .then(Ok)
+    }
+
+    async fn liked(
+        &self,
+        retry: Arc<RetryBudget>,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> Result<Vec<MetingSong>, Error> {
+        if !self.logged_in {
+            return Err(Error::NeedLogin);
+        }
+        let encode = |data: String| {
+            WeapiEncoder::try_from_str(&data).map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })
+        };
+        // 喜欢列表按 uid 查，先从 Cookie 对应的账号拿 uid
+        let account = self
+            .exec::<AccountResp>(ACCOUNT_URL, encode("{}".to_string())?)
+            .await
+            .map_err(Error::from)?;
+        check_code(account.code)?;
+        let uid = account.account.ok_or(Error::NeedLogin)?.id;
+        let liked = self
+            .exec::<LikedResp>(
+                LIKED_URL,
+                encode(serde_json::json!({ "uid": uid }).to_string())?,
+            )
+            .await
+            .map_err(Error::from)?;
+        check_code(liked.code)?;
+        let mut songs = Vec::new();
+        let missing = self
+            .songs_in_batches(liked.ids.into_iter(), retry, pic, lrc, url, |batch| {
+                songs.extend(batch)
+            })
+            .await;
+        if missing > 0 {
+            warn!("netease liked {uid}: {missing} songs missing");
+        }
+        Ok(songs)
+    }
+
+    async fn artist_albums(
+        &self,
+        id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<MetingAlbum>, Error> {
+        let id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            target: "u64",
+            feild: "<id>",
+        })?;
+        ArtistAlbumsReq::new(limit, offset)
+            .to_string()
+            .then(|req| WeapiEncoder::try_from_str(&req))
+            .map_err(|e| Error::Encode {
+                engine: ENCODER_NAME,
+                msg: format!("{e:?}"),
+            })?
+            .then(|we_data| async move {
+                self.exec_at::<HashMap<String, Value>>(
+                    ARTIST_ALBUMS_URL,
+                    &format!("{ARTIST_ALBUMS_URL}/{id}"),
+                    we_data,
+                )
+                .await
+            })
+            .await
+            .map_err(Error::from)?
+            .get("hotAlbums")
+            .ok_or(Error::NoField(".hotAlbums"))?
+            .as_array()
+            .ok_or(Error::TypeMismatch {
+                feild: ".hotAlbums",
+                target: "array",
+            })?
+            .iter()
+            .filter_map(get_album_summary)
+            .collect::<Vec<MetingAlbum>>()
+            .then(Ok)
+    }
+
+    async fn artist_mvs(
+        &self,
+        id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<MetingMv>, Error> {
+        let artist_id = id.parse::<u64>().map_err(|_| Error::TypeMismatch {
+            feild: "<id>",
+            target: "u64",
+        })?;
+        let resp = ArtistMvsReq {
+            artist_id,
+            limit,
+            offset,
+            total: true,
+        }
+        .to_string()
+        .then(|req| WeapiEncoder::try_from_str(&req))
+        .map_err(|e| Error::Encode {
+            engine: ENCODER_NAME,
+            msg: format!("{e:?}"),
+        })?
+        .then(|we_data| self.exec::<ArtistMvsResp>(ARTIST_MVS_URL, we_data))
+        .await
+        .map_err(Error::from)?;
+        check_code(resp.code)?;
+        Ok(resp.mvs.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use proptest::{collection, prelude::*};
+    use serde_json::{json, Value};
+    use tokio::sync::Semaphore;
+    use wiremock::{
+        matchers::{header, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{ClientOptions, Netease, ReqError, RetryPermit, WeapiEncoder};
+    use crate::{Error, MetingApi};
+
+    /// 起一个只响应 `url` 的 mock 上游
+    async fn mock(url: &str, body: Value) -> (MockServer, Netease) {
+        let server = MockServer::start().await;
+        Mock::given(path(url))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        (server, netease)
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        // 连不上（这里是端口没人监听）算上游不可用
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let options = ClientOptions {
+            connect_timeout: Some(Duration::from_millis(50)),
+            ..ClientOptions::default()
+        };
+        let netease = Netease::with_client_options(Arc::new(Semaphore::new(8)), options)
+            .unwrap()
+            .with_base_url(&format!("http://{closed}"));
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease.exec::<serde_json::Value>("/", data).await;
+        assert!(matches!(Error::from(result.unwrap_err()), Error::Server(_)));
+
+        // 连上了但响应太慢
+        let server = MockServer::start().await;
+        Mock::given(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .mount(&server)
+            .await;
+        let options = ClientOptions {
+            request_timeout: Some(Duration::from_millis(50)),
+            ..ClientOptions::default()
+        };
+        let netease = Netease::with_client_options(Arc::new(Semaphore::new(8)), options)
+            .unwrap()
+            .with_base_url(&server.uri());
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease.exec::<serde_json::Value>("/slow", data).await;
+        assert!(matches!(
+            Error::from(result.unwrap_err()),
+            Error::Timeout(_)
+        ));
+
+        // 及时返回了，但不是 json
+        Mock::given(path("/html"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html></html>"))
+            .mount(&server)
+            .await;
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease.exec::<serde_json::Value>("/html", data).await;
+        assert!(matches!(&result, Err(ReqError::Req(e)) if e.is_decode()));
+        assert!(matches!(Error::from(result.unwrap_err()), Error::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let netease = Netease::new(Arc::new(Semaphore::new(0)))
+            .with_queue_timeout(Some(Duration::from_millis(10)));
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease.exec::<serde_json::Value>("/", data).await;
+        assert!(matches!(result, Err(ReqError::Busy(_))));
+        assert!(matches!(Error::from(result.unwrap_err()), Error::Server(_)));
+        assert_eq!(netease.stats().queue_depth, 0);
+    }
+
+    /// 排队中的请求被外层超时取消后，排队数也要减回去
+    #[tokio::test]
+    async fn test_queue_cancelled() {
+        let netease = Netease::new(Arc::new(Semaphore::new(0)));
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let exec = netease.exec::<serde_json::Value>("/", data);
+        let result = tokio::time::timeout(Duration::from_millis(10), exec).await;
+        assert!(result.is_err());
+        assert_eq!(netease.stats().queue_depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_user_playlists() {
+        let body = json!({
+            "code": 200,
+            "playlist": [
+                { "id": 1, "name": "a", "coverImgUrl": "https://p1.music.126.net/a.jpg", "trackCount": 3 },
+                { "id": 2, "name": "b" },
+                { "name": "no id" },
+            ],
+        });
+        let (_server, netease) = mock(super::USER_PLAYLIST_URL, body).await;
+        let playlists = netease.user_playlists("1").await.unwrap();
+        assert_eq!(playlists.len(), 2);
+        assert_eq!(playlists[0].cover, "https://p1.music.126.net/a.jpg");
+        assert_eq!(playlists[0].count, 3);
+        assert_eq!(playlists[1].id, "2");
+        assert_eq!(playlists[1].count, 0);
+    }
+
+    #[test]
+    fn test_numeric_and_string_ids() {
+        use super::resp::{PlaylistResp, Song};
+
+        let song = |value| serde_json::from_value::<Song>(value).map(|song| song.id);
+        assert_eq!(
+            song(json!({ "id": 1, "name": "a", "ar": [{ "name": "x" }] })).unwrap(),
+            1
+        );
+        assert_eq!(
+            song(json!({ "id": "18446744073709551615", "name": "a", "ar": [] })).unwrap(),
+            u64::MAX
+        );
+        assert!(song(json!({ "id": "abc", "name": "a", "ar": [] })).is_err());
+
+        let playlist = json!({ "playlist": {
+            "id": 1,
+            "name": "p",
+            "trackIds": [{ "id": 1 }, { "id": "2" }, { "id": null }],
+        } });
+        let playlist = serde_json::from_value::<PlaylistResp>(playlist)
+            .unwrap()
+            .playlist
+            .unwrap();
+        let ids = playlist
+            .track_ids
+            .iter()
+            .map(|track| track.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_lyrics() {
+        let body = json!({
+            "code": 200,
+            "lrc": { "version": 1, "lyric": "[00:01.00]a" },
+            "tlyric": { "version": 0, "lyric": "" },
+            "yrc": { "version": 1, "lyric": "[1000,500](1000,500,0)a" },
+        });
+        let (_server, netease) = mock(super::LRC_URL, body).await;
+        let lyrics = netease.lyrics("1").await.unwrap();
+        assert_eq!(lyrics.lrc.as_deref(), Some("[00:01.00]a"));
+        assert_eq!(lyrics.tlyric, None);
+        assert_eq!(lyrics.romalrc, None);
+        assert!(lyrics.yrc.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lrc_meta() {
+        let body = json!({
+            "code": 200,
+            "lrc": { "version": 7, "lyric": "[00:01.00]a" },
+            "tlyric": { "version": 2, "lyric": "[00:01.00]甲" },
+            "lyricUser": { "id": 9, "userid": 42, "nickname": "someone", "uptime": 1500000000000u64 },
+        });
+        let (server, netease) = mock(super::LRC_URL, body).await;
+        let meta = netease.lrc_meta("1").await.unwrap();
+        assert_eq!(meta.lyric.as_deref(), Some("[00:01.00]a"));
+        assert_eq!(meta.tlyric.as_deref(), Some("[00:01.00]甲"));
+        assert_eq!(meta.version, Some(7));
+        assert_eq!(
+            meta.contributor,
+            Some(crate::MetingContributor {
+                id: "42".to_string(),
+                nickname: "someone".to_string(),
+                uptime: Some(1500000000000),
+            })
+        );
+
+        // 纯音乐没有歌词也没有上传者
+        server.reset().await;
+        Mock::given(path(super::LRC_URL))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "code": 200, "nolyric": true })),
+            )
+            .mount(&server)
+            .await;
+        let meta = netease.lrc_meta("1").await.unwrap();
+        assert_eq!(
+            (meta.lyric, meta.version, meta.contributor),
+            (None, None, None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_url_with_level() {
+        let item = json!({ "code": 200, "url": "http://a.flac", "type": "FLAC", "br": 999000, "size": 10 });
+        let (server, netease) =
+            mock(super::SONG_URL_V1, json!({ "code": 200, "data": [item] })).await;
+        let url = netease.url_with_level("1", "lossless").await.unwrap();
+        assert_eq!(
+            url,
+            crate::MetingUrl {
+                url: "https://a.flac".to_string(),
+                r#type: Some("flac".to_string()),
+                br: Some(999000),
+                size: Some(10),
+            }
+        );
+
+        // v1 没有结果时退回旧接口
+        server.reset().await;
+        Mock::given(path(super::SONG_URL_V1))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "code": 200, "data": [{ "code": 404 }] })),
+            )
+            .mount(&server)
+            .await;
+        let item = json!({ "code": 200, "url": "http://a.mp3", "type": "mp3", "br": 320000 });
+        Mock::given(path(super::SONG_URL))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "code": 200, "data": [item] })),
+            )
+            .mount(&server)
+            .await;
+        let url = netease.url_with_level("1", "hires").await.unwrap();
+        assert_eq!(url.r#type.as_deref(), Some("mp3"));
+        assert_eq!((url.br, url.size), (Some(320000), None));
+
+        // 需要登录不是上游故障，不退回旧接口
+        server.reset().await;
+        Mock::given(path(super::SONG_URL_V1))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 301 })))
+            .mount(&server)
+            .await;
+        let result = netease.url_with_level("1", "hires").await;
+        assert!(matches!(result, Err(Error::NeedLogin)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_region_real_ip() {
+        use wiremock::matchers::header_regex;
+
+        let server = MockServer::start().await;
+        Mock::given(path(super::SONG_URL))
+            .and(header_regex("X-Real-IP", r"^126\."))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": -462 })))
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8)))
+            .with_base_url(&server.uri())
+            .with_region(Some("cn"));
+        // 请求的地区优先于默认地区
+        let result = crate::REGION
+            .scope("JP".to_string(), netease.url("1"))
+            .await;
+        assert!(matches!(result, Err(Error::NeedLogin)));
+        // 默认地区不匹配，mock 返回空 body 的 404
+        assert!(matches!(netease.url("1").await, Err(Error::Decode(_))));
+    }
+
+    #[tokio::test]
+    async fn test_playlist_with_info() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({
+                "code": 200,
+                "playlist": {
+                    "id": 7, "name": "p", "coverImgUrl": "c", "trackCount": 1,
+                    "creator": { "nickname": "someone" },
+                    "trackIds": [{ "id": 1 }],
+                },
+            }),
+        )
+        .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [{ "id": 1, "name": "a", "ar": [{ "name": "x" }] }],
+            })))
+            .mount(&server)
+            .await;
+        let (info, songs) = netease
+            .playlist_with_info(
+                "7",
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| format!("pic/{id}"),
+                |id| format!("lrc/{id}"),
+                |id| format!("url/{id}"),
+            )
+            .await
+            .unwrap();
+        assert_eq!((info.id.as_str(), info.creator.as_str()), ("7", "someone"));
+        assert_eq!(serde_json::to_value(songs).unwrap()[0]["url"], "url/1");
+    }
+
+    /// `song/detail` 平级的 `privileges` 按 id 合进歌曲，歌单和单曲都带上 `playable` / `max_br`
+    #[tokio::test]
+    async fn test_song_detail_privileges() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({ "code": 200, "playlist": { "id": 7, "name": "p", "trackIds": [{ "id": 1 }, { "id": 2 }] } }),
+        )
+        .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 1, "name": "a", "ar": [{ "name": "x" }] },
+                    { "id": 2, "name": "b", "ar": [{ "name": "y" }] },
+                ],
+                "privileges": [
+                    { "id": 2, "fee": 1, "st": 0, "pl": 0, "dl": 0 },
+                    { "id": 1, "fee": 8, "st": 0, "pl": 128000, "dl": 320000 },
+                ],
+            })))
+            .mount(&server)
+            .await;
+        let songs = netease
+            .playlist(
+                "7",
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        let hints = |song: &crate::MetingSong| (song.fee(), song.playable(), song.max_br());
+        assert_eq!(hints(&songs[0]), (8, Some(true), Some(128000)));
+        assert_eq!(hints(&songs[1]), (1, Some(false), Some(0)));
+        let song = netease
+            .song(
+                "1",
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(hints(&song), (8, Some(true), Some(128000)));
+    }
+
+    /// 没有歌名、艺术家的歌照样输出，只是字段为空
+    #[tokio::test]
+    async fn test_song_without_name() {
+        let body = json!({
+            "code": 200,
+            "songs": [
+                { "id": 1, "ar": [{ "name": "x" }] },
+                { "id": 2, "name": null },
+                { "id": 3, "name": "c", "ar": [] },
+            ],
+        });
+        let (_server, netease) = mock(super::SONG_INFO_URL, body).await;
+        let songs = netease
+            .songs(
+                &["1", "2", "3"],
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        let ids = songs.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3"]);
+        let songs = songs.into_iter().map(|(_, song)| song).collect::<Vec<_>>();
+        let songs = serde_json::to_value(songs).unwrap();
+        let names = songs
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|song| (song["name"].clone(), song["artist"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (json!(""), json!("x")),
+                (json!(""), json!("")),
+                (json!("c"), json!("")),
+            ]
+        );
+    }
+
+    /// `song/detail` 打乱了顺序时仍按 `trackIds` 输出
+    #[tokio::test]
+    async fn test_playlist_track_order() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({
+                "code": 200,
+                "playlist": { "id": 7, "name": "p", "trackIds": [{ "id": 3 }, { "id": 1 }, { "id": 2 }] },
+            }),
+        )
+        .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 1, "name": "a", "ar": [{ "name": "x" }] },
+                    { "id": 2, "name": "b", "ar": [{ "name": "x" }] },
+                    { "id": 3, "name": "c", "ar": [{ "name": "x" }] },
+                ],
+            })))
+            .mount(&server)
+            .await;
+        let songs = netease
+            .playlist(
+                "7",
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        let ids = songs.iter().map(|song| song.url()).collect::<Vec<_>>();
+        assert_eq!(ids, ["3", "1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_playlists() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({
+                "code": 200,
+                "playlist": { "id": 7, "name": "p", "coverImgUrl": "c", "trackCount": 3 },
+            }),
+        )
+        .await;
+        let playlists = netease.playlists(&["7", "7", "8"]).await.unwrap();
+        assert_eq!(playlists.len(), 2);
+        assert_eq!((playlists[0].cover.as_str(), playlists[0].count), ("c", 3));
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_playlist_strict() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({
+                "code": 200,
+                "playlist": { "id": 7, "name": "p", "trackIds": [{ "id": 1 }, { "id": 2 }] },
+            }),
+        )
+        .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let fetch = || {
+            netease.playlist(
+                "7",
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+        };
+        assert!(fetch().await.unwrap().is_empty());
+        assert!(matches!(
+            crate::STRICT.scope(true, fetch()).await,
+            Err(Error::Incomplete { missing: 2 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_playlist_tracks() {
+        let (server, netease) = mock(
+            super::PLAYLIST_URL,
+            json!({
+                "code": 200,
+                "playlist": {
+                    "id": 7, "name": "p",
+                    "trackIds": [{ "id": 1 }, { "id": 2 }],
+                    "tracks": [{ "id": 1, "name": "a", "ar": [{ "name": "x" }, { "name": "y" }] }],
+                },
+            }),
+        )
+        .await;
+        let tracks = netease.playlist_tracks("7").await.unwrap();
+        assert_eq!(
+            tracks,
+            [
+                crate::MetingTrack {
+                    id: "1".to_string(),
+                    name: Some("a".to_string()),
+                    artist: Some("x/y".to_string()),
+                },
+                crate::MetingTrack {
+                    id: "2".to_string(),
+                    name: None,
+                    artist: None,
+                },
+            ]
+        );
+        // 不逐首请求详情
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests
+            .iter()
+            .all(|req| req.url.path() != super::SONG_INFO_URL));
+    }
+
+    #[tokio::test]
+    async fn test_song_album_pic_id() {
+        let body = json!({
+            "code": 200,
+            "songs": [{
+                "id": 1, "name": "a", "ar": [{ "name": "x" }],
+                "al": { "pic": 109951163076136650u64, "picStr": "109951163076136658" },
+            }],
+        });
+        let (_server, netease) = mock(super::SONG_INFO_URL, body).await;
+        let song = netease
+            .song(
+                "1",
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(song.pic, "109951163076136658");
+        assert_eq!(song.url, "1");
+
+        // 没有专辑封面，或 pic id 短到会被当成歌曲 id 时，退回歌曲 id
+        for al in [
+            json!({ "pic": 0 }),
+            json!({ "pic": 123456, "picStr": "123456" }),
+        ] {
+            let body = json!({
+                "code": 200,
+                "songs": [{ "id": 2, "name": "b", "ar": [], "al": al }],
+            });
+            let (_server, netease) = mock(super::SONG_INFO_URL, body).await;
+            let song = netease
+                .song(
+                    "2",
+                    |id| id.to_string(),
+                    |id| id.to_string(),
+                    |id| id.to_string(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(song.pic, "2", "{al}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pic_by_album_pic_id() {
+        let body = json!({ "code": 200, "songs": [] });
+        let (server, netease) = mock(super::SONG_INFO_URL, body).await;
+        let pic = netease.pic("109951163076136658").await.unwrap();
+        assert!(pic.starts_with("https://p3.music.126.net/"));
+        assert!(pic.ends_with("/109951163076136658.jpg"));
+        let encoded = pic.split('/').nth(3).unwrap();
+        assert!(!encoded.contains(['+', '/']));
+        // pic id 直接拼地址，不请求上游
+        assert!(server.received_requests().await.unwrap().is_empty());
+        // 不存在的歌曲 id 不当作 pic id
+        assert!(matches!(netease.pic("905").await, Err(Error::None)));
+    }
+
+    #[tokio::test]
+    async fn test_exec_non_object_root() {
+        let (_server, netease) = mock(super::LRC_URL, json!([{ "code": -460 }])).await;
+        let Err(Error::Decode(msg)) = netease.lrc("1").await else {
+            panic!("expected Error::Decode");
+        };
+        assert!(msg.contains("array at top level"), "{msg}");
+    }
+
+    #[tokio::test]
+    async fn test_comments() {
+        let body = json!({
+            "code": 200,
+            "total": 12345,
+            "hotComments": [
+                { "user": { "nickname": "a" }, "content": "好听", "likedCount": 99 },
+                { "user": null, "content": "b" },
+                { "likedCount": 1 },
+            ],
+        });
+        let (_server, netease) = mock("/weapi/v1/resource/comments/R_SO_4_1", body).await;
+        let comments = netease.comments("1").await.unwrap();
+        assert_eq!(comments.total, 12345);
+        assert_eq!(
+            serde_json::to_value(&comments.hot).unwrap(),
+            json!([
+                { "user": "a", "content": "好听", "likes": 99 },
+                { "user": "", "content": "b", "likes": 0 },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_releases() {
+        let body = json!({
+            "code": 200,
+            "data": [
+                { "id": 1, "name": "a", "artists": [{ "name": "x" }] },
+                { "id": 2, "name": "b", "artists": [{ "name": "y" }, { "name": "z" }] },
+            ],
+        });
+        let (_server, netease) = mock(super::NEW_SONGS_URL, body).await;
+        let songs = netease
+            .new_releases(
+                "JP",
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await
+            .unwrap();
+        let songs = serde_json::to_value(songs).unwrap();
+        assert_eq!(songs[1]["artist"], "y/z");
+        assert_eq!(songs[0]["url"], "1");
+    }
+
+    /// 默认用专辑接口带的曲目，`enrich` 时曲目换成歌曲详情里的
+    #[tokio::test]
+    async fn test_album() {
+        let server = MockServer::start().await;
+        Mock::given(path(format!("{}32311", super::ALBUM_URL)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "album": { "id": 32311, "name": "album" },
+                "songs": [
+                    { "id": 1, "name": "a", "ar": [{ "name": "x" }] },
+                    { "id": 2, "name": "b", "ar": [{ "name": "y" }] },
+                ],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 1, "name": "a", "ar": [{ "name": "x" }], "fee": 8, "al": { "picStr": "109951163076136658" } },
+                    { "id": 2, "name": "b", "ar": [{ "name": "y" }], "fee": 1 },
+                ],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        let album = |enrich| {
+            netease.album(
+                "32311",
+                enrich,
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+        };
+        let light = serde_json::to_value(album(false).await.unwrap()).unwrap();
+        assert_eq!(
+            (light[0]["pic"].clone(), light[0]["fee"].clone()),
+            (json!("1"), json!(0))
+        );
+        let enriched = serde_json::to_value(album(true).await.unwrap()).unwrap();
+        assert_eq!(enriched[0]["pic"], "109951163076136658");
+        assert_eq!(
+            (enriched[0]["fee"].clone(), enriched[1]["fee"].clone()),
+            (json!(8), json!(1))
+        );
+
+        // 专辑不存在时没有 `album`
+        Mock::given(path(format!("{}1", super::ALBUM_URL)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 404 })))
+            .mount(&server)
+            .await;
+        let missing = netease
+            .album(
+                "1",
+                false,
+                Arc::new(crate::RetryBudget::new(0, 0)),
+                |id| id.to_string(),
+                |id| id.to_string(),
+                |id| id.to_string(),
+            )
+            .await;
+        assert!(matches!(missing, Err(Error::None)));
+    }
+
+    #[test]
+    fn test_valid_id() {
+        assert!(Netease::valid_id("1"));
+        assert!(Netease::valid_id("18446744073709551615"));
+        for id in ["", "+1", "-1", "1a", "18446744073709551616", "１"] {
+            assert!(!Netease::valid_id(id), "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recommended_playlists() {
+        let body = json!({
+            "code": 200,
+            "hasTaste": false,
+            "result": [
+                { "id": 2829883282u64, "name": "a", "picUrl": "https://p1.music.126.net/x/1.jpg", "playCount": 12345, "trackCount": 30 },
+                { "id": "1", "name": "b" },
+                { "name": "no id" },
+            ],
+        });
+        let (_server, netease) = mock(super::PERSONALIZED_URL, body).await;
+        let playlists = netease.recommended_playlists().await.unwrap();
+        assert_eq!(playlists.len(), 2);
+        assert_eq!(
+            (playlists[0].id.as_str(), playlists[0].play_count),
+            ("2829883282", 12345)
+        );
+        assert_eq!(
+            (playlists[1].cover.as_str(), playlists[1].play_count),
+            ("", 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_toplist() {
+        let body = json!({
+            "code": 200,
+            "list": [
+                { "id": 19723756, "name": "飙升榜", "coverImgUrl": "c", "trackCount": 100, "creator": null },
+                { "id": 3778678, "name": "热歌榜", "trackCount": 200 },
+            ],
+        });
+        let (_server, netease) = mock(super::TOPLIST_URL, body).await;
+        let charts = netease.toplist().await.unwrap();
+        assert_eq!(charts.len(), 2);
+        assert_eq!((charts[0].id.as_str(), charts[0].count), ("19723756", 100));
+        assert_eq!(
+            (charts[1].name.as_str(), charts[1].cover.as_str()),
+            ("热歌榜", "")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_daily() {
+        let body = json!({
+            "code": 200,
+            "data": { "dailySongs": [{ "id": 1, "name": "a", "ar": [{ "name": "x" }] }] },
+        });
+        let (_server, netease) = mock(super::DAILY_URL, body).await;
+        let (pic, lrc, url) = (
+            |id: &str| id.to_string(),
+            |id: &str| id.to_string(),
+            |id: &str| id.to_string(),
+        );
+        assert!(matches!(
+            netease.daily(pic, lrc, url).await,
+            Err(Error::NeedLogin)
+        ));
+        let netease = netease.with_login_cookie(Some("MUSIC_U=token"));
+        let songs = netease.daily(pic, lrc, url).await.unwrap();
+        assert_eq!(serde_json::to_value(songs).unwrap()[0]["artist"], "x");
+    }
+
+    #[tokio::test]
+    async fn test_liked() {
+        let (server, netease) = mock(
+            super::ACCOUNT_URL,
+            json!({ "code": 200, "account": { "id": 42 }, "profile": { "userId": 42 } }),
+        )
+        .await;
+        Mock::given(path(super::LIKED_URL))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "code": 200, "ids": [2, 1] })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(path(super::SONG_INFO_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "code": 200,
+                "songs": [
+                    { "id": 2, "name": "b", "ar": [{ "name": "y" }] },
+                    { "id": 1, "name": "a", "ar": [{ "name": "x" }] },
+                ],
+            })))
+            .mount(&server)
+            .await;
+        let (pic, lrc, url) = (
+            |id: &str| id.to_string(),
+            |id: &str| id.to_string(),
+            |id: &str| id.to_string(),
+        );
+        let retry = Arc::new(crate::RetryBudget::new(0, 0));
+        // 没配置 Cookie 时不请求上游
+        assert!(matches!(
+            netease.liked(retry.clone(), pic, lrc, url).await,
+            Err(Error::NeedLogin)
+        ));
+        assert!(server.received_requests().await.unwrap().is_empty());
+        let netease = netease.with_login_cookie(Some("MUSIC_U=token"));
+        let songs = netease.liked(retry, pic, lrc, url).await.unwrap();
+        let songs = serde_json::to_value(songs).unwrap();
+        assert_eq!(songs[0]["name"], "b");
+        assert_eq!(songs[1]["url"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_artist_albums() {
+        let body = json!({
+            "code": 200,
+            "hotAlbums": [
+                {
+                    "id": 1, "name": "a", "picUrl": "https://p1.music.126.net/a.jpg",
+                    "size": 10, "publishTime": 1600000000000u64, "artist": { "name": "x" },
+                },
+                { "name": "no id" },
+            ],
+        });
+        let (_server, netease) = mock("/weapi/artist/albums/1", body).await;
+        let albums = netease.artist_albums("1", 30, 0).await.unwrap();
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].artist, "x");
+        assert_eq!(albums[0].count, 10);
+        assert_eq!(albums[0].publish_time, 1600000000000);
+    }
+
+    #[tokio::test]
+    async fn test_artist_mvs() {
+        let body = json!({
+            "code": 200,
+            "mvs": [
+                { "id": 5, "name": "a", "imgurl16v9": "https://p1.music.126.net/a.jpg", "imgurl": "x", "duration": 233000 },
+                { "id": "6", "name": "b", "imgurl16v9": "", "imgurl": "https://p1.music.126.net/b.jpg" },
+                { "name": "no id" },
+            ],
+            "hasMore": false,
+        });
+        let (server, netease) = mock(super::ARTIST_MVS_URL, body).await;
+        let mvs = netease.artist_mvs("1", 2, 4).await.unwrap();
+        assert_eq!(
+            mvs,
+            [
+                crate::MetingMv {
+                    id: "5".to_string(),
+                    name: "a".to_string(),
+                    cover: "https://p1.music.126.net/a.jpg".to_string(),
+                    duration: 233000,
+                },
+                crate::MetingMv {
+                    id: "6".to_string(),
+                    name: "b".to_string(),
+                    cover: "https://p1.music.126.net/b.jpg".to_string(),
+                    duration: 0,
+                },
+            ]
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+        assert!(netease.artist_mvs("abc", 2, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_suggest() {
+        let body = json!({
+            "code": 200,
+            "result": {
+                "songs": [{ "id": 1, "name": "晴天", "artists": [{ "name": "周杰伦" }, { "name": "x" }] }],
+                "artists": [{ "id": "2", "name": "周杰伦" }, { "name": "no id" }],
+                "albums": [{ "id": 3, "name": "叶惠美", "artist": { "name": "周杰伦" } }],
+                "order": ["songs", "artists", "albums"],
+            },
+        });
+        let (server, netease) = mock(super::SUGGEST_URL, body).await;
+        let suggest = serde_json::to_value(netease.suggest("晴天").await.unwrap()).unwrap();
+        assert_eq!(
+            suggest,
+            json!({
+                "songs": [{ "id": "1", "name": "晴天", "artist": "周杰伦/x" }],
+                "artists": [{ "id": "2", "name": "周杰伦" }],
+                "albums": [{ "id": "3", "name": "叶惠美", "artist": "周杰伦" }],
+            })
+        );
+
+        server.reset().await;
+        Mock::given(path(super::SUGGEST_URL))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "code": 200, "result": {} })),
+            )
+            .mount(&server)
+            .await;
+        assert_eq!(
+            netease.suggest("zzz").await.unwrap(),
+            crate::MetingSuggest::default()
+        );
+    }
+
+    /// openssl 和 rustcrypto 两个后端都要得到同样的结果
+    #[test]
+    fn test_weapi_vector() {
+        let data = WeapiEncoder::with_secret_key(r#"{"id":"1"}"#, b"abcdefghijklmnop").unwrap();
+        assert_eq!(data.params, "ERVMY+uBJ6qMpQmfSfp+0pMySG92n6RT0I6/sTVmVkM=");
+        assert_eq!(
+            data.enc_sec_key,
+            "d15a1683c992095d0c234c19966605c5c5964911268bbeda8cb8d08d834913e5\
+             9d53b32358903a121b5fca784c1f5ae44951fd02524df58ecc98e52cc7cf8689\
+             b42c2e93ddf05b0592512d87f5960467e2f086c018849d76014d323500e30f13\
+             ef4cafbb0cf5a66731a3f1776c75ca35d0062dac70a3e33245afabcf47938487"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_anonymous() {
+        let server = MockServer::start().await;
+        Mock::given(path(super::ANONYMOUS_URL))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Set-Cookie", "NMTID=x; Path=/")
+                    .append_header("Set-Cookie", "MUSIC_A=token; Max-Age=1; Path=/")
+                    .set_body_json(json!({ "code": 200 })),
+            )
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        netease.register_anonymous().await.unwrap();
+        let cookie = netease.cookie.read().await.get("Cookie").unwrap().clone();
+        assert!(cookie.to_str().unwrap().ends_with("; MUSIC_A=token"));
+    }
+
+    #[tokio::test]
+    async fn test_daily_signin() {
+        let (server, netease) = mock(super::SIGNIN_URL, json!({ "code": 200, "point": 3 })).await;
+        assert!(matches!(
+            netease.daily_signin().await,
+            Err(Error::NeedLogin)
+        ));
+        let netease = netease.with_login_cookie(Some("MUSIC_U=token"));
+        assert_eq!(netease.daily_signin().await.unwrap(), Some(3));
+
+        server.reset().await;
+        Mock::given(path(super::SIGNIN_URL))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "code": -2, "msg": "重复签到" })),
+            )
+            .mount(&server)
+            .await;
+        assert_eq!(netease.daily_signin().await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_rewrite_pic_host() {
+        use super::rewrite_pic_host;
+        let cdn = "https://img.example.com";
+        assert_eq!(
+            rewrite_pic_host("https://p2.music.126.net/a/b.jpg?param=300y300", cdn),
+            "https://img.example.com/a/b.jpg?param=300y300"
+        );
+        assert_eq!(
+            rewrite_pic_host("http://p1.music.126.net/c.jpg", cdn),
+            "https://img.example.com/c.jpg"
+        );
+        assert_eq!(
+            rewrite_pic_host("https://example.org/c.jpg", cdn),
+            "https://example.org/c.jpg"
+        );
+    }
+
+    #[test]
+    fn test_resize_pic() {
+        let netease = Netease::new(Arc::new(Semaphore::new(1)));
+        assert_eq!(
+            netease.resize_pic("https://p2.music.126.net/a.jpg", 300),
+            "https://p2.music.126.net/a.jpg?param=300y300"
+        );
+        assert_eq!(
+            netease.resize_pic("https://p2.music.126.net/a.jpg?param=1y1", 640),
+            "https://p2.music.126.net/a.jpg?param=640y640"
+        );
+    }
+
+    #[test]
+    fn test_parse_id() {
+        for (input, expected) in [
+            (" 456 ", Some("456")),
+            ("https://music.163.com/song?id=456&userid=1", Some("456")),
+            ("https://music.163.com/#/song?id=456", Some("456")),
+            ("https://y.music.163.com/m/song?id=456", Some("456")),
+            ("https://music.163.com/#/playlist?id=456", None),
+            ("https://example.com/song?id=456", None),
+            ("https://music.163.com/song?id=abc", None),
+            ("abc", None),
+        ] {
+            assert_eq!(Netease::parse_id(input).as_deref(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let netease = Netease::new(Arc::new(Semaphore::new(1)));
+        assert_eq!(netease.source(), "netease");
+        assert_eq!(
+            netease.with_source(Some("mirror1:netease")).source(),
+            "mirror1:netease"
+        );
+    }
+
+    #[test]
+    fn test_proxy_hosts() {
+        let netease = Netease::new(Arc::new(Semaphore::new(1)));
+        assert_eq!(netease.proxy_hosts(), ["music.126.net"]);
+        let netease = netease.with_pic_cdn(Some("img.example.com/"));
+        assert_eq!(netease.proxy_hosts(), ["music.126.net", "img.example.com"]);
+    }
+
+    /// 偏向上游真实字段名的随机 json
+    fn arb_json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<u64>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".{0,8}".prop_map(Value::from),
+        ];
+        let key = prop_oneof![
+            Just("id".to_string()),
+            Just("name".to_string()),
+            Just("ar".to_string()),
+            Just("al".to_string()),
+            Just("songs".to_string()),
+            Just("playlist".to_string()),
+            Just("trackIds".to_string()),
+            ".{0,8}",
+        ];
+        leaf.prop_recursive(5, 128, 8, move |inner| {
+            prop_oneof![
+                collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                collection::btree_map(key.clone(), inner, 0..8)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parsers_never_panic(value in arb_json()) {
+            use super::resp::{PlaylistResp, SearchResp, SongsResp, UserPlaylistResp};
+
+            for root in [value.clone(), json!({ "songs": value.clone() }), json!({ "playlist": value.clone() }), json!({ "result": value })] {
+                let _ = serde_json::from_value::<SongsResp>(root.clone());
+                let _ = serde_json::from_value::<PlaylistResp>(root.clone());
+                let _ = serde_json::from_value::<SearchResp>(root.clone());
+                let _ = serde_json::from_value::<UserPlaylistResp>(root);
+            }
+        }
+    }
+
+    /// 失败后到下次重试之间，许可已经归还
+    #[tokio::test]
+    async fn test_retry_releases_permit() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(1))).with_base_url(&server.uri());
+        let counter = netease.counter.clone();
+        let result = crate::retry(
+            &crate::RetryBudget::new(2, 10),
+            netease,
+            |netease| async move {
+                let data = WeapiEncoder::try_from_str("{}").unwrap();
+                netease.exec::<Value>("/", data).await
+            },
+            |_| assert_eq!(counter.available_permits(), 1),
+        )
+        .await;
+        assert!(matches!(result, Err(ReqError::Req(_))));
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    /// 429 时按 `Retry-After` 等待后再重试，`code: -460` 也算限流
+    #[tokio::test]
+    async fn test_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 200 })))
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(1))).with_base_url(&server.uri());
+        let start = std::time::Instant::now();
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease
+            .exec_retry::<Value>(&crate::RetryBudget::new(1, 1), "/", data, |e| {
+                assert_eq!(e.retry_after(), Some(Duration::from_secs(1)))
+            })
+            .await;
+        assert_eq!(result.unwrap()["code"], 200);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        server.reset().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": -460 })))
+            .mount(&server)
+            .await;
+        let data = WeapiEncoder::try_from_str("{}").unwrap();
+        let result = netease.exec::<Value>("/", data).await;
+        assert!(matches!(result, Err(ReqError::RateLimited(None))));
+    }
+
+    /// 默认退避期间归还许可，`Hold` 时一直占着，且重试不会因为等自己的许可卡住
+    #[tokio::test]
+    async fn test_retry_permit() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        for (retry_permit, available) in [(RetryPermit::Release, 1), (RetryPermit::Hold, 0)] {
+            let netease = Netease::new(Arc::new(Semaphore::new(1)))
+                .with_base_url(&server.uri())
+                .with_retry_permit(retry_permit);
+            let result = netease
+                .exec_retry::<Value>(
+                    &crate::RetryBudget::new(2, 10),
+                    "/",
+                    WeapiEncoder::try_from_str("{}").unwrap(),
+                    |_| assert_eq!(netease.counter.available_permits(), available),
+                )
+                .await;
+            assert!(matches!(result, Err(ReqError::Req(_))));
+            assert_eq!(netease.counter.available_permits(), 1);
+        }
+        assert_eq!(server.received_requests().await.unwrap().len(), 6);
+    }
+
+    /// `REAL_IP` 作用域内优先于地区和随机 ip
+    #[tokio::test]
+    async fn test_real_ip_override() {
+        let server = MockServer::start().await;
+        Mock::given(path(super::LRC_URL))
+            .and(header("X-Real-IP", "2001:db8::1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 200 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        let lrc = crate::REGION.scope(
+            "hk".to_string(),
+            crate::REAL_IP.scope("2001:db8::1".parse().unwrap(), netease.lrc("1")),
+        );
+        let _ = lrc.await;
+    }
+
+    /// 延迟按接口常量分开统计，拼了 id 的地址也归到常量下
+    #[tokio::test]
+    async fn test_upstream_latency() {
+        let server = MockServer::start().await;
+        Mock::given(path(super::LRC_URL))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 200 })))
+            .mount(&server)
+            .await;
+        Mock::given(path(format!("{}R_SO_4_1", super::COMMENTS_URL)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "code": 200 }))
+                    .set_delay(Duration::from_millis(60)),
+            )
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        let _ = netease.lrc("1").await;
+        let _ = netease.lrc("1").await;
+        let _ = netease.comments("1").await;
+        let upstream = netease.stats().upstream;
+        assert_eq!(
+            upstream.keys().collect::<Vec<_>>(),
+            [&super::LRC_URL, &super::COMMENTS_URL]
+        );
+        assert_eq!(upstream[super::LRC_URL].count, 2);
+        let comments = &upstream[super::COMMENTS_URL];
+        assert_eq!((comments.count, comments.le[1], comments.le[2]), (1, 0, 1));
+        let json = serde_json::to_value(comments).unwrap();
+        assert_eq!(json["le"]["100"], 1);
+    }
+
+    /// 单次还能重试，但共享额度用完后立即失败
+    #[tokio::test]
+    async fn test_retry_budget() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        let netease = Netease::new(Arc::new(Semaphore::new(8))).with_base_url(&server.uri());
+        let budget = crate::RetryBudget::new(5, 3);
+        for _ in 0..3 {
+            let _ = crate::retry(
+                &budget,
+                netease.clone(),
+                |netease| async move {
+                    let data = WeapiEncoder::try_from_str("{}").unwrap();
+                    netease.exec::<Value>("/", data).await
+                },
+                |_| (),
+            )
+            .await;
+        }
+        assert_eq!(budget.remaining(), 0);
+        // 3 次首发 + 3 次重试
+        assert_eq!(server.received_requests().await.unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_similar() {
+        let body = json!({
+            "code": 200,
+            "songs": [
+                { "id": 1, "name": "a", "artists": [{ "name": "x" }, { "name": "y" }] },
+                { "id": 2, "name": "b", "ar": [{ "name": "z" }] },
+            ],
+        });
+        let (_server, netease) = mock(super::SIMILAR_URL, body).await;
+        let songs = netease
+            .similar(
+                "1",
+                |id| format!("pic/{id}"),
+                |id| format!("lrc/{id}"),
+                |id| format!("url/{id}"),
+            )
+            .await
+            .unwrap();
+        let songs = serde_json::to_value(songs).unwrap();
+        assert_eq!(songs[0]["artist"], "x/y");
+        assert_eq!(songs[0]["pic"], "pic/1");
+        assert_eq!(songs[1]["artist"], "z");
+    }
+
+    #[tokio::test]
+    async fn test_need_login() {
+        let (_server, netease) = mock(super::SONG_URL, json!({ "code": -462 })).await;
+        assert!(matches!(netease.url("1").await, Err(Error::NeedLogin)));
+
+        let body = json!({ "code": 200, "data": [{ "id": 1, "code": 200, "url": null }] });
+        let (_server, netease) = mock(super::SONG_URL, body).await;
+        assert!(matches!(netease.url("1").await, Err(Error::NeedLogin)));
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let check = |playable, reason: Option<&str>, br| crate::MetingCheck {
+            playable,
+            reason: reason.map(str::to_string),
+            br,
+        };
+        for (item, expected) in [
+            (
+                json!({ "code": 200, "url": "http://a", "br": 320000, "fee": 8 }),
+                check(true, None, Some(320000)),
+            ),
+            (
+                json!({ "code": 200, "url": "http://a", "br": 128000, "freeTrialInfo": { "start": 0, "end": 30 } }),
+                check(false, Some("trial"), None),
+            ),
+            (
+                json!({ "code": 200, "url": null, "br": 0, "fee": 1 }),
+                check(false, Some("vip"), None),
+            ),
+            (
+                json!({ "code": 404, "url": null, "br": 0, "fee": 0 }),
+                check(false, Some("unavailable"), None),
+            ),
+        ] {
+            assert_eq!(super::get_check(&item), expected);
+        }
+
+        let (_server, netease) = mock(super::SONG_URL, json!({ "code": -462 })).await;
+        assert_eq!(
+            netease.check("1").await.unwrap(),
+            check(false, Some("need_login"), None)
+        );
+    }
+
+    #[test]
+    fn test_playlist_req() {
+        assert_eq!(
+            super::Playlist::new("1").to_string(),
+            r#"{"id":"1","offset":"0","total":"True","limit":"9999","n":"9999"}"#
+        );
+    }
+
+    #[test]
+    fn test_search_req_keyword() {
+        let options = crate::MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        for (raw, expected) in [
+            ("%E5%91%A8%E6%9D%B0%E4%BC%A6", "周杰伦"),
+            ("周杰伦", "周杰伦"),
+            (" AC/DC  back in black", "AC/DC back in black"),
+        ] {
+            let keyword = crate::normalize_keyword(raw);
+            let req = super::SearchReq::new(&keyword, options).to_string();
+            let req = serde_json::from_str::<Value>(&req).unwrap();
+            assert_eq!(req["s"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_cloudsearch_shape() {
+        let body =
+            serde_json::from_str::<Value>(include_str!("../fixtures/netease/cloudsearch_pc.json"))
+                .unwrap();
+        let (_server, netease) = mock(super::SEARCH_URL, body).await;
+        let options = crate::MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let songs = netease
+            .search(
+                "晴天",
+                options,
+                str::to_string,
+                str::to_string,
+                str::to_string,
+            )
+            .await
+            .unwrap();
+        let songs = serde_json::to_value(songs).unwrap();
+        // 权限在歌曲的 `privilege` 里，pic id 在 `al.pic_str`
+        assert_eq!(songs[0]["pic"], "109951169270389932");
+        assert_eq!(
+            (&songs[0]["fee"], &songs[0]["playable"]),
+            (&json!(1), &json!(false))
+        );
+        // 权限在平级的 `privileges` 里，pic id 只能从 `picUrl` 取
+        assert_eq!(songs[1]["url"], "29850531");
+        assert_eq!(songs[1]["pic"], "109951163076136658");
+        assert_eq!(
+            (&songs[1]["fee"], &songs[1]["playable"]),
+            (&json!(8), &json!(true))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_fee() {
+        let body = json!({
+            "code": 200,
+            "result": {
+                "songs": [
+                    { "id": 1, "name": "a", "ar": [], "fee": 1 },
+                    { "id": 2, "name": "b", "ar": [] },
+                ],
+            },
+        });
+        let (_server, netease) = mock(super::SEARCH_URL, body).await;
+        let options = crate::MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let songs = netease
+            .search("a", options, str::to_string, str::to_string, str::to_string)
+            .await
+            .unwrap();
+        assert_eq!(
+            songs.iter().map(|song| song.fee).collect::<Vec<_>>(),
+            [1, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_artists() {
+        let body = json!({
+            "code": 200,
+            "result": {
+                "artistCount": 2,
+                "artists": [
+                    { "id": 6452, "name": "周杰伦", "picUrl": "http://p1.music.126.net/a.jpg" },
+                    { "id": "7", "name": "b", "picUrl": null, "img1v1Url": "https://p2.music.126.net/b.jpg" },
+                    { "name": "no id" },
+                ],
+            },
+        });
+        let (_server, netease) = mock(super::SEARCH_URL, body).await;
+        let options = crate::MetingSearchOptions {
+            limit: 30,
+            page: 1,
+            r#type: 1,
+        };
+        let artists = netease.search_artists("周杰伦", options).await.unwrap();
+        assert_eq!(
+            artists,
+            [
+                crate::MetingArtist {
+                    id: "6452".to_string(),
+                    name: "周杰伦".to_string(),
+                    pic: "https://p1.music.126.net/a.jpg".to_string(),
+                },
+                crate::MetingArtist {
+                    id: "7".to_string(),
+                    name: "b".to_string(),
+                    pic: "https://p2.music.126.net/b.jpg".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_ordered_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let tasks = (0..64u64).map(|i| {
+            let (running, peak) = (running.clone(), peak.clone());
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                // 越早的任务越慢，确认结果仍然按顺序
+                tokio::time::sleep(Duration::from_millis(64 - i)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        });
+        let mut outputs = Vec::new();
+        super::spawn_ordered(tasks, 4, |i| outputs.push(i)).await;
+        assert_eq!(outputs, (0..64).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_ordered_cancel() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let finished = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..4).map(|_| {
+            let finished = finished.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                finished.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let ordered = super::spawn_ordered(tasks, 2, |_| ());
+        assert!(tokio::time::timeout(Duration::from_millis(20), ordered)
+            .await
+            .is_err());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(finished.load(Ordering::SeqCst), 0);
     }
 }
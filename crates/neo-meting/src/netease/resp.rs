@@ -0,0 +1,587 @@
+//! 上游响应的结构，只声明用到的字段，变化大的子字段仍然用 [`Value`]
+//!
+//! 列表里解析不了的条目直接跳过（上游偶尔混入缺字段的歌），不让整个响应失败
+
+use std::collections::HashMap;
+
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value;
+
+use super::{get_id, MIN_PIC_ID};
+use crate::{
+    MetingArtist, MetingComment, MetingContributor, MetingMv, MetingPlaylist, MetingRecommend,
+    MetingSong, MetingSuggest, MetingSuggestion, Then,
+};
+
+/// 数字或数字字符串的 id
+fn id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    get_id(&value).ok_or_else(|| de::Error::custom(format!("invalid id: {value}")))
+}
+
+/// 跳过解析不了的条目，`null` 视为空列表
+fn lenient<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Option::<Vec<Value>>::deserialize(deserializer)?
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|item| T::deserialize(item).ok())
+        .collect::<Vec<_>>()
+        .then(Ok)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Artist {
+    pub name: String,
+}
+
+impl Artist {
+    /// 多个艺术家以 `/` 分隔，歌曲和搜索建议共用
+    pub fn join(artists: &[Artist]) -> String {
+        artists
+            .iter()
+            .map(|artist| artist.name.as_str())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// 专辑封面的 pic id，大数字有时只在 `picStr` 里是准确的
+///
+/// `cloudsearch` 里是 `pic_str`，再没有时可以从 `picUrl` 的文件名里取
+#[derive(Debug, Default, Deserialize)]
+pub struct Album {
+    #[serde(default, rename = "picStr")]
+    pub pic_str: Option<String>,
+    #[serde(default, rename = "pic_str")]
+    pub cloudsearch_pic_str: Option<String>,
+    #[serde(default)]
+    pub pic: Option<Value>,
+    #[serde(default, rename = "picUrl")]
+    pub pic_url: Option<String>,
+}
+
+/// 播放权限，`cloudsearch` 里在歌曲的 `privilege`，`song/detail` 等接口是和 `songs` 平级的 `privileges`
+#[derive(Debug, Deserialize)]
+pub struct Privilege {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    #[serde(default)]
+    pub fee: Option<u8>,
+    /// 小于 0 为下架
+    #[serde(default)]
+    pub st: i64,
+    /// 当前身份能播放的最高码率，0 为不能播放
+    #[serde(default)]
+    pub pl: u64,
+}
+
+impl Privilege {
+    pub fn playable(&self) -> bool {
+        self.st >= 0 && self.pl > 0
+    }
+}
+
+/// 歌曲对象，`song/detail` 里艺术家是 `ar`、专辑是 `al`，`simiSong` 等旧接口是 `artists` / `album`
+#[derive(Debug, Deserialize)]
+pub struct Song {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    /// 部分用户上传的歌没有歌名，当作空字符串，不让整首歌被跳过
+    #[serde(default, deserialize_with = "name")]
+    pub name: String,
+    #[serde(default, alias = "artists", deserialize_with = "lenient")]
+    pub ar: Vec<Artist>,
+    #[serde(default, alias = "album", deserialize_with = "album")]
+    pub al: Album,
+    /// 0 免费，1 VIP，4 需购买专辑，8 低音质免费；没有时看 `privilege.fee`
+    #[serde(default)]
+    pub fee: Option<u8>,
+    #[serde(default, deserialize_with = "optional")]
+    pub privilege: Option<Privilege>,
+}
+
+/// 没有、`null` 或不是字符串时为空字符串
+fn name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    optional::<D, String>(deserializer).map(Option::unwrap_or_default)
+}
+
+/// 专辑字段格式不对时当作没有封面，不让整首歌被跳过
+fn album<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Album, D::Error> {
+    Value::deserialize(deserializer)?
+        .then(|value| Album::deserialize(value).unwrap_or_default())
+        .then(Ok)
+}
+
+/// 格式不对时当作没有
+fn optional<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Option::<Value>::deserialize(deserializer)?
+        .and_then(|value| T::deserialize(value).ok())
+        .then(Ok)
+}
+
+impl Song {
+    /// 多个艺术家以 `/` 分隔
+    pub fn artist(&self) -> String {
+        Artist::join(&self.ar)
+    }
+
+    /// 封面的 pic id，没有或短于 [`MIN_PIC_ID`] 时用歌曲 id，见 [`super::Netease`] 的 `pic`
+    pub fn pic_id(&self) -> u64 {
+        let al = &self.al;
+        let pic_str = al.pic_str.as_ref().or(al.cloudsearch_pic_str.as_ref());
+        pic_str
+            .and_then(|pic| pic.parse().ok())
+            .or_else(|| al.pic.as_ref().and_then(get_id))
+            .or_else(|| {
+                // `https://p1.music.126.net/<加密>/<pic id>.jpg`
+                let file = al.pic_url.as_deref()?.rsplit('/').next()?;
+                file.split(['.', '?']).next()?.parse().ok()
+            })
+            // 太短的会被 `pic` 当成歌曲 id
+            .filter(|&pic| pic >= MIN_PIC_ID)
+            .unwrap_or(self.id)
+    }
+
+    /// 按 id 把和 `songs` 平级的 `privileges` 合进每首歌，歌曲自己带的优先
+    pub fn with_privileges(songs: Vec<Song>, privileges: Vec<Privilege>) -> Vec<Song> {
+        let mut privileges = privileges
+            .into_iter()
+            .map(|privilege| (privilege.id, privilege))
+            .collect::<HashMap<_, _>>();
+        songs
+            .into_iter()
+            .map(|song| {
+                let privilege = privileges.remove(&song.id);
+                song.or_privilege(privilege)
+            })
+            .collect()
+    }
+
+    /// 歌曲对象里没有 `privilege` 时用接口另外返回的
+    pub fn or_privilege(mut self, privilege: Option<Privilege>) -> Self {
+        self.privilege = self.privilege.or(privilege);
+        self
+    }
+
+    pub fn into_meting(
+        self,
+        pic: impl Fn(&str) -> String,
+        lrc: impl Fn(&str) -> String,
+        url: impl Fn(&str) -> String,
+    ) -> MetingSong {
+        let (id, artist) = (self.id.to_string(), self.artist());
+        let pic_id = self.pic_id().to_string();
+        let privilege = self.privilege.as_ref();
+        MetingSong {
+            name: self.name,
+            artist,
+            url: url(&id),
+            pic: pic(&pic_id),
+            lrc: lrc(&id),
+            fee: self.fee.or_else(|| privilege?.fee).unwrap_or_default(),
+            playable: privilege.map(Privilege::playable),
+            max_br: privilege.map(|privilege| privilege.pl),
+            source: None,
+        }
+    }
+}
+
+/// `song/detail`、`simiSong`：`{ songs: [song] }`，`song/detail` 还有平级的 `privileges`
+#[derive(Debug, Deserialize)]
+pub struct SongsResp {
+    #[serde(deserialize_with = "lenient")]
+    pub songs: Vec<Song>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub privileges: Vec<Privilege>,
+}
+
+impl SongsResp {
+    /// 合好 `privileges` 的歌曲
+    pub fn into_songs(self) -> Vec<Song> {
+        Song::with_privileges(self.songs, self.privileges)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Creator {
+    #[serde(default)]
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrackId {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+}
+
+/// 歌单对象，用户歌单列表里没有 `trackIds` / `tracks`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistInfo {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub cover_img_url: Option<String>,
+    #[serde(default)]
+    pub creator: Option<Creator>,
+    #[serde(default)]
+    pub track_count: Option<u64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub track_ids: Vec<TrackId>,
+    /// 歌单接口顺带返回的前若干首详情，不一定覆盖全部 `trackIds`
+    #[serde(default, deserialize_with = "lenient")]
+    pub tracks: Vec<Song>,
+}
+
+impl From<PlaylistInfo> for MetingPlaylist {
+    fn from(info: PlaylistInfo) -> Self {
+        MetingPlaylist {
+            id: info.id.to_string(),
+            name: info.name,
+            cover: info.cover_img_url.unwrap_or_default(),
+            creator: info.creator.and_then(|c| c.nickname).unwrap_or_default(),
+            count: info.track_count.unwrap_or_default(),
+        }
+    }
+}
+
+/// `playlist/detail`，需要登录时只有 `code`
+#[derive(Debug, Deserialize)]
+pub struct PlaylistResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub playlist: Option<PlaylistInfo>,
+}
+
+/// `user/playlist`
+#[derive(Debug, Deserialize)]
+pub struct UserPlaylistResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub playlist: Vec<PlaylistInfo>,
+}
+
+/// 艺术家搜索（`type=100`）里的艺术家
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchArtist {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub pic_url: Option<String>,
+    /// 没有 `picUrl` 时的方形头像
+    #[serde(default, rename = "img1v1Url")]
+    pub img1v1_url: Option<String>,
+}
+
+impl From<SearchArtist> for MetingArtist {
+    fn from(artist: SearchArtist) -> Self {
+        MetingArtist {
+            id: artist.id.to_string(),
+            name: artist.name,
+            pic: artist
+                .pic_url
+                .or(artist.img1v1_url)
+                .unwrap_or_default()
+                .replace("http://", "https://"),
+        }
+    }
+}
+
+/// `song/lyric` 里的 `lyricUser`，没人上传过时没有
+#[derive(Debug, Deserialize)]
+pub struct LyricUser {
+    #[serde(deserialize_with = "id")]
+    pub userid: u64,
+    pub nickname: String,
+    #[serde(default)]
+    pub uptime: Option<u64>,
+}
+
+impl From<LyricUser> for MetingContributor {
+    fn from(user: LyricUser) -> Self {
+        MetingContributor {
+            id: user.userid.to_string(),
+            nickname: user.nickname,
+            uptime: user.uptime,
+        }
+    }
+}
+
+/// `search/suggest/web` 里的一项，歌曲的艺术家在 `artists`，专辑的在 `artist`
+#[derive(Debug, Deserialize)]
+pub struct SuggestItem {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    pub name: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub artists: Vec<Artist>,
+    #[serde(default, deserialize_with = "optional")]
+    pub artist: Option<Artist>,
+}
+
+impl From<SuggestItem> for MetingSuggestion {
+    fn from(item: SuggestItem) -> Self {
+        let artist = match item.artist {
+            Some(artist) => artist.name,
+            None => Artist::join(&item.artists),
+        };
+        MetingSuggestion {
+            id: item.id.to_string(),
+            name: item.name,
+            artist,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SuggestResult {
+    #[serde(default, deserialize_with = "lenient")]
+    pub songs: Vec<SuggestItem>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub artists: Vec<SuggestItem>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub albums: Vec<SuggestItem>,
+}
+
+impl From<SuggestResult> for MetingSuggest {
+    fn from(result: SuggestResult) -> Self {
+        let convert = |items: Vec<SuggestItem>| items.into_iter().map(Into::into).collect();
+        MetingSuggest {
+            songs: convert(result.songs),
+            artists: convert(result.artists),
+            albums: convert(result.albums),
+        }
+    }
+}
+
+/// `search/suggest/web`，没有建议时 `result` 为空对象
+#[derive(Debug, Deserialize)]
+pub struct SuggestResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "optional")]
+    pub result: Option<SuggestResult>,
+}
+
+/// `artist/mvs` 里的一项
+#[derive(Debug, Deserialize)]
+pub struct Mv {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    pub name: String,
+    /// 16:9 的封面，老 MV 没有时用 `imgurl`
+    #[serde(default)]
+    pub imgurl16v9: Option<String>,
+    #[serde(default)]
+    pub imgurl: Option<String>,
+    #[serde(default)]
+    pub duration: u64,
+}
+
+impl From<Mv> for MetingMv {
+    fn from(mv: Mv) -> Self {
+        MetingMv {
+            id: mv.id.to_string(),
+            name: mv.name,
+            cover: mv
+                .imgurl16v9
+                .filter(|cover| !cover.is_empty())
+                .or(mv.imgurl)
+                .unwrap_or_default(),
+            duration: mv.duration,
+        }
+    }
+}
+
+/// `artist/mvs`
+#[derive(Debug, Deserialize)]
+pub struct ArtistMvsResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub mvs: Vec<Mv>,
+}
+
+/// `personalized/playlist` 里的一项
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Personalized {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub pic_url: Option<String>,
+    #[serde(default)]
+    pub play_count: u64,
+}
+
+impl From<Personalized> for MetingRecommend {
+    fn from(playlist: Personalized) -> Self {
+        MetingRecommend {
+            id: playlist.id.to_string(),
+            name: playlist.name,
+            cover: playlist.pic_url.unwrap_or_default(),
+            play_count: playlist.play_count,
+        }
+    }
+}
+
+/// `personalized/playlist`，未登录时也有
+#[derive(Debug, Deserialize)]
+pub struct PersonalizedResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub result: Vec<Personalized>,
+}
+
+/// `toplist`，榜单对象和歌单一样，没有 `creator`
+#[derive(Debug, Deserialize)]
+pub struct ToplistResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub list: Vec<PlaylistInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResult {
+    /// 没有结果时上游不返回 `songs`
+    #[serde(default, deserialize_with = "lenient")]
+    pub songs: Vec<Song>,
+    /// 只有艺术家搜索有
+    #[serde(default, deserialize_with = "lenient")]
+    pub artists: Vec<SearchArtist>,
+    /// 歌曲里没有 `privilege` 时，按 id 对应的播放权限
+    #[serde(default, deserialize_with = "lenient")]
+    pub privileges: Vec<Privilege>,
+}
+
+/// `cloudsearch/pc`
+#[derive(Debug, Deserialize)]
+pub struct SearchResp {
+    #[serde(default)]
+    pub result: Option<SearchResult>,
+}
+
+/// `v1/album/{id}`，曲目里的 `privilege` 等字段不全
+#[derive(Debug, Deserialize)]
+pub struct AlbumResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    /// 只用来判断专辑是否存在
+    #[serde(default)]
+    pub album: Option<Value>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub songs: Vec<Song>,
+}
+
+/// `discovery/new/songs`
+#[derive(Debug, Deserialize)]
+pub struct NewSongsResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub data: Vec<Song>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DailyData {
+    #[serde(rename = "dailySongs", deserialize_with = "lenient")]
+    pub daily_songs: Vec<Song>,
+}
+
+/// `discovery/recommend/songs`
+#[derive(Debug, Deserialize)]
+pub struct DailyResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub data: Option<DailyData>,
+}
+
+/// `point/dailyTask`，成功时 `point` 为这次拿到的积分
+#[derive(Debug, Deserialize)]
+pub struct SigninResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub point: Option<u64>,
+    #[serde(default)]
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Account {
+    #[serde(deserialize_with = "id")]
+    pub id: u64,
+}
+
+/// `w/nuser/account/get`，没登录时 `account` 为 `null`
+#[derive(Debug, Deserialize)]
+pub struct AccountResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub account: Option<Account>,
+}
+
+/// `song/like/get`
+#[derive(Debug, Deserialize)]
+pub struct LikedResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub ids: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentUser {
+    #[serde(default)]
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    #[serde(default)]
+    pub user: Option<CommentUser>,
+    pub content: String,
+    #[serde(default)]
+    pub liked_count: u64,
+}
+
+impl From<Comment> for MetingComment {
+    fn from(comment: Comment) -> Self {
+        MetingComment {
+            user: comment.user.and_then(|u| u.nickname).unwrap_or_default(),
+            content: comment.content,
+            likes: comment.liked_count,
+        }
+    }
+}
+
+/// `resource/comments/R_SO_4_{id}`，`hotComments` 只在第一页有
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentsResp {
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub hot_comments: Vec<Comment>,
+}
@@ -0,0 +1,508 @@
+//! 歌曲类 json 响应的渲染，`?pretty=1` 时所有 json 响应缩进输出
+
+use std::collections::{BTreeMap, HashSet};
+
+use futures_util::stream;
+use neo_meting::MetingSong;
+use salvo::{
+    handler,
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        StatusError,
+    },
+    writing::Scribe,
+    Depot, FlowCtrl, Request, Response,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::args::args;
+
+tokio::task_local! {
+    /// 为 `true` 时 [`Json`] 缩进输出，由 `?pretty=` 设置
+    static PRETTY: bool;
+}
+
+/// 不在 [`PRETTY`] 作用域内时紧凑输出
+fn pretty() -> bool {
+    PRETTY.try_with(|pretty| *pretty).unwrap_or(false)
+}
+
+/// `?pretty=1|0`，没有时用 `--pretty`；为 `true` 时后续 handler 都跑在 [`PRETTY`] 作用域内
+#[handler]
+pub async fn pretty_scope(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let pretty = match req.query::<&str>("pretty") {
+        Some("1" | "true") => true,
+        Some("0" | "false") => false,
+        _ => args().pretty,
+    };
+    if pretty {
+        PRETTY.scope(true, ctrl.call_next(req, depot, res)).await;
+    }
+}
+
+/// 同 `salvo::writing::Json`，[`PRETTY`] 作用域内用 `to_vec_pretty`
+pub struct Json<T>(pub T);
+
+impl<T: Serialize + Send> Scribe for Json<T> {
+    fn render(self, res: &mut Response) {
+        if !pretty() {
+            return salvo::writing::Json(self.0).render(res);
+        }
+        match serde_json::to_vec_pretty(&self.0) {
+            Ok(bytes) => {
+                res.headers_mut()
+                    .entry(CONTENT_TYPE)
+                    .or_insert(HeaderValue::from_static("application/json; charset=utf-8"));
+                res.write_body(bytes).ok();
+            }
+            Err(e) => {
+                warn!("render json: {e:?}");
+                res.render(StatusError::internal_server_error());
+            }
+        }
+    }
+}
+
+/// `?fields=name,artist,url`，不认识的字段名直接忽略
+fn fields(req: &Request) -> Option<HashSet<String>> {
+    req.query::<&str>("fields").map(|fields| {
+        fields
+            .split(',')
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect()
+    })
+}
+
+/// `?pic_sizes=300,640`，不是正整数的忽略
+fn pic_sizes(req: &Request) -> Option<Vec<u32>> {
+    req.query::<&str>("pic_sizes").map(|sizes| {
+        sizes
+            .split(',')
+            .filter_map(|size| size.trim().parse().ok())
+            .filter(|size| *size != 0)
+            .collect()
+    })
+}
+
+/// 歌曲对象的字段名风格，`?compat=` 优先，其次是 `--compat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compat {
+    /// 原样输出，和原版 Meting 一致
+    Meting,
+    /// 老版 APlayer 的 `title` / `author`
+    Aplayer,
+}
+
+impl Compat {
+    /// `(原字段名, 输出的字段名)`
+    fn renames(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Compat::Meting => &[],
+            Compat::Aplayer => &[("name", "title"), ("artist", "author")],
+        }
+    }
+}
+
+/// 不认识的值当作没给
+fn compat(req: &Request) -> Compat {
+    req.query::<&str>("compat")
+        .and_then(|compat| clap::ValueEnum::from_str(compat, true).ok())
+        .unwrap_or(args().compat)
+}
+
+/// 按 `renames` 改歌曲对象的字段名，数组逐个处理
+pub fn rename(value: Value, renames: &[(&str, &str)]) -> Value {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| rename(item, renames))
+            .collect::<Vec<_>>()
+            .into(),
+        Value::Object(map) => map
+            .into_iter()
+            .map(
+                |(key, value)| match renames.iter().find(|(from, _)| *from == key) {
+                    Some((_, to)) => (to.to_string(), value),
+                    None => (key, value),
+                },
+            )
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        other => other,
+    }
+}
+
+/// 给歌曲对象加上 `pic_sizes: { "<size>": "<pic>?size=<size>" }`，数组逐个处理
+///
+/// 只是在 `pic` 的链接上加参数，真正的缩放由 pic 接口转给上游
+pub fn add_pic_sizes(value: Value, sizes: &[u32]) -> Value {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| add_pic_sizes(item, sizes))
+            .collect::<Vec<_>>()
+            .into(),
+        Value::Object(mut map) => {
+            if let Some(pic) = map.get("pic").and_then(Value::as_str) {
+                let pic_sizes = sizes
+                    .iter()
+                    .map(|size| (size.to_string(), format!("{pic}?size={size}")))
+                    .collect::<BTreeMap<_, _>>();
+                map.insert("pic_sizes".to_string(), serde_json::json!(pic_sizes));
+            }
+            map.into()
+        }
+        other => other,
+    }
+}
+
+/// `?fields=`、`?pic_sizes=` 和 [`Compat`] 对歌曲对象的改写，都没给时原样输出
+struct Shape {
+    fields: Option<HashSet<String>>,
+    pic_sizes: Option<Vec<u32>>,
+    compat: Compat,
+}
+
+impl Shape {
+    fn new(req: &Request) -> Option<Self> {
+        match (fields(req), pic_sizes(req), compat(req)) {
+            (None, None, Compat::Meting) => None,
+            (fields, pic_sizes, compat) => Some(Self {
+                fields,
+                pic_sizes,
+                compat,
+            }),
+        }
+    }
+
+    /// 先加 `pic_sizes`，这样 `?fields=` 也能选到它；`?fields=` 用的是原字段名，最后再改名
+    fn apply(&self, value: Value) -> Value {
+        let value = match &self.pic_sizes {
+            Some(sizes) => add_pic_sizes(value, sizes),
+            None => value,
+        };
+        let value = match &self.fields {
+            Some(fields) => project(value, fields),
+            None => value,
+        };
+        match self.compat {
+            Compat::Meting => value,
+            compat => rename(value, compat.renames()),
+        }
+    }
+}
+
+/// 只保留歌曲对象中 `fields` 里的字段，数组逐个处理
+pub fn project(value: Value, fields: &HashSet<String>) -> Value {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| project(item, fields))
+            .collect::<Vec<_>>()
+            .into(),
+        Value::Object(map) => map
+            .into_iter()
+            .filter(|(key, _)| fields.contains(key))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        other => other,
+    }
+}
+
+/// 渲染 `MetingSong` / `Vec<MetingSong>`
+pub fn render_songs(req: &Request, res: &mut Response, songs: impl Serialize + Send) {
+    let Some(shape) = Shape::new(req) else {
+        res.render(Json(songs));
+        return;
+    };
+    match serde_json::to_value(songs) {
+        Ok(value) => res.render(Json(shape.apply(value))),
+        Err(e) => {
+            warn!("render songs: {e:?}");
+            res.render(StatusError::internal_server_error());
+        }
+    }
+}
+
+/// 流式响应里每一帧包含的歌曲数
+const STREAM_CHUNK: usize = 64;
+
+/// 按帧写出 `[song, ...]`，不再额外拼一整块 json 文本
+///
+/// 只是分块序列化：`songs` 已经全部在内存里，峰值内存仍由整个歌单决定。
+/// `?pretty=1` 时不分块，整个数组缩进输出
+pub fn stream_songs(req: &Request, res: &mut Response, songs: Vec<MetingSong>) {
+    if pretty() {
+        return render_songs(req, res, songs);
+    }
+    let shape = Shape::new(req);
+    let mut songs = songs.into_iter().peekable();
+    let (mut first, mut done) = (true, false);
+    let frames = std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut frame = if first { b"[".to_vec() } else { Vec::new() };
+        for song in songs.by_ref().take(STREAM_CHUNK) {
+            if !first {
+                frame.push(b',');
+            }
+            first = false;
+            let written = match &shape {
+                Some(shape) => serde_json::to_value(song)
+                    .and_then(|song| serde_json::to_writer(&mut frame, &shape.apply(song))),
+                None => serde_json::to_writer(&mut frame, &song),
+            };
+            if let Err(e) = written {
+                done = true;
+                return Some(Err(e));
+            }
+        }
+        if songs.peek().is_none() {
+            frame.push(b']');
+            done = true;
+        }
+        Some(Ok(frame))
+    });
+    res.add_header(CONTENT_TYPE, "application/json; charset=utf-8", true)
+        .ok();
+    res.stream(stream::iter(frames));
+}
+
+/// 按 `?fields=` / `?pic_sizes=` 把单首歌序列化成 json 文本，给 WebSocket 之类逐条发送的场景用
+pub fn song_writer(req: &Request) -> impl Fn(&MetingSong) -> serde_json::Result<String> + Send {
+    let shape = Shape::new(req);
+    move |song| match &shape {
+        Some(shape) => serde_json::to_value(song).map(|song| shape.apply(song).to_string()),
+        None => serde_json::to_string(song),
+    }
+}
+
+/// 渲染 `{ ..., <songs_key>: [MetingSong] }` 这类带额外信息的结果
+pub fn render_nested_songs(
+    req: &Request,
+    res: &mut Response,
+    body: impl Serialize + Send,
+    songs_key: &str,
+) {
+    let Some(shape) = Shape::new(req) else {
+        res.render(Json(body));
+        return;
+    };
+    match serde_json::to_value(body) {
+        Ok(mut value) => {
+            if let Some(songs) = value.get_mut(songs_key) {
+                *songs = shape.apply(songs.take());
+            }
+            res.render(Json(value))
+        }
+        Err(e) => {
+            warn!("render songs: {e:?}");
+            res.render(StatusError::internal_server_error());
+        }
+    }
+}
+
+/// 渲染 `{ <key>: MetingSong | null }`
+pub fn render_keyed_songs(req: &Request, res: &mut Response, songs: impl Serialize + Send) {
+    let Some(shape) = Shape::new(req) else {
+        res.render(Json(songs));
+        return;
+    };
+    match serde_json::to_value(songs) {
+        Ok(mut value) => {
+            if let Value::Object(songs) = &mut value {
+                songs
+                    .values_mut()
+                    .filter(|song| !song.is_null())
+                    .for_each(|song| *song = shape.apply(song.take()));
+            }
+            res.render(Json(value))
+        }
+        Err(e) => {
+            warn!("render songs: {e:?}");
+            res.render(StatusError::internal_server_error());
+        }
+    }
+}
+
+/// 渲染 `{ ..., <songs_key>: { <provider>: [MetingSong] } }` 这类按 provider 分组的结果
+pub fn render_grouped_songs(
+    req: &Request,
+    res: &mut Response,
+    body: impl Serialize + Send,
+    songs_key: &str,
+) {
+    let Some(shape) = Shape::new(req) else {
+        res.render(Json(body));
+        return;
+    };
+    match serde_json::to_value(body) {
+        Ok(mut value) => {
+            if let Some(Value::Object(groups)) = value.get_mut(songs_key) {
+                groups
+                    .values_mut()
+                    .for_each(|songs| *songs = shape.apply(songs.take()));
+            }
+            res.render(Json(value))
+        }
+        Err(e) => {
+            warn!("render songs: {e:?}");
+            res.render(StatusError::internal_server_error());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use neo_meting::MetingSong;
+    use salvo::{
+        handler,
+        test::{ResponseExt, TestClient},
+        Request, Response, Router, Service,
+    };
+    use serde_json::{json, Value};
+
+    use super::{add_pic_sizes, pretty_scope, project, render_songs, stream_songs, STREAM_CHUNK};
+
+    #[test]
+    fn test_project() {
+        let fields = ["name", "url", "unknown"]
+            .map(String::from)
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let songs = json!([
+            { "name": "a", "artist": "x", "url": "u", "pic": "p", "lrc": "l" },
+            { "name": "b", "artist": "y", "url": "v", "pic": "q", "lrc": "m" },
+        ]);
+        assert_eq!(
+            project(songs, &fields),
+            json!([{ "name": "a", "url": "u" }, { "name": "b", "url": "v" }])
+        );
+    }
+
+    #[test]
+    fn test_add_pic_sizes() {
+        let songs = json!([{ "name": "a", "pic": "http://h/netease/pic/1" }, { "name": "b" }]);
+        assert_eq!(
+            add_pic_sizes(songs, &[300, 640]),
+            json!([
+                {
+                    "name": "a",
+                    "pic": "http://h/netease/pic/1",
+                    "pic_sizes": {
+                        "300": "http://h/netease/pic/1?size=300",
+                        "640": "http://h/netease/pic/1?size=640",
+                    },
+                },
+                { "name": "b" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compat() {
+        #[handler]
+        async fn songs(req: &mut Request, res: &mut Response) {
+            let song = serde_json::from_value::<MetingSong>(json!({
+                "name": "a", "artist": "x", "url": "u", "pic": "p", "lrc": "l"
+            }))
+            .unwrap();
+            render_songs(req, res, [song])
+        }
+        let service = Service::new(Router::new().get(songs));
+        for (query, expected) in [
+            (
+                "compat=aplayer&fields=name,artist",
+                json!([{ "title": "a", "author": "x" }]),
+            ),
+            ("compat=unknown&fields=name", json!([{ "name": "a" }])),
+        ] {
+            let body = TestClient::get(format!("http://127.0.0.1:5811/?{query}"))
+                .send(&service)
+                .await
+                .take_json::<Value>()
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{query}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pretty() {
+        #[handler]
+        async fn songs(req: &mut Request, res: &mut Response) {
+            let song = serde_json::from_value::<MetingSong>(json!({
+                "name": "a", "artist": "x", "url": "u", "pic": "p", "lrc": "l"
+            }))
+            .unwrap();
+            match req.query::<&str>("stream") {
+                Some(_) => stream_songs(req, res, vec![song]),
+                None => render_songs(req, res, [song]),
+            }
+        }
+        let service = Service::new(Router::new().hoop(pretty_scope).get(songs));
+        for (query, pretty) in [
+            ("", false),
+            ("pretty=0", false),
+            ("pretty=1", true),
+            ("pretty=1&stream=1", true),
+            ("pretty=true&compat=aplayer", true),
+        ] {
+            let mut res = TestClient::get(format!("http://127.0.0.1:5811/?{query}"))
+                .send(&service)
+                .await;
+            assert_eq!(
+                res.content_type().unwrap().essence_str(),
+                "application/json",
+                "{query}"
+            );
+            let body = res.take_string().await.unwrap();
+            assert_eq!(body.starts_with("[\n  {\n    \""), pretty, "{query}");
+            let song = &serde_json::from_str::<Value>(&body).unwrap()[0];
+            assert_eq!(song.get("title").is_some(), query.contains("aplayer"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_songs() {
+        #[handler]
+        async fn playlist(req: &mut Request, res: &mut Response) {
+            let count = req.query::<usize>("count").unwrap();
+            let songs = (0..count)
+                .map(|id| {
+                    serde_json::from_value::<MetingSong>(json!({
+                        "name": id.to_string(), "artist": "x", "url": "u", "pic": "p", "lrc": "l"
+                    }))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            stream_songs(req, res, songs)
+        }
+        let service = Service::new(Router::new().get(playlist));
+        for count in [0, 1, STREAM_CHUNK, STREAM_CHUNK * 2 + 1] {
+            let body = TestClient::get("http://127.0.0.1:5811/")
+                .query("count", count)
+                .query("fields", "name")
+                .send(&service)
+                .await
+                .take_json::<Value>()
+                .await
+                .unwrap();
+            let expected = (0..count)
+                .map(|id| json!({ "name": id.to_string() }))
+                .collect::<Vec<_>>();
+            assert_eq!(body, Value::from(expected));
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! `--selftest`：不启动服务，用每个 provider 自带的公开曲目把主要接口各请求一次，
+//! 一条命令验证加密、网络和解析
+
+use std::{sync::Arc, time::Instant};
+
+use neo_meting::Capability;
+
+use crate::Provider;
+
+/// 自检的方法，provider 不支持的跳过
+pub const METHODS: [Capability; 4] = [
+    Capability::Song,
+    Capability::Pic,
+    Capability::Lrc,
+    Capability::Search,
+];
+
+/// 一个 provider 的一个方法的结果
+#[derive(Debug)]
+pub struct Check {
+    pub provider: &'static str,
+    pub method: Capability,
+    /// 成功时为耗时（毫秒），失败时为错误
+    pub result: Result<u128, String>,
+}
+
+/// 依次请求，不并发，免得某个 provider 的并发额度影响结果；没有样本的 provider 不出现
+pub async fn run(providers: &[Arc<dyn Provider>]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for provider in providers {
+        let Some((id, keyword)) = provider.selftest_sample() else {
+            continue;
+        };
+        for method in METHODS {
+            if !provider.capabilities().contains(&method) {
+                continue;
+            }
+            let start = Instant::now();
+            let result = provider
+                .probe(method, id, keyword)
+                .await
+                .map(|_| start.elapsed().as_millis())
+                .map_err(|e| format!("{e:?}"));
+            checks.push(Check {
+                provider: provider.name(),
+                method,
+                result,
+            });
+        }
+    }
+    checks
+}
+
+/// 每项一行打印到 stdout，全部通过时返回 `true`
+pub fn report(checks: &[Check]) -> bool {
+    for check in checks {
+        let (provider, method) = (check.provider, check.method.as_str());
+        match &check.result {
+            Ok(ms) => println!("ok   {provider} {method} ({ms}ms)"),
+            Err(e) => println!("FAIL {provider} {method}: {e}"),
+        }
+    }
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+    println!("{} passed, {failed} failed", checks.len() - failed);
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use neo_meting::{Capability, Error, MetingApi, MetingSearchOptions, MetingSong};
+    use serde_json::json;
+
+    use super::{report, run};
+    use crate::Provider;
+
+    /// 样本歌曲的歌词拿不到
+    #[derive(Clone)]
+    struct Fake;
+    impl MetingApi for Fake {
+        fn name() -> &'static str {
+            "selftest-fake"
+        }
+        fn capabilities() -> &'static [Capability] {
+            &[Capability::Pic, Capability::Lrc, Capability::Search]
+        }
+        fn selftest_sample() -> Option<(&'static str, &'static str)> {
+            Some(("1", "a"))
+        }
+        async fn pic(&self, id: &str) -> Result<String, Error> {
+            Ok(format!("https://example.com/{id}.jpg"))
+        }
+        async fn lrc(&self, _id: &str) -> Result<String, Error> {
+            Err(Error::None)
+        }
+        async fn search(
+            &self,
+            _keyword: &str,
+            _option: MetingSearchOptions,
+            _pic: impl Fn(&str) -> String + Send,
+            _lrc: impl Fn(&str) -> String + Send,
+            url: impl Fn(&str) -> String + Send,
+        ) -> Result<Vec<MetingSong>, Error> {
+            serde_json::from_value(json!([{
+                "name": "a", "artist": "b", "url": url("1"), "pic": "", "lrc": ""
+            }]))
+            .map_err(|e| Error::Remote(e.to_string()))
+        }
+    }
+
+    /// 没有样本，整个跳过
+    #[derive(Clone)]
+    struct NoSample;
+    impl MetingApi for NoSample {
+        fn name() -> &'static str {
+            "selftest-none"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_selftest() {
+        let providers: Vec<Arc<dyn Provider>> = vec![Arc::new(Fake), Arc::new(NoSample)];
+        let checks = run(&providers).await;
+        let results = checks
+            .iter()
+            .map(|check| (check.provider, check.method, check.result.is_ok()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                ("selftest-fake", Capability::Pic, true),
+                ("selftest-fake", Capability::Lrc, false),
+                ("selftest-fake", Capability::Search, true),
+            ]
+        );
+        assert!(!report(&checks));
+        assert!(report(&checks[..1]));
+    }
+}
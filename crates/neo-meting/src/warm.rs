@@ -0,0 +1,470 @@
+//! 歌单预热：后台把整张歌单的 url / pic / lrc 解析进缓存，播放时直接命中；
+//! 只要封面时用 [`WarmPics`]，同步返回结果
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use neo_meting::{current_region, Capability, Error, REGION};
+use salvo::{
+    async_trait,
+    http::{StatusCode, StatusError},
+    Depot, FlowCtrl, Handler, Request, Response,
+};
+use tracing::{info, warn};
+
+use crate::{render::Json, Provider};
+
+/// 每个任务同时预热的曲目数，给正常请求留出 provider 的并发额度
+pub const TASKS: usize = 4;
+/// 最多保留的任务数，超出后丢掉最早的
+const MAX_JOBS: usize = 64;
+/// `/cache/warm/pics` 一次最多给的 id 数
+const MAX_PIC_IDS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Done,
+    /// 歌单本身没拿到，单首失败只计入 `failed`
+    Failed,
+}
+
+/// 一个预热任务的进度
+#[derive(Debug)]
+pub struct Job {
+    id: u64,
+    provider: &'static str,
+    playlist: String,
+    total: AtomicUsize,
+    done: AtomicUsize,
+    failed: AtomicUsize,
+    /// 结束后为 `Some`
+    result: Mutex<Option<Result<(), String>>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct JobStatus {
+    pub job: String,
+    pub provider: &'static str,
+    pub playlist: String,
+    pub state: JobState,
+    /// 歌单的曲目数，拿到歌单之前为 0
+    pub total: usize,
+    /// 处理完的曲目数，包括失败的
+    pub done: usize,
+    /// url / pic / lrc 任一个没解析成功的曲目数
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+impl Job {
+    /// 拿到歌单后记下曲目数
+    pub fn start(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn finish_one(&self, ok: bool) {
+        if !ok {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn end(&self, result: Result<(), Error>) {
+        let result = result.map_err(|e| format!("{e:?}"));
+        match &result {
+            Ok(()) => info!(
+                "cache warm {} {}/{}: {} songs, {} failed",
+                self.id,
+                self.provider,
+                self.playlist,
+                self.total.load(Ordering::Relaxed),
+                self.failed.load(Ordering::Relaxed),
+            ),
+            Err(e) => warn!(
+                "cache warm {} {}/{}: {e}",
+                self.id, self.provider, self.playlist
+            ),
+        }
+        *self.result.lock().unwrap() = Some(result);
+    }
+
+    fn status(&self) -> JobStatus {
+        let (state, error) = match self.result.lock().unwrap().clone() {
+            None => (JobState::Running, None),
+            Some(Ok(())) => (JobState::Done, None),
+            Some(Err(e)) => (JobState::Failed, Some(e)),
+        };
+        JobStatus {
+            job: self.id.to_string(),
+            provider: self.provider,
+            playlist: self.playlist.clone(),
+            state,
+            total: self.total.load(Ordering::Relaxed),
+            done: self.done.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    next: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Arc<Job>>>,
+}
+
+impl Jobs {
+    fn create(&self, provider: &'static str, playlist: String) -> Arc<Job> {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = Arc::new(Job {
+            id,
+            provider,
+            playlist,
+            total: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            result: Mutex::new(None),
+        });
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(id, job.clone());
+        while jobs.len() > MAX_JOBS {
+            jobs.pop_first();
+        }
+        job
+    }
+
+    fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WarmReq {
+    provider: String,
+    id: String,
+}
+
+/// `POST /cache/warm`，body 为 `{ "provider": "netease", "id": "<歌单 id>" }`
+///
+/// 立即返回 202 和任务状态，之后用 [`WarmStatus`] 查询进度
+pub struct Warm {
+    providers: Vec<Arc<dyn Provider>>,
+    jobs: Arc<Jobs>,
+}
+
+/// `GET /cache/warm/{job}`
+pub struct WarmStatus(Arc<Jobs>);
+
+#[derive(Debug, serde::Deserialize)]
+struct WarmPicsReq {
+    provider: String,
+    playlist: Option<String>,
+    #[serde(default)]
+    ids: Vec<String>,
+}
+
+/// `POST /cache/warm/pics`，body 为 `{ "provider", "playlist" }` 或 `{ "provider", "ids": [...] }`
+///
+/// 只解析封面，等全部完成后返回 `{ "<歌曲 id>": "<封面地址>" | null }`
+pub struct WarmPics {
+    providers: Vec<Arc<dyn Provider>>,
+    tasks: usize,
+}
+
+impl WarmPics {
+    pub fn new(providers: Vec<Arc<dyn Provider>>, tasks: usize) -> Self {
+        Self { providers, tasks }
+    }
+}
+
+impl Warm {
+    pub fn new(providers: Vec<Arc<dyn Provider>>) -> Self {
+        Self {
+            providers,
+            jobs: Arc::default(),
+        }
+    }
+
+    /// 共用同一份任务列表的查询接口
+    pub fn status(&self) -> WarmStatus {
+        WarmStatus(self.jobs.clone())
+    }
+}
+
+#[async_trait]
+impl Handler for Warm {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let Ok(body) = req.parse_json::<WarmReq>().await else {
+            res.render(StatusError::bad_request().brief(r#"expected { "provider", "id" }"#));
+            return;
+        };
+        let Some(provider) = self
+            .providers
+            .iter()
+            .find(|provider| provider.name() == body.provider)
+            .cloned()
+        else {
+            res.render(StatusError::not_found().brief("unknown provider"));
+            return;
+        };
+        if !provider.capabilities().contains(&Capability::Playlist) {
+            let brief = format!("{} does not support playlist", provider.name());
+            res.render(StatusError::not_found().brief(brief));
+            return;
+        }
+        let job = self.jobs.create(provider.name(), body.id);
+        res.status_code(StatusCode::ACCEPTED);
+        res.render(Json(job.status()));
+        // 播放地址的缓存按地区分开，后台任务里要重新进入
+        let region = current_region();
+        tokio::spawn(async move {
+            let warm = provider.warm_playlist(&job.playlist, &job);
+            let result = match region {
+                Some(region) => REGION.scope(region, warm).await,
+                None => warm.await,
+            };
+            job.end(result);
+        });
+    }
+}
+
+#[async_trait]
+impl Handler for WarmPics {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let body = match req.parse_json::<WarmPicsReq>().await {
+            Ok(body) if body.playlist.is_some() == body.ids.is_empty() => body,
+            _ => {
+                let brief = r#"expected { "provider", "playlist" } or { "provider", "ids" }"#;
+                res.render(StatusError::bad_request().brief(brief));
+                return;
+            }
+        };
+        if body.ids.len() > MAX_PIC_IDS {
+            let brief = format!("expected at most {MAX_PIC_IDS} ids");
+            res.render(StatusError::bad_request().brief(brief));
+            return;
+        }
+        let Some(provider) = self
+            .providers
+            .iter()
+            .find(|provider| provider.name() == body.provider)
+        else {
+            res.render(StatusError::not_found().brief("unknown provider"));
+            return;
+        };
+        let needed = match body.playlist {
+            Some(_) => [Capability::Pic, Capability::Playlist].as_slice(),
+            None => [Capability::Pic].as_slice(),
+        };
+        if let Some(method) = needed
+            .iter()
+            .find(|method| !provider.capabilities().contains(method))
+        {
+            let brief = format!("{} does not support {}", provider.name(), method.as_str());
+            res.render(StatusError::not_found().brief(brief));
+            return;
+        }
+        match provider
+            .warm_pics(body.playlist.as_deref(), body.ids, self.tasks)
+            .await
+        {
+            Ok(pics) => res.render(Json(pics)),
+            Err(e) => res.render(crate::prosess_meting_error(file!(), line!(), e)),
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for WarmStatus {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        match req.param::<u64>("job").and_then(|id| self.0.get(id)) {
+            Some(job) => res.render(Json(job.status())),
+            None => res.render(StatusError::not_found().brief("unknown job")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use neo_meting::{cache::Cache, Capability, Error, MetingApi, MetingSong, RetryBudget};
+    use salvo::{
+        http::StatusCode,
+        test::{ResponseExt, TestClient},
+        Router, Service,
+    };
+    use serde_json::{json, Value};
+
+    use super::{Warm, WarmPics};
+    use crate::CACHE;
+
+    /// 歌单里两首歌，id 为 907 的 lrc 拿不到，909 没有封面
+    #[derive(Clone)]
+    struct Fake;
+    impl MetingApi for Fake {
+        fn name() -> &'static str {
+            "fake"
+        }
+        fn capabilities() -> &'static [Capability] {
+            &[Capability::Pic, Capability::Playlist]
+        }
+        async fn playlist(
+            &self,
+            _id: &str,
+            _retry: Arc<RetryBudget>,
+            pic: impl Fn(&str) -> String + Send + Sync,
+            lrc: impl Fn(&str) -> String + Send + Sync,
+            url: impl Fn(&str) -> String + Send + Sync,
+        ) -> Result<Vec<MetingSong>, Error> {
+            ["906", "907"]
+                .into_iter()
+                .map(|id| {
+                    serde_json::from_value(json!({
+                        "name": id, "artist": "x", "url": url(id), "pic": pic(id), "lrc": lrc(id)
+                    }))
+                    .map_err(|e| Error::Remote(e.to_string()))
+                })
+                .collect()
+        }
+        async fn url(&self, id: &str) -> Result<String, Error> {
+            Ok(format!("https://example.com/{id}.mp3"))
+        }
+        async fn pic(&self, id: &str) -> Result<String, Error> {
+            match id {
+                "909" => Err(Error::None),
+                _ => Ok(format!("https://example.com/{id}.jpg")),
+            }
+        }
+        async fn lrc(&self, id: &str) -> Result<String, Error> {
+            match id {
+                "907" => Err(Error::None),
+                _ => Ok("[00:00.00]a".to_string()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warm_playlist() {
+        let warm = Warm::new(vec![Arc::new(Fake)]);
+        let status = warm.status();
+        let service = Service::new(
+            Router::with_path("cache/warm")
+                .post(warm)
+                .push(Router::with_path("{job}").get(status)),
+        );
+        let mut res = TestClient::post("http://127.0.0.1/cache/warm")
+            .json(&json!({ "provider": "fake", "id": "1" }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::ACCEPTED));
+        let job = res.take_json::<Value>().await.unwrap()["job"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let status = loop {
+            let status = TestClient::get(format!("http://127.0.0.1/cache/warm/{job}"))
+                .send(&service)
+                .await
+                .take_json::<Value>()
+                .await
+                .unwrap();
+            if status["state"] != "running" {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status["state"], "done");
+        assert_eq!(
+            (status["total"].clone(), status["failed"].clone()),
+            (json!(2), json!(1))
+        );
+        assert_eq!(
+            CACHE.get(&Cache::key("fake", "pic", "907")).as_deref(),
+            Some("https://example.com/907.jpg")
+        );
+
+        let res = TestClient::post("http://127.0.0.1/cache/warm")
+            .json(&json!({ "provider": "other", "id": "1" }))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        let res = TestClient::get("http://127.0.0.1/cache/warm/999")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn test_warm_pics() {
+        let service = Service::new(
+            Router::with_path("cache/warm/pics").post(WarmPics::new(vec![Arc::new(Fake)], 2)),
+        );
+        for (body, expected) in [
+            (
+                json!({ "provider": "fake", "ids": ["908", "909"] }),
+                json!({ "908": "https://example.com/908.jpg", "909": null }),
+            ),
+            (
+                json!({ "provider": "fake", "playlist": "1" }),
+                json!({ "906": "https://example.com/906.jpg", "907": "https://example.com/907.jpg" }),
+            ),
+        ] {
+            let pics = TestClient::post("http://127.0.0.1/cache/warm/pics")
+                .json(&body)
+                .send(&service)
+                .await
+                .take_json::<Value>()
+                .await
+                .unwrap();
+            assert_eq!(pics, expected);
+        }
+        assert_eq!(
+            CACHE.get(&Cache::key("fake", "pic", "908")).as_deref(),
+            Some("https://example.com/908.jpg")
+        );
+
+        for (body, status) in [
+            (json!({ "provider": "fake" }), StatusCode::BAD_REQUEST),
+            (
+                json!({ "provider": "fake", "playlist": "1", "ids": ["908"] }),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                json!({ "provider": "other", "ids": ["908"] }),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let res = TestClient::post("http://127.0.0.1/cache/warm/pics")
+                .json(&body)
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code, Some(status));
+        }
+    }
+}